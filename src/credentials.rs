@@ -0,0 +1,213 @@
+//! Registry credentials from docker-client configuration.
+//!
+//! This module decodes the `auths` stored in `~/.docker/config.json`,
+//! and queries the configured credential helpers (`credHelpers` and
+//! `credsStore`) when present.
+
+use crate::errors::Result;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::{env, path, process};
+
+/// Get registry credentials from a JSON config reader.
+///
+/// This is a convenience decoder for docker-client credentials
+/// typically stored under `~/.docker/config.json`.
+///
+/// Credential helpers configured for the index take precedence over
+/// the stored `auths`.
+pub fn get_credentials<T: Read>(
+    reader: T,
+    index: &str,
+) -> Result<(Option<String>, Option<String>)> {
+    let config: DockerConfig = serde_json::from_reader(reader)?;
+    let real_index = match index {
+        // docker.io has some special casing in config.json
+        "docker.io" | "registry-1.docker.io" => "https://index.docker.io/v1/",
+        other => other,
+    };
+
+    let helper = config
+        .cred_helpers
+        .iter()
+        .find(|(k, _)| to_hostname(k) == to_hostname(real_index))
+        .map(|(_, v)| v)
+        .or(config.creds_store.as_ref());
+    if let Some(helper) = helper {
+        match run_credential_helper(helper, real_index) {
+            Ok(Some(up)) => {
+                trace!(
+                    "Found credentials for user={:?} on {} via helper {}",
+                    up.0,
+                    index,
+                    helper
+                );
+                return Ok(up);
+            }
+            Ok(None) => trace!("Credential helper {} has no auth for {}", helper, index),
+            Err(e) => warn!("Credential helper {} failed: {}", helper, e),
+        }
+    }
+
+    let auth = match config
+        .auths
+        .iter()
+        .find(|(k, _)| to_hostname(k) == to_hostname(real_index))
+    {
+        Some((_, x)) => base64::decode(x.auth.as_str())?,
+        None => bail!("no auth for index {}", real_index),
+    };
+    let s = String::from_utf8(auth)?;
+    let up = split_credentials(&s);
+    trace!("Found credentials for user={:?} on {}", up.0, index);
+    Ok(up)
+}
+
+/// Return the path of the docker-client configuration file.
+///
+/// This honors `$DOCKER_CONFIG`, defaulting to `~/.docker/config.json`.
+pub fn docker_config_path() -> Result<path::PathBuf> {
+    if let Some(dir) = env::var_os("DOCKER_CONFIG") {
+        return Ok(path::PathBuf::from(dir).join("config.json"));
+    }
+
+    match env::var_os("HOME") {
+        Some(home) => Ok(path::PathBuf::from(home)
+            .join(".docker")
+            .join("config.json")),
+        None => bail!("cannot locate docker config: neither $DOCKER_CONFIG nor $HOME is set"),
+    }
+}
+
+/// Split a `user:password` string into its optional parts.
+fn split_credentials(s: &str) -> (Option<String>, Option<String>) {
+    let creds: Vec<&str> = s.splitn(2, ':').collect();
+    match (creds.first(), creds.get(1)) {
+        (Some(&""), Some(p)) => (None, Some(p.to_string())),
+        (Some(u), Some(&"")) => (Some(u.to_string()), None),
+        (Some(u), Some(p)) => (Some(u.to_string()), Some(p.to_string())),
+        (_, _) => (None, None),
+    }
+}
+
+/// Reduce a registry URL or index name to its hostname, as docker does.
+fn to_hostname(index: &str) -> &str {
+    let stripped = index
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    stripped.split('/').next().unwrap_or(stripped)
+}
+
+/// Query the `docker-credential-<helper>` binary for the given server.
+///
+/// Returns `None` if the helper has no credentials for it.
+fn run_credential_helper(
+    helper: &str,
+    server_url: &str,
+) -> Result<Option<(Option<String>, Option<String>)>> {
+    let binary = format!("docker-credential-{}", helper);
+    trace!(
+        "Running credential helper '{} get' for {}",
+        binary,
+        server_url
+    );
+
+    let mut child = process::Command::new(&binary)
+        .arg("get")
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(server_url.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+
+    if !output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.contains("credentials not found") {
+            return Ok(None);
+        }
+        bail!(
+            "'{} get' failed with {}: {}",
+            binary,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let creds: HelperCredentials = serde_json::from_slice(&output.stdout)?;
+    let user = Some(creds.username).filter(|u| !u.is_empty());
+    let secret = Some(creds.secret).filter(|s| !s.is_empty());
+    Ok(Some((user, secret)))
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, AuthObj>,
+    #[serde(rename = "credsStore")]
+    creds_store: Option<String>,
+    #[serde(rename = "credHelpers", default)]
+    cred_helpers: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct AuthObj {
+    #[serde(default)]
+    auth: String,
+}
+
+/// Output of a credential helper `get` command.
+#[derive(Debug, Default, Deserialize)]
+struct HelperCredentials {
+    #[serde(rename = "Username")]
+    username: String,
+    #[serde(rename = "Secret")]
+    secret: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auths_match_by_hostname() -> Result<()> {
+        let config = r#"{"auths": {"https://quay.io": {"auth": "dXNlcjpwYXNz"}}}"#;
+
+        let creds = get_credentials(config.as_bytes(), "quay.io")?;
+
+        assert_eq!((Some("user".to_string()), Some("pass".to_string())), creds);
+        Ok(())
+    }
+
+    #[test]
+    fn docker_io_uses_index_entry() -> Result<()> {
+        let config = r#"{"auths": {"https://index.docker.io/v1/": {"auth": "dXNlcjpwYXNz"}}}"#;
+
+        let creds = get_credentials(config.as_bytes(), "registry-1.docker.io")?;
+
+        assert_eq!((Some("user".to_string()), Some("pass".to_string())), creds);
+        Ok(())
+    }
+
+    #[test]
+    fn failing_helper_falls_back_to_auths() -> Result<()> {
+        let config = r#"{
+            "auths": {"quay.io": {"auth": "dXNlcjpwYXNz"}},
+            "credsStore": "dkregistry-nonexistent-helper"
+        }"#;
+
+        let creds = get_credentials(config.as_bytes(), "quay.io")?;
+
+        assert_eq!((Some("user".to_string()), Some("pass".to_string())), creds);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_index_fails() {
+        let config = r#"{"auths": {}, "credHelpers": {}}"#;
+
+        assert!(get_credentials(config.as_bytes(), "quay.io").is_err());
+    }
+}
@@ -14,4 +14,77 @@ error_chain! {
         Utf8Parse(std::string::FromUtf8Error);
         StrumParse(strum::ParseError);
     }
+
+    errors {
+        /// Authentication was requested but no credentials were configured.
+        MissingCredentials {
+            description("missing credentials")
+            display("cannot authenticate without credentials")
+        }
+        /// The registry rejected the request with HTTP 429.
+        RateLimited(retry_after: Option<std::time::Duration>) {
+            description("rate limited")
+            display("rate limited by the registry, retry after {:?}", retry_after)
+        }
+        /// The request did not complete within the configured timeout.
+        Timeout(url: String) {
+            description("request timed out")
+            display("request to '{}' timed out", url)
+        }
+        /// The TLS handshake failed, e.g. because the registry certificate is not trusted.
+        Tls(url: String) {
+            description("TLS handshake failed")
+            display("TLS handshake with '{}' failed", url)
+        }
+        /// The registry answered with errors, along with the HTTP status code.
+        Registry(status: u16, errors: Vec<crate::v2::RegistryError>, headers: crate::v2::ResponseHeaders) {
+            description("registry error")
+            display(
+                "registry error (status {}): {}{}",
+                status,
+                errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "),
+                headers
+            )
+        }
+        /// The registry answered with an unexpected HTTP status.
+        Status(context: String, status: http::StatusCode, headers: crate::v2::ResponseHeaders) {
+            description("unexpected HTTP status")
+            display("{}: wrong HTTP status '{}'{}", context, status, headers)
+        }
+        /// The requested resource does not exist (HTTP 404).
+        NotFound(resource: String) {
+            description("not found")
+            display("'{}' not found", resource)
+        }
+        /// The registry does not support or allow the operation (HTTP 405).
+        Unsupported(operation: String) {
+            description("unsupported operation")
+            display("operation '{}' is not supported by the registry", operation)
+        }
+        /// A digest uses a hash algorithm which is not supported.
+        UnsupportedDigestAlgorithm(algorithm: String) {
+            description("unsupported digest algorithm")
+            display("unsupported digest algorithm '{}'", algorithm)
+        }
+        /// A request was redirected, but redirects are disabled or exhausted.
+        Redirect(location: String) {
+            description("unfollowed redirect")
+            display("request redirected to '{}'", location)
+        }
+        /// A response body exceeds the configured size limit.
+        ResponseTooLarge(url: String, limit: usize) {
+            description("response too large")
+            display("response from '{}' exceeds the limit of {} bytes", url, limit)
+        }
+        /// A repository name does not conform to the distribution spec.
+        InvalidName(name: String, reason: String) {
+            description("invalid repository name")
+            display("invalid repository name '{}': {}", name, reason)
+        }
+        /// Downloaded content does not hash to the requested digest.
+        DigestMismatch(expected: String, actual: String) {
+            description("digest mismatch")
+            display("content verification failed. expected '{}', got '{}'", expected, actual)
+        }
+    }
 }
@@ -30,6 +30,12 @@
 //! # run().await.unwrap();
 //! # }
 //! ```
+//!
+//! ## Logging
+//!
+//! Events are emitted with `tracing`, within spans carrying the `registry`,
+//! `repository`, `reference` and `operation` of high-level calls. Without
+//! a `tracing` subscriber, events are forwarded to `log`.
 
 #![deny(missing_debug_implementations)]
 
@@ -38,59 +44,18 @@ extern crate serde;
 #[macro_use]
 extern crate error_chain;
 #[macro_use]
-extern crate log;
+extern crate tracing;
 #[macro_use]
 extern crate strum_macros;
 
+pub mod credentials;
 pub mod errors;
 pub mod mediatypes;
 pub mod reference;
 pub mod render;
 pub mod v2;
 
-use errors::Result;
-use std::collections::HashMap;
-use std::io::Read;
+pub use credentials::get_credentials;
 
 /// Default User-Agent client identity.
-pub static USER_AGENT: &str = "camallo-dkregistry/0.0";
-
-/// Get registry credentials from a JSON config reader.
-///
-/// This is a convenience decoder for docker-client credentials
-/// typically stored under `~/.docker/config.json`.
-pub fn get_credentials<T: Read>(
-    reader: T,
-    index: &str,
-) -> Result<(Option<String>, Option<String>)> {
-    let map: Auths = serde_json::from_reader(reader)?;
-    let real_index = match index {
-        // docker.io has some special casing in config.json
-        "docker.io" | "registry-1.docker.io" => "https://index.docker.io/v1/",
-        other => other,
-    };
-    let auth = match map.auths.get(real_index) {
-        Some(x) => base64::decode(x.auth.as_str())?,
-        None => bail!("no auth for index {}", real_index),
-    };
-    let s = String::from_utf8(auth)?;
-    let creds: Vec<&str> = s.splitn(2, ':').collect();
-    let up = match (creds.get(0), creds.get(1)) {
-        (Some(&""), Some(p)) => (None, Some(p.to_string())),
-        (Some(u), Some(&"")) => (Some(u.to_string()), None),
-        (Some(u), Some(p)) => (Some(u.to_string()), Some(p.to_string())),
-        (_, _) => (None, None),
-    };
-    trace!("Found credentials for user={:?} on {}", up.0, index);
-    Ok(up)
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-struct Auths {
-    auths: HashMap<String, AuthObj>,
-}
-
-#[derive(Debug, Default, Deserialize, Serialize)]
-struct AuthObj {
-    auth: String,
-}
+pub static USER_AGENT: &str = concat!("dkregistry-rs/", env!("CARGO_PKG_VERSION"));
@@ -2,12 +2,17 @@
 
 use crate::errors::{Error, Result};
 use mime;
+use std::fmt;
 use strum::EnumProperty;
 
 // For schema1 types, see https://docs.docker.com/registry/spec/manifest-v2-1/
 // For schema2 types, see https://docs.docker.com/registry/spec/manifest-v2-2/
+// For OCI types, see https://github.com/opencontainers/image-spec/blob/master/media-types.md
 
-#[derive(EnumProperty, EnumString, ToString, Debug, Hash, PartialEq)]
+/// Media types of registry objects.
+///
+/// Unknown media types are kept as `Other`, so that parsing never fails.
+#[derive(EnumProperty, EnumString, IntoStaticStr, Clone, Debug, Hash, PartialEq, Eq)]
 pub enum MediaTypes {
     /// Manifest, version 2 schema 1.
     #[strum(serialize = "application/vnd.docker.distribution.manifest.v1+json")]
@@ -31,22 +36,66 @@ pub enum MediaTypes {
     #[strum(serialize = "application/vnd.docker.distribution.manifest.list.v2+json")]
     #[strum(props(Sub = "vnd.docker.distribution.manifest.list.v2+json"))]
     ManifestList,
+    /// OCI image manifest, the OCI counterpart of a schema 2 manifest.
+    #[strum(serialize = "application/vnd.oci.image.manifest.v1+json")]
+    #[strum(props(Sub = "vnd.oci.image.manifest.v1+json"))]
+    OciImageManifestV1,
+    /// OCI image index, the OCI counterpart of a manifest list.
+    #[strum(serialize = "application/vnd.oci.image.index.v1+json")]
+    #[strum(props(Sub = "vnd.oci.image.index.v1+json"))]
+    OciImageIndexV1,
     /// Image layer, as a gzip-compressed tar.
     #[strum(serialize = "application/vnd.docker.image.rootfs.diff.tar.gzip")]
     #[strum(props(Sub = "vnd.docker.image.rootfs.diff.tar.gzip"))]
     ImageLayerTgz,
+    /// Foreign image layer, not pushed to registries, as a gzip-compressed tar.
+    #[strum(serialize = "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip")]
+    #[strum(props(Sub = "vnd.docker.image.rootfs.foreign.diff.tar.gzip"))]
+    ImageLayerForeignTgz,
+    /// OCI image layer, as an uncompressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.v1.tar")]
+    #[strum(props(Sub = "vnd.oci.image.layer.v1.tar"))]
+    OciImageLayerV1Tar,
+    /// OCI image layer, as a gzip-compressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.v1.tar+gzip")]
+    #[strum(props(Sub = "vnd.oci.image.layer.v1.tar+gzip"))]
+    OciImageLayerV1TarGzip,
+    /// OCI image layer, as a zstd-compressed tar.
+    #[strum(serialize = "application/vnd.oci.image.layer.v1.tar+zstd")]
+    #[strum(props(Sub = "vnd.oci.image.layer.v1.tar+zstd"))]
+    OciImageLayerV1TarZstd,
     /// Configuration object for a container.
     #[strum(serialize = "application/vnd.docker.container.image.v1+json")]
     #[strum(props(Sub = "vnd.docker.container.image.v1+json"))]
     ContainerConfigV1,
+    /// OCI image configuration.
+    #[strum(serialize = "application/vnd.oci.image.config.v1+json")]
+    #[strum(props(Sub = "vnd.oci.image.config.v1+json"))]
+    OciImageConfigV1,
+    /// Empty OCI descriptor content, e.g. the config of artifacts.
+    #[strum(serialize = "application/vnd.oci.empty.v1+json")]
+    #[strum(props(Sub = "vnd.oci.empty.v1+json"))]
+    OciEmptyV1,
     /// Generic JSON
     #[strum(serialize = "application/json")]
     #[strum(props(Sub = "json"))]
     ApplicationJson,
+    /// Any other media type, as given.
+    #[strum(default = "true")]
+    Other(String),
+}
+
+impl fmt::Display for MediaTypes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MediaTypes::Other(media_type) => f.write_str(media_type),
+            known => f.write_str(known.into()),
+        }
+    }
 }
 
 impl MediaTypes {
-    // TODO(lucab): proper error types
+    /// Get the media type of a MIME type, ignoring its parameters.
     pub fn from_mime(mtype: &mime::Mime) -> Result<Self> {
         match (mtype.type_(), mtype.subtype(), mtype.suffix()) {
             (mime::APPLICATION, mime::JSON, _) => Ok(MediaTypes::ApplicationJson),
@@ -60,17 +109,28 @@ impl MediaTypes {
                     ("vnd.docker.distribution.manifest.list.v2", "json") => {
                         Ok(MediaTypes::ManifestList)
                     }
+                    ("vnd.oci.image.manifest.v1", "json") => Ok(MediaTypes::OciImageManifestV1),
+                    ("vnd.oci.image.index.v1", "json") => Ok(MediaTypes::OciImageIndexV1),
                     ("vnd.docker.image.rootfs.diff.tar.gzip", _) => Ok(MediaTypes::ImageLayerTgz),
+                    ("vnd.oci.image.layer.v1.tar", "gzip") => {
+                        Ok(MediaTypes::OciImageLayerV1TarGzip)
+                    }
+                    ("vnd.oci.image.layer.v1.tar", "zstd") => {
+                        Ok(MediaTypes::OciImageLayerV1TarZstd)
+                    }
                     ("vnd.docker.container.image.v1", "json") => Ok(MediaTypes::ContainerConfigV1),
-                    _ => bail!("unknown mediatype {:?}", mtype),
+                    ("vnd.oci.image.config.v1", "json") => Ok(MediaTypes::OciImageConfigV1),
+                    ("vnd.oci.empty.v1", "json") => Ok(MediaTypes::OciEmptyV1),
+                    _ => Ok(mtype.essence_str().parse()?),
                 }
             }
-            _ => bail!("unknown mediatype {:?}", mtype),
+            _ => Ok(mtype.essence_str().parse()?),
         }
     }
     pub fn to_mime(&self) -> Result<mime::Mime> {
         match self {
             &MediaTypes::ApplicationJson => Ok(mime::APPLICATION_JSON),
+            MediaTypes::Other(media_type) => media_type.parse(),
             ref m => {
                 if let Some(s) = m.get_str("Sub") {
                     ("application/".to_string() + s).parse()
@@ -81,4 +141,97 @@ impl MediaTypes {
         }
         .map_err(|e| Error::from(e.to_string()))
     }
+
+    /// Whether this is the media type of a manifest list or OCI image index.
+    pub fn is_manifest_list(&self) -> bool {
+        matches!(self, MediaTypes::ManifestList | MediaTypes::OciImageIndexV1)
+    }
+
+    /// Whether this is the media type of an image layer, of any compression.
+    pub fn is_layer(&self) -> bool {
+        matches!(
+            self,
+            MediaTypes::ImageLayerTgz
+                | MediaTypes::ImageLayerForeignTgz
+                | MediaTypes::OciImageLayerV1Tar
+                | MediaTypes::OciImageLayerV1TarGzip
+                | MediaTypes::OciImageLayerV1TarZstd
+        )
+    }
+
+    /// Whether this is the media type of a single-image manifest, of any schema.
+    pub fn is_image_manifest(&self) -> bool {
+        matches!(
+            self,
+            MediaTypes::ManifestV2S1
+                | MediaTypes::ManifestV2S1Signed
+                | MediaTypes::ManifestV2S2
+                | MediaTypes::OciImageManifestV1
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn media_types_round_trip() -> Result<()> {
+        for media_type in &[
+            "application/vnd.docker.distribution.manifest.v2+json",
+            "application/vnd.oci.image.index.v1+json",
+            "application/vnd.oci.image.config.v1+json",
+            "application/vnd.oci.image.layer.v1.tar+zstd",
+            "application/vnd.example.unknown+json",
+        ] {
+            assert_eq!(MediaTypes::from_str(media_type)?.to_string(), *media_type);
+        }
+        assert_eq!(
+            MediaTypes::from_str("application/vnd.example.unknown+json")?,
+            MediaTypes::Other("application/vnd.example.unknown+json".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn media_types_from_mime_ignore_parameters() -> Result<()> {
+        let mime: mime::Mime = "application/vnd.oci.image.manifest.v1+json; charset=utf-8"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            MediaTypes::from_mime(&mime)?,
+            MediaTypes::OciImageManifestV1
+        );
+
+        let mime: mime::Mime = "application/vnd.docker.image.rootfs.diff.tar.gzip"
+            .parse()
+            .unwrap();
+        assert_eq!(MediaTypes::from_mime(&mime)?, MediaTypes::ImageLayerTgz);
+
+        let mime: mime::Mime = "text/plain".parse().unwrap();
+        assert_eq!(
+            MediaTypes::from_mime(&mime)?,
+            MediaTypes::Other("text/plain".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn media_types_kinds() {
+        assert!(MediaTypes::ManifestList.is_manifest_list());
+        assert!(MediaTypes::OciImageIndexV1.is_manifest_list());
+        assert!(!MediaTypes::ManifestV2S2.is_manifest_list());
+
+        assert!(MediaTypes::ManifestV2S2.is_image_manifest());
+        assert!(MediaTypes::OciImageManifestV1.is_image_manifest());
+        assert!(!MediaTypes::OciImageIndexV1.is_image_manifest());
+        assert!(!MediaTypes::Other("application/json".to_string()).is_image_manifest());
+
+        assert!(MediaTypes::OciImageLayerV1TarZstd.is_layer());
+        assert!(MediaTypes::ImageLayerTgz.is_layer());
+        assert!(!MediaTypes::OciImageConfigV1.is_layer());
+    }
 }
@@ -17,6 +17,13 @@
 //! assert_eq!(dkref.registry(), "registry-1.docker.io");
 //! assert_eq!(dkref.repository(), "library/busybox");
 //! assert_eq!(dkref.version(), "latest");
+//!
+//! // Parse a reference with both a tag and a digest
+//! let dkref = Reference::parse("quay.io/foo/bar:v1@sha256:0123")?;
+//! assert_eq!(dkref.registry(), "quay.io");
+//! assert_eq!(dkref.repository(), "foo/bar");
+//! assert_eq!(dkref.tag(), Some("v1".to_string()));
+//! assert_eq!(dkref.digest(), Some("sha256:0123".to_string()));
 //! #
 //! # Ok(())
 //! # };
@@ -92,6 +99,9 @@ impl fmt::Display for Version {
 }
 
 /// A registry image reference.
+///
+/// If both a tag and a digest are given, the digest is the version
+/// and the tag is only informative.
 #[derive(Clone, Debug, Default)]
 pub struct Reference {
     has_schema: bool,
@@ -99,6 +109,7 @@ pub struct Reference {
     registry: String,
     repository: String,
     version: Version,
+    tag: Option<String>,
 }
 
 impl Reference {
@@ -111,9 +122,26 @@ impl Reference {
             registry: reg,
             repository,
             version: ver,
+            tag: None,
         }
     }
 
+    /// Parse an image reference, e.g. `quay.io/foo/bar:tag@sha256:...`.
+    ///
+    /// Docker Hub references are normalized, see `parse_with`.
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        parse_url(input, true)
+    }
+
+    /// Parse an image reference, optionally applying the Docker Hub normalization.
+    ///
+    /// When normalizing, references without a registry host get the default registry,
+    /// and single-component repositories on it the `library/` prefix. Otherwise,
+    /// the registry is left empty and the repository is kept as-is.
+    pub fn parse_with(input: &str, normalize: bool) -> Result<Self, Error> {
+        parse_url(input, normalize)
+    }
+
     pub fn registry(&self) -> String {
         self.registry.clone()
     }
@@ -126,6 +154,22 @@ impl Reference {
         self.version.to_string()
     }
 
+    /// Return the tag of this reference, if any.
+    pub fn tag(&self) -> Option<String> {
+        match self.version {
+            Version::Tag(ref t) => Some(t.clone()),
+            Version::Digest(_, _) => self.tag.clone(),
+        }
+    }
+
+    /// Return the digest of this reference, if any.
+    pub fn digest(&self) -> Option<String> {
+        match self.version {
+            Version::Tag(_) => None,
+            Version::Digest(_, _) => Some(self.version.to_string()),
+        }
+    }
+
     pub fn to_raw_string(&self) -> String {
         self.raw_input.clone()
     }
@@ -141,18 +185,25 @@ impl Reference {
 
 impl fmt::Display for Reference {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{}/{}{:?}", self.registry, self.repository, self.version)
+        if !self.registry.is_empty() {
+            write!(f, "{}/", self.registry)?;
+        }
+        write!(f, "{}", self.repository)?;
+        if let (Version::Digest(_, _), Some(tag)) = (&self.version, &self.tag) {
+            write!(f, ":{}", tag)?;
+        }
+        write!(f, "{:?}", self.version)
     }
 }
 
 impl str::FromStr for Reference {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        parse_url(s)
+        parse_url(s, true)
     }
 }
 
-fn parse_url(input: &str) -> Result<Reference, Error> {
+fn parse_url(input: &str, normalize: bool) -> Result<Reference, Error> {
     // TODO(lucab): investigate using a grammar-based parser.
     let mut rest = input;
 
@@ -183,29 +234,51 @@ fn parse_url(input: &str) -> Result<Reference, Error> {
         # optional port
         ([:][0-9]{1,6})?
         $
-    ")?.is_match(&first) {
+    ")?.is_match(&first) || (!components.is_empty() && is_localhost(&first)) {
         first
     } else {
         components.push_front(first);
-        DEFAULT_REGISTRY.to_string()
+        if normalize {
+            DEFAULT_REGISTRY.to_string()
+        } else {
+            String::new()
+        }
     };
 
-    // Take image name and extract tag or digest-ref, if any.
+    // Take image name and extract digest-ref and tag, if any.
     let last = components
         .pop_back()
         .ok_or_else(|| Error::from("missing image name"))?;
-    let (image_name, version) = match (last.rfind('@'), last.rfind(':')) {
-        (Some(i), _) | (None, Some(i)) => {
+    let (name_and_tag, digest) = match last.find('@') {
+        Some(i) => {
             let s = last.split_at(i);
-            (String::from(s.0), Version::from_str(s.1)?)
+            (String::from(s.0), Some(Version::from_str(s.1)?))
+        }
+        None => (last, None),
+    };
+    let (image_name, tag) = match name_and_tag.rfind(':') {
+        Some(i) => {
+            let s = name_and_tag.split_at(i);
+            (
+                String::from(s.0),
+                Some(s.1.trim_start_matches(':').to_string()),
+            )
         }
-        (None, None) => (last, Version::default()),
+        None => (name_and_tag, None),
     };
     ensure!(!image_name.is_empty(), "empty image name");
+    if let Some(ref tag) = tag {
+        ensure!(!tag.is_empty(), "empty tag");
+    }
+    let (version, tag) = match (digest, tag) {
+        (Some(digest), tag) => (digest, tag),
+        (None, Some(tag)) => (Version::Tag(tag), None),
+        (None, None) => (Version::default(), None),
+    };
 
     // Handle images in default library namespace, that is:
     // `ubuntu` -> `library/ubuntu`
-    if components.is_empty() && &registry == DEFAULT_REGISTRY {
+    if normalize && components.is_empty() && registry == DEFAULT_REGISTRY {
         components.push_back("library".to_string());
     }
     components.push_back(image_name);
@@ -238,5 +311,11 @@ fn parse_url(input: &str) -> Result<Reference, Error> {
         registry,
         repository,
         version,
+        tag,
     })
 }
+
+/// Check whether a path component is a `localhost` registry host, with an optional port.
+fn is_localhost(component: &str) -> bool {
+    component == "localhost" || component.starts_with("localhost:")
+}
@@ -1,13 +1,19 @@
-use crate::errors::{Error, Result};
+use crate::errors::{Error, ErrorKind, Result};
 use crate::v2::*;
 use reqwest::{header::HeaderValue, RequestBuilder, StatusCode, Url};
+use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
 
 /// Represents all supported authentication schemes and is stored by `Client`.
 #[derive(Debug, Clone)]
 pub enum Auth {
     Bearer(BearerAuth),
     Basic(BasicAuth),
+    #[cfg(feature = "negotiate")]
+    Negotiate(NegotiateAuth),
 }
 
 impl Auth {
@@ -18,53 +24,380 @@ impl Auth {
             Auth::Basic(basic_auth) => {
                 request_builder.basic_auth(basic_auth.user.clone(), basic_auth.password.clone())
             }
+            #[cfg(feature = "negotiate")]
+            Auth::Negotiate(negotiate_auth) => {
+                match negotiate::spnego_token(&negotiate_auth.host) {
+                    Ok(token) => request_builder.header(
+                        reqwest::header::AUTHORIZATION,
+                        format!("Negotiate {}", token),
+                    ),
+                    Err(e) => {
+                        warn!("failed to generate negotiate token: {}", e);
+                        request_builder
+                    }
+                }
+            }
         }
     }
 }
 
+/// Kind of authentication currently held by a `Client`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthKind {
+    /// Bearer token obtained with credentials.
+    Bearer,
+    /// HTTP Basic credentials.
+    Basic,
+    /// Bearer token obtained without credentials.
+    Anonymous,
+    /// SPNEGO (Kerberos) tokens.
+    #[cfg(feature = "negotiate")]
+    Negotiate,
+}
+
+/// Outcome of a request made with the current authentication.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthStatus {
+    /// The request was accepted (HTTP 200).
+    Authorized,
+    /// Authentication is missing or invalid (HTTP 401).
+    Unauthorized,
+    /// Authenticated, but not permitted (HTTP 403).
+    Forbidden,
+}
+
+/// Authentication challenge answered by the most recent `authenticate` call.
+///
+/// This carries no credentials or tokens, and is meant for diagnostics.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuthChallenge {
+    /// Bearer challenge, with the scopes requested from the token endpoint.
+    Bearer {
+        realm: String,
+        service: Option<String>,
+        scopes: Vec<String>,
+    },
+    /// Basic challenge.
+    Basic { realm: String },
+    /// Negotiate challenge.
+    #[cfg(feature = "negotiate")]
+    Negotiate,
+}
+
+/// Actions that can be requested for a resource in a `Scope`.
+static SCOPE_ACTIONS: &[&str] = &["pull", "push", "delete", "*"];
+
+/// Access scope for a token request.
+///
+/// Scopes are described at https://docs.docker.com/registry/spec/auth/scope/.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Scope {
+    resource_type: String,
+    resource_name: String,
+    actions: Vec<String>,
+}
+
+impl Scope {
+    /// Scope for listing the registry catalog, i.e. `registry:catalog:*`.
+    pub fn catalog() -> Self {
+        Self {
+            resource_type: "registry".to_string(),
+            resource_name: "catalog".to_string(),
+            actions: vec!["*".to_string()],
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.resource_type,
+            self.resource_name,
+            self.actions.join(",")
+        )
+    }
+}
+
+/// Builder for a validated `Scope`.
+#[derive(Clone, Debug)]
+pub struct ScopeBuilder {
+    resource_type: String,
+    resource_name: String,
+    actions: Vec<String>,
+}
+
+impl ScopeBuilder {
+    /// Start a scope for an arbitrary resource type and name.
+    pub fn new(resource_type: &str, resource_name: &str) -> Self {
+        Self {
+            resource_type: resource_type.to_owned(),
+            resource_name: resource_name.to_owned(),
+            actions: vec![],
+        }
+    }
+
+    /// Start a scope for a repository, e.g. `repository:<name>:<actions>`.
+    pub fn repository(name: &str) -> Self {
+        Self::new("repository", name)
+    }
+
+    /// Request an action on the resource.
+    pub fn action(mut self, action: &str) -> Self {
+        if !self.actions.iter().any(|a| a == action) {
+            self.actions.push(action.to_owned());
+        }
+        self
+    }
+
+    /// Request the `pull` action.
+    pub fn pull(self) -> Self {
+        self.action("pull")
+    }
+
+    /// Request the `push` action.
+    pub fn push(self) -> Self {
+        self.action("push")
+    }
+
+    /// Request the `delete` action.
+    pub fn delete(self) -> Self {
+        self.action("delete")
+    }
+
+    /// Validate and return the `Scope`.
+    pub fn build(self) -> Result<Scope> {
+        ensure!(
+            !self.resource_type.is_empty() && !self.resource_type.contains(':'),
+            "invalid scope resource type '{}'",
+            self.resource_type
+        );
+        ensure!(!self.resource_name.is_empty(), "empty scope resource name");
+        ensure!(!self.actions.is_empty(), "no actions in scope");
+        if let Some(action) = self
+            .actions
+            .iter()
+            .find(|a| !SCOPE_ACTIONS.contains(&a.as_str()))
+        {
+            bail!("unsupported scope action '{}'", action);
+        }
+
+        Ok(Scope {
+            resource_type: self.resource_type,
+            resource_name: self.resource_name,
+            actions: self.actions,
+        })
+    }
+}
+
+/// Bearer tokens shared by clones of a `Client`, keyed by their sorted scopes.
+pub(crate) type TokenCache = Arc<RwLock<HashMap<Vec<String>, BearerAuth>>>;
+
+/// OAuth2 client identifier sent to token endpoints.
+static OAUTH2_CLIENT_ID: &str = "dkregistry";
+
 /// Used for Bearer HTTP Authentication.
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct BearerAuth {
+    #[serde(default)]
     token: String,
+    access_token: Option<String>,
     expires_in: Option<u32>,
     issued_at: Option<String>,
     refresh_token: Option<String>,
+    #[serde(skip)]
+    acquired_at: Option<Instant>,
+    #[serde(skip)]
+    scopes: Vec<String>,
+    #[serde(skip)]
+    challenge: WwwAuthenticateHeaderContentBearer,
 }
 
 impl BearerAuth {
+    /// Whether the token expires within the given duration from now.
+    ///
+    /// Tokens without a known lifetime are considered to never expire.
+    fn expires_within(&self, skew: Duration) -> bool {
+        match self.expiry() {
+            Some(expiry) => expiry <= Instant::now() + skew,
+            None => false,
+        }
+    }
+
+    /// The instant at which the token expires, if its lifetime is known.
+    fn expiry(&self) -> Option<Instant> {
+        match (self.acquired_at, self.expires_in) {
+            (Some(acquired_at), Some(expires_in)) => {
+                Some(acquired_at + Duration::from_secs(expires_in.into()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Request a token for the given scopes from the challenge's realm.
+    ///
+    /// Without credentials an anonymous token is requested.
     async fn try_from_header_content(
         client: Client,
         scopes: &[&str],
-        credentials: (String, String),
+        credentials: Option<(String, String)>,
         bearer_header_content: WwwAuthenticateHeaderContentBearer,
     ) -> Result<Self> {
-        let auth_ep = bearer_header_content.auth_ep(scopes);
-        trace!("authenticate: token endpoint: {}", auth_ep);
+        client.ensure_secure_realm(&bearer_header_content.realm)?;
 
-        let url = reqwest::Url::parse(&auth_ep).map_err(|e| {
+        let auth_req = match credentials {
+            Some(credentials) if client.oauth2_post => {
+                Self::oauth2_password_request(client, scopes, credentials, &bearer_header_content)?
+            }
+            credentials => {
+                let account = match (&client.token_client_id, &credentials) {
+                    (Some(_), Some((user, _))) if !user.is_empty() => Some(user.as_str()),
+                    _ => None,
+                };
+                let url = bearer_header_content.auth_ep(
+                    scopes,
+                    client.token_client_id.as_deref(),
+                    account,
+                )?;
+                trace!("authenticate: token endpoint: {}", url);
+
+                let auth = credentials.map(|(user, password)| {
+                    Auth::Basic(BasicAuth {
+                        user,
+                        password: Some(password),
+                        expires_at: None,
+                    })
+                });
+
+                Client {
+                    auth: Arc::new(RwLock::new(auth)),
+                    ..client
+                }
+                .build_reqwest(Method::GET, url)
+            }
+        };
+
+        let acquired_at = Instant::now();
+        let mut bearer_auth =
+            Self::try_from_token_response(auth_req.send().await.map_err(request_error)?).await?;
+
+        bearer_auth.acquired_at = Some(acquired_at);
+        bearer_auth.scopes = scopes.iter().map(ToString::to_string).collect();
+        bearer_auth.challenge = bearer_header_content;
+
+        Ok(bearer_auth)
+    }
+
+    /// Build a token request for the OAuth2 `password` grant.
+    ///
+    /// Credentials are sent as form fields, with one `scope` field per scope.
+    fn oauth2_password_request(
+        client: Client,
+        scopes: &[&str],
+        credentials: (String, String),
+        bearer_header_content: &WwwAuthenticateHeaderContentBearer,
+    ) -> Result<RequestBuilder> {
+        let url = reqwest::Url::parse(&bearer_header_content.realm).map_err(|e| {
             Error::from(format!(
                 "failed to parse url from string '{}': {}",
-                auth_ep, e
+                bearer_header_content.realm, e
             ))
         })?;
+        trace!("authenticate: oauth2 token endpoint: {}", url);
+
+        let mut form = vec![
+            ("grant_type", "password".to_string()),
+            ("username", credentials.0),
+            ("password", credentials.1),
+            ("client_id", client.oauth2_client_id().to_string()),
+        ];
+        if let Some(service) = &bearer_header_content.service {
+            form.push(("service", service.clone()));
+        }
+        form.extend(scopes.iter().map(|s| ("scope", s.to_string())));
 
         let auth_req = Client {
-            auth: Some(Auth::Basic(BasicAuth {
-                user: credentials.0,
-                password: Some(credentials.1),
-            })),
+            auth: Default::default(),
             ..client
         }
-        .build_reqwest(Method::GET, url);
+        .build_reqwest(Method::POST, url)
+        .form(&form);
 
-        let r = auth_req.send().await?;
+        Ok(auth_req)
+    }
+
+    /// Renew this token for the given scopes via the OAuth2 `refresh_token` grant.
+    ///
+    /// The refresh token is sent in place of the original credentials.
+    async fn refresh_with_token(&self, client: Client, scopes: &[&str]) -> Result<Self> {
+        let refresh_token = self
+            .refresh_token
+            .clone()
+            .ok_or("cannot refresh without a refresh token")?;
+        client.ensure_secure_realm(&self.challenge.realm)?;
+
+        let url = reqwest::Url::parse(&self.challenge.realm).map_err(|e| {
+            Error::from(format!(
+                "failed to parse url from string '{}': {}",
+                self.challenge.realm, e
+            ))
+        })?;
+        trace!("refresh: token endpoint: {}", url);
+
+        let mut form = vec![
+            ("grant_type", "refresh_token".to_string()),
+            ("refresh_token", refresh_token.clone()),
+            ("client_id", client.oauth2_client_id().to_string()),
+        ];
+        if let Some(service) = &self.challenge.service {
+            form.push(("service", service.clone()));
+        }
+        if !scopes.is_empty() {
+            form.push(("scope", scopes.join(" ")));
+        }
+
+        let auth_req = Client {
+            auth: Default::default(),
+            ..client
+        }
+        .build_reqwest(Method::POST, url)
+        .form(&form);
+
+        let acquired_at = Instant::now();
+        let mut bearer_auth =
+            Self::try_from_token_response(auth_req.send().await.map_err(request_error)?).await?;
+
+        // The token endpoint may not rotate the refresh token.
+        if bearer_auth.refresh_token.is_none() {
+            bearer_auth.refresh_token = Some(refresh_token);
+        }
+        bearer_auth.acquired_at = Some(acquired_at);
+        bearer_auth.scopes = scopes.iter().map(ToString::to_string).collect();
+        bearer_auth.challenge = self.challenge.clone();
+
+        Ok(bearer_auth)
+    }
+
+    /// Parse and validate the token endpoint response.
+    async fn try_from_token_response(r: reqwest::Response) -> Result<Self> {
         let status = r.status();
         trace!("authenticate: got status {}", status);
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            bail!(ErrorKind::RateLimited(parse_retry_after(r.headers())));
+        }
         if status != StatusCode::OK {
-            bail!("authenticate: wrong HTTP status '{}'", status);
+            return Err(status_error(&r, "authenticate: GET"));
         }
 
-        let bearer_auth = r.json::<BearerAuth>().await?;
+        let mut bearer_auth = r.json::<BearerAuth>().await?;
+
+        // OAuth2 token endpoints only return `access_token`.
+        if bearer_auth.token.is_empty() {
+            if let Some(access_token) = bearer_auth.access_token.take() {
+                bearer_auth.token = access_token;
+            }
+        }
 
         match bearer_auth.token.as_str() {
             "unauthenticated" => bail!("token is unauthenticated"),
@@ -72,14 +405,10 @@ impl BearerAuth {
             _ => {}
         };
 
-        // mask the token before logging it
-        let chars_count = bearer_auth.token.chars().count();
-        let mask_start = std::cmp::min(1, chars_count - 1);
-        let mask_end = std::cmp::max(chars_count - 1, 1);
-        let mut masked_token = bearer_auth.token.clone();
-        masked_token.replace_range(mask_start..mask_end, &"*".repeat(mask_end - mask_start));
-
-        trace!("authenticate: got token: {:?}", masked_token);
+        trace!(
+            "authenticate: got token: {:?}",
+            mask_token(&bearer_auth.token)
+        );
 
         Ok(bearer_auth)
     }
@@ -90,6 +419,29 @@ impl BearerAuth {
 pub struct BasicAuth {
     user: String,
     password: Option<String>,
+    expires_at: Option<Instant>,
+}
+
+impl BasicAuth {
+    /// Whether the credentials expire within the given duration from now.
+    ///
+    /// Only short-lived credentials (e.g. from ECR) have an expiry.
+    fn expires_within(&self, skew: Duration) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= Instant::now() + skew,
+            None => false,
+        }
+    }
+}
+
+/// Used for Negotiate (SPNEGO) HTTP Authentication.
+///
+/// The handshake, including any continuation tokens, is completed while
+/// authenticating. Afterwards a fresh initial token is sent with each request.
+#[cfg(feature = "negotiate")]
+#[derive(Debug, Clone)]
+pub struct NegotiateAuth {
+    host: String,
 }
 
 /// Structured representation for the content of the authentication response header.
@@ -97,18 +449,71 @@ pub struct BasicAuth {
 pub(crate) enum WwwAuthenticateHeaderContent {
     Bearer(WwwAuthenticateHeaderContentBearer),
     Basic(WwwAuthenticateHeaderContentBasic),
+    #[cfg(feature = "negotiate")]
+    Negotiate,
 }
 
 impl WwwAuthenticateHeaderContent {
+    /// Create a `WwwAuthenticateHeaderContent` from all challenges in a response.
+    ///
+    /// Bearer is preferred over Negotiate (if enabled), then Basic.
+    /// Challenges with unsupported schemes are ignored.
+    pub(crate) fn from_www_authentication_headers(header_values: Vec<HeaderValue>) -> Result<Self> {
+        let mut fallbacks = vec![];
+        for header_value in header_values {
+            match Self::from_www_authentication_header(header_value) {
+                Ok(content @ WwwAuthenticateHeaderContent::Bearer(_)) => return Ok(content),
+                Ok(content) => fallbacks.push(content),
+                Err(e) => debug!("skipping unsupported authentication challenge: {}", e),
+            }
+        }
+
+        #[cfg(feature = "negotiate")]
+        fallbacks.sort_by_key(|content| match content {
+            WwwAuthenticateHeaderContent::Negotiate => 0,
+            _ => 1,
+        });
+
+        fallbacks
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::from("no supported authentication challenge found"))
+    }
+
+    /// Describe this challenge, as answered for the given scopes.
+    fn to_challenge(&self, scopes: &[&str]) -> AuthChallenge {
+        match self {
+            WwwAuthenticateHeaderContent::Bearer(bearer) => AuthChallenge::Bearer {
+                realm: bearer.realm.clone(),
+                service: bearer.service.clone(),
+                scopes: scopes.iter().map(ToString::to_string).collect(),
+            },
+            WwwAuthenticateHeaderContent::Basic(basic) => AuthChallenge::Basic {
+                realm: basic.realm.clone(),
+            },
+            #[cfg(feature = "negotiate")]
+            WwwAuthenticateHeaderContent::Negotiate => AuthChallenge::Negotiate,
+        }
+    }
+
     /// Create a `WwwAuthenticateHeaderContent` by parsing a `HeaderValue` instance.
     pub(crate) fn from_www_authentication_header(header_value: HeaderValue) -> Result<Self> {
         let header = String::from_utf8(header_value.as_bytes().to_vec())?;
 
+        // Negotiate challenges carry no parameters, or a bare token.
+        #[cfg(feature = "negotiate")]
+        {
+            let scheme = header.split_whitespace().next().unwrap_or_default();
+            if scheme.eq_ignore_ascii_case("negotiate") {
+                return Ok(WwwAuthenticateHeaderContent::Negotiate);
+            }
+        }
+
         // This regex will result in multiple captures which will contain one key-value pair each.
         // The first capture will be the only one with the "method" group set.
         let re = regex::Regex::new(
             r#"(?x)\s*
-            ((?P<method>[A-Z][a-z]+)\s*)?
+            ((?P<method>[A-Za-z]+)\s+)?
             (
                 \s*
                     (?P<key>[a-z]+)
@@ -132,6 +537,13 @@ impl WwwAuthenticateHeaderContent {
             .as_str()
             .to_string();
 
+        // The authentication scheme is case-insensitive, see RFC 7235.
+        let method = match method.to_lowercase().as_str() {
+            "bearer" => "Bearer".to_string(),
+            "basic" => "Basic".to_string(),
+            _ => method,
+        };
+
         let serialized_content = {
             let serialized_captures = captures
                 .iter()
@@ -179,7 +591,7 @@ impl WwwAuthenticateHeaderContent {
 }
 
 /// Structured content for the Bearer authentication response header.
-#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
 pub(crate) struct WwwAuthenticateHeaderContentBearer {
     realm: String,
     service: Option<String>,
@@ -187,30 +599,45 @@ pub(crate) struct WwwAuthenticateHeaderContentBearer {
 }
 
 impl WwwAuthenticateHeaderContentBearer {
-    fn auth_ep(&self, scopes: &[&str]) -> String {
-        let service = self
-            .service
-            .as_ref()
-            .map(|sv| format!("?service={}", sv))
-            .unwrap_or_default();
-
-        let scope = scopes
-            .iter()
-            .enumerate()
-            .fold(String::new(), |acc, (i, &s)| {
-                let separator = if i > 1 { "&" } else { "" };
-                acc + separator + "scope=" + s
-            });
-
-        let scope_prefix = if scopes.is_empty() {
-            ""
-        } else if service.is_empty() {
-            "?"
-        } else {
-            "&"
-        };
+    /// Build the token endpoint URL for the given scopes.
+    ///
+    /// Any query parameters already present in the realm are preserved,
+    /// `client_id` and `account` are only added if missing from it.
+    fn auth_ep(
+        &self,
+        scopes: &[&str],
+        client_id: Option<&str>,
+        account: Option<&str>,
+    ) -> Result<Url> {
+        let mut url = Url::parse(&self.realm).map_err(|e| {
+            Error::from(format!(
+                "failed to parse url from string '{}': {}",
+                self.realm, e
+            ))
+        })?;
+
+        let in_realm = |key: &str| url.query_pairs().any(|(k, _)| k == key);
+        let client_id = client_id.filter(|_| !in_realm("client_id"));
+        let account = account.filter(|_| !in_realm("account"));
+
+        if self.service.is_some() || !scopes.is_empty() || client_id.is_some() || account.is_some()
+        {
+            let mut query_pairs = url.query_pairs_mut();
+            if let Some(service) = &self.service {
+                query_pairs.append_pair("service", service);
+            }
+            for scope in scopes {
+                query_pairs.append_pair("scope", scope);
+            }
+            if let Some(client_id) = client_id {
+                query_pairs.append_pair("client_id", client_id);
+            }
+            if let Some(account) = account {
+                query_pairs.append_pair("account", account);
+            }
+        }
 
-        format!("{}{}{}{}", self.realm, service, scope_prefix, scope)
+        Ok(url)
     }
 }
 
@@ -221,83 +648,503 @@ pub(crate) struct WwwAuthenticateHeaderContentBasic {
 }
 
 impl Client {
-    /// Make a request and return the response's www authentication header.
-    async fn get_www_authentication_header(&self) -> Result<HeaderValue> {
+    /// Return the `client_id` sent in OAuth2 token requests.
+    fn oauth2_client_id(&self) -> &str {
+        self.token_client_id.as_deref().unwrap_or(OAUTH2_CLIENT_ID)
+    }
+
+    /// Refuse to send credentials to a plain-HTTP token endpoint, unless the registry is insecure too.
+    fn ensure_secure_realm(&self, realm: &str) -> Result<()> {
+        let insecure_registry = self.base_url.starts_with("http://");
+        if !insecure_registry && !realm.starts_with("https://") {
+            bail!(
+                "authenticate: refusing insecure token endpoint '{}' for a secure registry",
+                realm
+            );
+        }
+        Ok(())
+    }
+
+    /// Make a request and return the response's www authentication headers.
+    async fn get_www_authentication_headers(&self) -> Result<Vec<HeaderValue>> {
         let url = {
-            let ep = format!("{}/v2/", self.base_url.clone(),);
+            let ep = format!("{}{}/", self.base_url, self.api_base_path,);
             reqwest::Url::parse(&ep)
                 .map_err(|e| format!("failed to parse url from string '{}': {}", ep, e))?
         };
 
         let r = self
-            .build_reqwest(Method::GET, url.clone())
-            .send()
-            .map_err(|e| Error::from(format!("{}", e)))
+            .send_with_retry(&|| self.build_reqwest(Method::GET, url.clone()))
             .await?;
 
         trace!("GET '{}' status: {:?}", r.url(), r.status());
-        r.headers()
-            .get(reqwest::header::WWW_AUTHENTICATE)
-            .ok_or_else(|| {
-                Error::from(format!(
-                    "missing {:?} header",
-                    reqwest::header::WWW_AUTHENTICATE
-                ))
-            })
+        let header_values = r
+            .headers()
+            .get_all(reqwest::header::WWW_AUTHENTICATE)
+            .iter()
             .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+
+        if header_values.is_empty() {
+            bail!("missing {:?} header", reqwest::header::WWW_AUTHENTICATE);
+        }
+
+        Ok(header_values)
     }
 
     /// Perform registry authentication and return the authenticated client.
     ///
     /// If Bearer authentication is used the returned client will be authorized for the requested scopes.
+    /// Without credentials, an anonymous token is requested for Bearer challenges.
     pub async fn authenticate(mut self, scopes: &[&str]) -> Result<Self> {
-        let credentials = self
-            .credentials
-            .clone()
-            .ok_or("cannot authenticate without credentials")?;
+        let span = info_span!("registry_operation", operation = "authenticate", registry = %self.index, scopes = ?scopes);
+        async move {
+            if let Some(bearer_auth) = self.cached_token(scopes) {
+                trace!("authenticate: reusing cached token");
+                self.auth = Arc::new(RwLock::new(Some(Auth::Bearer(bearer_auth))));
+                return Ok(self);
+            }
+
+            let current_auth = self.auth.read().expect("auth lock poisoned").clone();
+            if let Some(Auth::Bearer(bearer_auth)) = current_auth {
+                if bearer_auth.refresh_token.is_some() {
+                    match bearer_auth.refresh_with_token(self.clone(), scopes).await {
+                        Ok(refreshed_auth) => {
+                            trace!("authenticate: renewed token with refresh token");
+                            self.cache_token(&refreshed_auth);
+                            self.auth = Arc::new(RwLock::new(Some(Auth::Bearer(refreshed_auth))));
+                            return Ok(self);
+                        }
+                        Err(e) => debug!("authenticate: refresh token rejected: {}", e),
+                    }
+                }
+            }
+
+            let credentials_expiry = self.fetch_ecr_credentials().await?;
+            let credentials = self.credentials.clone();
 
+            let client = Client {
+                auth: Default::default(),
+                ..self.clone()
+            };
+
+            let authentication_headers = client.get_www_authentication_headers().await?;
+            let content = WwwAuthenticateHeaderContent::from_www_authentication_headers(
+                authentication_headers,
+            )?;
+            self.last_auth_challenge = Some(content.to_challenge(scopes));
+
+            let auth = match content {
+                WwwAuthenticateHeaderContent::Basic(_) => {
+                    let (user, password) = credentials.ok_or(ErrorKind::MissingCredentials)?;
+                    let basic_auth = BasicAuth {
+                        user,
+                        password: Some(password),
+                        expires_at: credentials_expiry,
+                    };
+
+                    Auth::Basic(basic_auth)
+                }
+                #[cfg(feature = "negotiate")]
+                WwwAuthenticateHeaderContent::Negotiate => {
+                    let mut context = negotiate::GssContext::new(&self.index)?;
+                    client.negotiate(&mut context).await?;
+
+                    Auth::Negotiate(NegotiateAuth {
+                        host: self.index.clone(),
+                    })
+                }
+                WwwAuthenticateHeaderContent::Bearer(bearer_header_content) => {
+                    let bearer_auth = BearerAuth::try_from_header_content(
+                        client,
+                        scopes,
+                        credentials,
+                        bearer_header_content,
+                    )
+                    .await?;
+                    self.cache_token(&bearer_auth);
+
+                    Auth::Bearer(bearer_auth)
+                }
+            };
+
+            trace!("authenticate: login succeeded");
+            self.auth = Arc::new(RwLock::new(Some(auth)));
+
+            Ok(self)
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Perform registry authentication for typed scopes.
+    ///
+    /// This is equivalent to `authenticate` with the scopes formatted as strings.
+    pub async fn authenticate_scopes(self, scopes: &[Scope]) -> Result<Self> {
+        let scopes = scopes.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let scopes = scopes.iter().map(String::as_str).collect::<Vec<_>>();
+        self.authenticate(&scopes).await
+    }
+
+    /// Renew the bearer token if it is about to expire.
+    ///
+    /// The renewed token is requested for the same scopes as the current one.
+    pub(crate) async fn refresh_auth_if_needed(&self) -> Result<()> {
+        let current_auth = self.auth.read().expect("auth lock poisoned").clone();
+        let bearer_auth = match current_auth {
+            Some(Auth::Bearer(bearer_auth))
+                if bearer_auth.expires_within(self.token_refresh_skew) =>
+            {
+                bearer_auth
+            }
+            Some(Auth::Basic(basic_auth)) if basic_auth.expires_within(self.token_refresh_skew) => {
+                return self.renew_basic_auth().await;
+            }
+            _ => return Ok(()),
+        };
+
+        trace!("refresh: renewing bearer token");
         let client = Client {
-            auth: None,
+            auth: Default::default(),
             ..self.clone()
         };
+        let scopes = bearer_auth
+            .scopes
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
 
-        let authentication_header = client.get_www_authentication_header().await?;
-        let auth = match WwwAuthenticateHeaderContent::from_www_authentication_header(
-            authentication_header,
-        )? {
-            WwwAuthenticateHeaderContent::Basic(_) => {
-                let basic_auth = BasicAuth {
-                    user: credentials.0,
-                    password: Some(credentials.1),
-                };
-
-                Auth::Basic(basic_auth)
-            }
-            WwwAuthenticateHeaderContent::Bearer(bearer_header_content) => {
-                let bearer_auth = BearerAuth::try_from_header_content(
+        let renewed_auth = match bearer_auth.refresh_token {
+            Some(_) => bearer_auth.refresh_with_token(client, &scopes).await?,
+            None => {
+                BearerAuth::try_from_header_content(
                     client,
-                    scopes,
-                    credentials,
-                    bearer_header_content,
+                    &scopes,
+                    self.credentials.clone(),
+                    bearer_auth.challenge,
                 )
-                .await?;
+                .await?
+            }
+        };
+
+        self.cache_token(&renewed_auth);
+        *self.auth.write().expect("auth lock poisoned") = Some(Auth::Bearer(renewed_auth));
 
-                Auth::Bearer(bearer_auth)
+        Ok(())
+    }
+
+    /// Send a request, re-authenticating once if the registry asks for more scopes.
+    ///
+    /// `build` is called again for the retry, so that it picks up the escalated token.
+    /// Transient errors are retried according to the client retry policy.
+    pub(crate) async fn send_with_escalation<F>(&self, build: F) -> Result<reqwest::Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        self.send_escalating(build, false).await
+    }
+
+    /// Send a request to the registry itself like `send_with_escalation`, never to its mirrors.
+    pub(crate) async fn send_to_primary_with_escalation<F>(
+        &self,
+        build: F,
+    ) -> Result<reqwest::Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        self.send_escalating(build, true).await
+    }
+
+    async fn send_escalating<F>(&self, build: F, primary_only: bool) -> Result<reqwest::Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let response = self.send_retrying(&build, primary_only).await?;
+        if !self.auto_escalate_scopes || response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        match self.escalate_scopes(response.headers()).await {
+            Ok(true) => {
+                trace!(
+                    "escalate: retrying '{}' with escalated scopes",
+                    response.url()
+                );
+                self.send_retrying(&build, primary_only).await
+            }
+            Ok(false) => Ok(response),
+            Err(e) => {
+                debug!("escalate: failed to escalate scopes: {}", e);
+                Ok(response)
             }
+        }
+    }
+
+    async fn send_retrying<F>(&self, build: &F, primary_only: bool) -> Result<reqwest::Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        if primary_only {
+            self.send_to_primary(build).await
+        } else {
+            self.send_with_retry(build).await
+        }
+    }
+
+    /// Replace the bearer token with one for the union of its scopes and the challenged ones.
+    ///
+    /// Returns `false` if the challenge does not ask for any scope beyond the current token's.
+    async fn escalate_scopes(&self, headers: &reqwest::header::HeaderMap) -> Result<bool> {
+        let current_auth = self.auth.read().expect("auth lock poisoned").clone();
+        let bearer_auth = match current_auth {
+            Some(Auth::Bearer(bearer_auth)) => bearer_auth,
+            _ => return Ok(false),
         };
 
-        trace!("authenticate: login succeeded");
-        self.auth = Some(auth);
+        let header_values = headers
+            .get_all(reqwest::header::WWW_AUTHENTICATE)
+            .iter()
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+        let challenge =
+            match WwwAuthenticateHeaderContent::from_www_authentication_headers(header_values)? {
+                WwwAuthenticateHeaderContent::Bearer(challenge) => challenge,
+                _ => return Ok(false),
+            };
+
+        let mut scopes = bearer_auth.scopes.clone();
+        for scope in challenge.scope.iter().flat_map(|s| s.split_whitespace()) {
+            if !scopes.iter().any(|s| s == scope) {
+                scopes.push(scope.to_string());
+            }
+        }
+        if scopes.len() == bearer_auth.scopes.len() {
+            return Ok(false);
+        }
+
+        trace!("escalate: requesting token for scopes {:?}", scopes);
+        let client = Client {
+            auth: Default::default(),
+            ..self.clone()
+        };
+        let scopes = scopes.iter().map(String::as_str).collect::<Vec<_>>();
+        let escalated_auth = BearerAuth::try_from_header_content(
+            client,
+            &scopes,
+            self.credentials.clone(),
+            challenge,
+        )
+        .await?;
+
+        self.cache_token(&escalated_auth);
+        *self.auth.write().expect("auth lock poisoned") = Some(Auth::Bearer(escalated_auth));
+
+        Ok(true)
+    }
+
+    /// Replace expiring Basic credentials with freshly fetched ones.
+    async fn renew_basic_auth(&self) -> Result<()> {
+        let mut client = self.clone();
+        let expires_at = match client.fetch_ecr_credentials().await? {
+            Some(expires_at) => expires_at,
+            None => {
+                debug!("refresh: credentials are expiring but cannot be renewed");
+                return Ok(());
+            }
+        };
+
+        trace!("refresh: renewed basic credentials");
+        let (user, password) = client.credentials.ok_or(ErrorKind::MissingCredentials)?;
+        *self.auth.write().expect("auth lock poisoned") = Some(Auth::Basic(BasicAuth {
+            user,
+            password: Some(password),
+            expires_at: Some(expires_at),
+        }));
+
+        Ok(())
+    }
+
+    /// Replace the credentials with ones from ECR, if enabled, and return their expiry.
+    #[cfg(feature = "ecr")]
+    async fn fetch_ecr_credentials(&mut self) -> Result<Option<Instant>> {
+        if !self.ecr {
+            return Ok(None);
+        }
+
+        let ecr_credentials = ecr::get_authorization_token(&self.client, &self.index).await?;
+        self.credentials = Some((ecr_credentials.user, ecr_credentials.password));
+
+        Ok(Some(ecr_credentials.expires_at))
+    }
+
+    /// Replace the credentials with ones from ECR, if enabled, and return their expiry.
+    #[cfg(not(feature = "ecr"))]
+    async fn fetch_ecr_credentials(&mut self) -> Result<Option<Instant>> {
+        Ok(None)
+    }
+
+    /// Look up a still valid cached token for the given scopes.
+    ///
+    /// Expired entries are evicted on lookup.
+    fn cached_token(&self, scopes: &[&str]) -> Option<BearerAuth> {
+        let cache = self.token_cache.as_ref()?;
+        let key = token_cache_key(scopes.iter().copied());
+
+        let mut cache = cache.write().expect("token cache lock poisoned");
+        match cache.get(&key) {
+            Some(bearer_auth) if !bearer_auth.expires_within(self.token_refresh_skew) => {
+                Some(bearer_auth.clone())
+            }
+            Some(_) => {
+                trace!("authenticate: evicting expired token for {:?}", key);
+                cache.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Store a token in the cache, if enabled.
+    fn cache_token(&self, bearer_auth: &BearerAuth) {
+        if let Some(cache) = &self.token_cache {
+            let key = token_cache_key(bearer_auth.scopes.iter().map(String::as_str));
+            cache
+                .write()
+                .expect("token cache lock poisoned")
+                .insert(key, bearer_auth.clone());
+        }
+    }
 
-        Ok(self)
+    /// Return the instant at which the current bearer token expires.
+    ///
+    /// This is `None` if no bearer token is held or its lifetime is unknown.
+    pub fn token_expiry(&self) -> Option<Instant> {
+        match self.auth.read().expect("auth lock poisoned").as_ref() {
+            Some(Auth::Bearer(bearer_auth)) => bearer_auth.expiry(),
+            _ => None,
+        }
+    }
+
+    /// Return the challenge answered by the most recent `authenticate` call.
+    ///
+    /// This is `None` until a challenge has been answered. Tokens served from
+    /// the cache or renewed with a refresh token leave it unchanged.
+    pub fn last_auth_challenge(&self) -> Option<AuthChallenge> {
+        self.last_auth_challenge.clone()
+    }
+
+    /// Return the kind of authentication currently held by this client.
+    ///
+    /// This is `None` if the client has not been authenticated.
+    pub fn is_authenticated_as(&self) -> Option<AuthKind> {
+        match self.auth.read().expect("auth lock poisoned").as_ref() {
+            Some(Auth::Bearer(_)) if self.credentials.is_none() => Some(AuthKind::Anonymous),
+            Some(Auth::Bearer(_)) => Some(AuthKind::Bearer),
+            Some(Auth::Basic(_)) => Some(AuthKind::Basic),
+            #[cfg(feature = "negotiate")]
+            Some(Auth::Negotiate(_)) => Some(AuthKind::Negotiate),
+            None => None,
+        }
+    }
+
+    /// Replace the credentials of this client, e.g. after they were rotated.
+    ///
+    /// Held and cached tokens are dropped, so that the next `authenticate`
+    /// uses the new credentials. The connection pool is kept. Clones of
+    /// this client made before the call keep using the previous credentials.
+    pub fn set_credentials(&mut self, user: &str, password: &str) {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self.auth = Default::default();
+        if self.token_cache.is_some() {
+            self.token_cache = Some(Default::default());
+        }
     }
 
     /// Check whether the client can successfully make requests to the registry.
     ///
     /// This could be due to granted anonymous access or valid credentials.
+    /// A `Forbidden` status is reported as `false`.
     pub async fn is_auth(&self) -> Result<bool> {
+        match self.auth_status().await? {
+            AuthStatus::Authorized => Ok(true),
+            AuthStatus::Unauthorized | AuthStatus::Forbidden => Ok(false),
+        }
+    }
+
+    /// Check whether a repository can be pulled without credentials.
+    ///
+    /// An anonymous token is requested for `repository:<name>:pull` and checked
+    /// with a `HEAD` of the `latest` manifest, which may not exist. The stored
+    /// credentials and tokens of this client are left untouched. Failures
+    /// other than a denied token, e.g. an unavailable token endpoint, are
+    /// returned as errors.
+    pub async fn anonymous_pull_allowed(&self, name: &str) -> Result<bool> {
+        validate_name(name)?;
+        let anonymous = Client {
+            credentials: None,
+            auth: Default::default(),
+            token_cache: None,
+            auto_escalate_scopes: false,
+            last_auth_challenge: None,
+            #[cfg(feature = "ecr")]
+            ecr: false,
+            ..self.clone()
+        };
+
+        let anonymous = match anonymous.auth_status().await? {
+            AuthStatus::Authorized => anonymous,
+            _ => match anonymous
+                .authenticate(&[&format!("repository:{}:pull", name)])
+                .await
+            {
+                Ok(anonymous) => anonymous,
+                Err(e) => match e.kind() {
+                    ErrorKind::MissingCredentials => {
+                        debug!("anonymous_pull_allowed: no anonymous token: {}", e);
+                        return Ok(false);
+                    }
+                    ErrorKind::Status(_, status, _)
+                        if *status == StatusCode::UNAUTHORIZED
+                            || *status == StatusCode::FORBIDDEN =>
+                    {
+                        debug!("anonymous_pull_allowed: anonymous token denied: {}", e);
+                        return Ok(false);
+                    }
+                    _ => return Err(e),
+                },
+            },
+        };
+
+        let ep = format!(
+            "{}{}/{}/manifests/latest",
+            self.base_url, self.api_base_path, name
+        );
+        let url = Url::parse(&ep)
+            .map_err(|e| Error::from(format!("failed to parse url from string '{}': {}", ep, e)))?;
+        let accept_headers =
+            manifest::build_accept_headers(&self.index, self.accept_media_types.as_deref(), false);
+        let res = anonymous
+            .send_with_retry(&|| {
+                anonymous
+                    .build_reqwest(Method::HEAD, url.clone())
+                    .headers(accept_headers.clone())
+            })
+            .await?;
+
+        trace!("HEAD '{}' status: {:?}", res.url(), res.status());
+        match res.status() {
+            status if status.is_success() => Ok(true),
+            // The repository is readable, but has no `latest` tag.
+            StatusCode::NOT_FOUND => Ok(true),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Ok(false),
+            _ => Err(response_error(res, "anonymous_pull_allowed: HEAD").await),
+        }
+    }
+
+    /// Check how the registry responds to requests made by this client.
+    pub async fn auth_status(&self) -> Result<AuthStatus> {
         let url = {
-            let ep = format!("{}/v2/", self.base_url.clone(),);
+            let ep = format!("{}{}/", self.base_url, self.api_base_path,);
             match Url::parse(&ep) {
                 Ok(url) => url,
                 Err(e) => {
@@ -309,25 +1156,99 @@ impl Client {
             }
         };
 
-        let req = self.build_reqwest(Method::GET, url.clone());
-
+        self.refresh_auth_if_needed().await?;
         trace!("Sending request to '{}'", url);
-        let resp = req.send().await?;
+        let resp = self
+            .send_with_retry(&|| self.build_reqwest(Method::GET, url.clone()))
+            .await?;
         trace!("GET '{:?}'", resp);
 
         let status = resp.status();
         match status {
-            reqwest::StatusCode::OK => Ok(true),
-            reqwest::StatusCode::UNAUTHORIZED => Ok(false),
-            _ => Err(format!("is_auth: wrong HTTP status '{}'", status).into()),
+            reqwest::StatusCode::OK => Ok(AuthStatus::Authorized),
+            reqwest::StatusCode::UNAUTHORIZED => Ok(AuthStatus::Unauthorized),
+            reqwest::StatusCode::FORBIDDEN => Ok(AuthStatus::Forbidden),
+            _ => Err(status_error(&resp, "auth_status: GET")),
         }
     }
 }
 
+/// Mask all but the first and last characters of a token, for logging.
+///
+/// Tokens too short to keep anything hidden are masked entirely.
+fn mask_token(token: &str) -> String {
+    let chars_count = token.chars().count();
+    if chars_count <= 2 {
+        return "*".repeat(chars_count);
+    }
+
+    token
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if i == 0 || i == chars_count - 1 {
+                c
+            } else {
+                '*'
+            }
+        })
+        .collect()
+}
+
+/// Build the token cache key for a set of scopes.
+fn token_cache_key<'a>(scopes: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut key = scopes.map(ToString::to_string).collect::<Vec<_>>();
+    key.sort();
+    key.dedup();
+    key
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn insecure_realm_requires_insecure_registry() -> Result<()> {
+        let client = Client::configure()
+            .registry("registry.example.com")
+            .build()?;
+        assert!(client
+            .ensure_secure_realm("https://auth.example.com/token")
+            .is_ok());
+        assert!(client
+            .ensure_secure_realm("http://auth.example.com/token")
+            .is_err());
+
+        let client = Client::configure()
+            .registry("registry.example.com")
+            .insecure_registry(true)
+            .build()?;
+        assert!(client
+            .ensure_secure_realm("http://auth.example.com/token")
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn basic_auth_encodes_empty_password() -> Result<()> {
+        let auth = Auth::Basic(BasicAuth {
+            user: "USERNAME".to_string(),
+            password: Some(String::new()),
+            expires_at: None,
+        });
+        let request = auth
+            .add_auth_headers(reqwest::Client::new().get("http://registry.example.com/v2/"))
+            .build()?;
+        // base64("USERNAME:")
+        assert_eq!(
+            request.headers()[reqwest::header::AUTHORIZATION],
+            "Basic VVNFUk5BTUU6"
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn bearer_realm_parses_correctly() -> Result<()> {
         let realm = "https://sat-r220-02.lab.eng.rdu2.redhat.com/v2/token";
@@ -378,4 +1299,198 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn scheme_parses_case_insensitively() -> Result<()> {
+        let realm = "https://auth.example.com/token";
+
+        for scheme in &["Bearer", "bearer", "BEARER"] {
+            let header_value =
+                HeaderValue::from_str(&format!(r#"{} realm="{}",service="svc""#, scheme, realm))?;
+
+            let content =
+                WwwAuthenticateHeaderContent::from_www_authentication_header(header_value)?;
+
+            assert_eq!(
+                WwwAuthenticateHeaderContent::Bearer(WwwAuthenticateHeaderContentBearer {
+                    realm: realm.to_string(),
+                    service: Some("svc".to_string()),
+                    scope: None,
+                }),
+                content
+            );
+        }
+
+        for scheme in &["basic", "BASIC"] {
+            let header_value = HeaderValue::from_str(&format!(r#"{} realm="Registry""#, scheme))?;
+
+            let content =
+                WwwAuthenticateHeaderContent::from_www_authentication_header(header_value)?;
+
+            assert_eq!(
+                WwwAuthenticateHeaderContent::Basic(WwwAuthenticateHeaderContentBasic {
+                    realm: "Registry".to_string(),
+                }),
+                content
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn bearer_challenge_is_preferred() -> Result<()> {
+        let realm = "https://auth.example.com/token";
+
+        let header_values = vec![
+            HeaderValue::from_str("Negotiate")?,
+            HeaderValue::from_str(r#"Basic realm="Registry""#)?,
+            HeaderValue::from_str(&format!(r#"Bearer realm="{}""#, realm))?,
+        ];
+
+        let content = WwwAuthenticateHeaderContent::from_www_authentication_headers(header_values)?;
+
+        assert_eq!(
+            WwwAuthenticateHeaderContent::Bearer(WwwAuthenticateHeaderContentBearer {
+                realm: realm.to_string(),
+                service: None,
+                scope: None,
+            }),
+            content
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "negotiate")]
+    #[test]
+    fn negotiate_challenge_is_preferred_over_basic() -> Result<()> {
+        let header_values = vec![
+            HeaderValue::from_str(r#"Basic realm="Registry""#)?,
+            HeaderValue::from_str("negotiate")?,
+        ];
+
+        let content = WwwAuthenticateHeaderContent::from_www_authentication_headers(header_values)?;
+
+        assert_eq!(WwwAuthenticateHeaderContent::Negotiate, content);
+
+        Ok(())
+    }
+
+    #[test]
+    fn scope_builder_formats_scopes() -> Result<()> {
+        let scope = ScopeBuilder::repository("foo/bar").pull().push().build()?;
+        assert_eq!("repository:foo/bar:pull,push", scope.to_string());
+
+        let scope = ScopeBuilder::new("repository(plugin)", "foo/bar")
+            .action("*")
+            .build()?;
+        assert_eq!("repository(plugin):foo/bar:*", scope.to_string());
+
+        assert_eq!("registry:catalog:*", Scope::catalog().to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn scope_builder_rejects_invalid_scopes() {
+        assert!(ScopeBuilder::repository("foo").build().is_err());
+        assert!(ScopeBuilder::repository("").pull().build().is_err());
+        assert!(ScopeBuilder::repository("foo")
+            .action("write")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn short_tokens_are_masked() {
+        assert_eq!("", mask_token(""));
+        assert_eq!("*", mask_token("a"));
+        assert_eq!("**", mask_token("ab"));
+        assert_eq!("a*c", mask_token("abc"));
+        assert_eq!("t***n", mask_token("token"));
+    }
+
+    #[test]
+    fn bearer_auth_expires_within_skew() {
+        let bearer_auth = BearerAuth {
+            expires_in: Some(60),
+            acquired_at: Some(Instant::now()),
+            ..Default::default()
+        };
+        assert!(!bearer_auth.expires_within(Duration::from_secs(30)));
+        assert!(bearer_auth.expires_within(Duration::from_secs(90)));
+
+        let unbounded_auth = BearerAuth {
+            expires_in: None,
+            acquired_at: Some(Instant::now()),
+            ..Default::default()
+        };
+        assert!(!unbounded_auth.expires_within(Duration::from_secs(90)));
+    }
+
+    #[test]
+    fn bearer_auth_ep_joins_multiple_scopes() -> Result<()> {
+        let content = WwwAuthenticateHeaderContentBearer {
+            realm: "https://auth.example.com/token".to_string(),
+            service: Some("registry.example.com".to_string()),
+            scope: None,
+        };
+
+        let auth_ep = content.auth_ep(
+            &[
+                "repository:a:pull",
+                "repository:b:pull",
+                "repository:c:pull",
+            ],
+            None,
+            None,
+        )?;
+
+        assert_eq!(
+            "https://auth.example.com/token?service=registry.example.com\
+             &scope=repository%3Aa%3Apull&scope=repository%3Ab%3Apull&scope=repository%3Ac%3Apull",
+            auth_ep.as_str()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn bearer_auth_ep_keeps_realm_query() -> Result<()> {
+        let content = WwwAuthenticateHeaderContentBearer {
+            realm: "https://auth.example.com/token?account=foo".to_string(),
+            service: Some("registry.example.com".to_string()),
+            scope: None,
+        };
+
+        let auth_ep = content.auth_ep(&["repository:a:pull"], None, Some("bar"))?;
+
+        assert_eq!(
+            "https://auth.example.com/token?account=foo&service=registry.example.com\
+             &scope=repository%3Aa%3Apull",
+            auth_ep.as_str()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn bearer_auth_ep_adds_client_id_and_account() -> Result<()> {
+        let content = WwwAuthenticateHeaderContentBearer {
+            realm: "https://auth.example.com/token".to_string(),
+            service: Some("registry.example.com".to_string()),
+            scope: None,
+        };
+
+        let auth_ep = content.auth_ep(&["repository:a:pull"], Some("my-tool"), Some("foo"))?;
+
+        assert_eq!(
+            "https://auth.example.com/token?service=registry.example.com\
+             &scope=repository%3Aa%3Apull&client_id=my-tool&account=foo",
+            auth_ep.as_str()
+        );
+
+        Ok(())
+    }
 }
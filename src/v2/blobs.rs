@@ -1,13 +1,56 @@
-use crate::errors::{Error, Result};
+use crate::errors::{Error, ErrorKind, Result};
 use crate::v2::*;
+use bytes::Bytes;
+use futures::future;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
 use reqwest;
 use reqwest::{Method, StatusCode};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::Instrument;
 
 impl Client {
     /// Check if a blob exists.
+    ///
+    /// This is meant to skip uploading or mounting blobs already in the repository.
+    /// The registry itself is asked, not its mirrors, which may have blobs it lacks.
     pub async fn has_blob(&self, name: &str, digest: &str) -> Result<bool> {
+        Ok(self.head_blob(name, digest, true).await?.is_some())
+    }
+
+    /// Return the size of a blob, as announced by the registry, without downloading it.
+    ///
+    /// Returns `None` if the blob does not exist.
+    pub async fn blob_size(&self, name: &str, digest: &str) -> Result<Option<u64>> {
+        let res = match self.head_blob(name, digest, false).await? {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+        // HEAD responses have no body, read the announced length from the header.
+        let size = res
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .ok_or_else(|| Error::from("blob HEAD: missing Content-Length"))?
+            .to_str()?
+            .parse()
+            .chain_err(|| "blob HEAD: invalid Content-Length")?;
+        Ok(Some(size))
+    }
+
+    /// Send a `HEAD` request for a blob, returning `None` if it does not exist.
+    ///
+    /// With `primary_only`, mirrors are skipped, as needed when pushing.
+    async fn head_blob(
+        &self,
+        name: &str,
+        digest: &str,
+        primary_only: bool,
+    ) -> Result<Option<reqwest::Response>> {
         let url = {
-            let ep = format!("{}/v2/{}/blobs/{}", self.base_url, name, digest);
+            validate_name(name)?;
+            let ep = format!(
+                "{}{}/{}/blobs/{}",
+                self.base_url, self.api_base_path, name, digest
+            );
             match reqwest::Url::parse(&ep) {
                 Ok(url) => url,
                 Err(e) => {
@@ -19,68 +62,347 @@ impl Client {
             }
         };
 
-        let res = self.build_reqwest(Method::HEAD, url.clone()).send().await?;
+        self.refresh_auth_if_needed().await?;
+        let build = || self.build_reqwest(Method::HEAD, url.clone());
+        let res = if primary_only {
+            self.send_to_primary_with_escalation(build).await?
+        } else {
+            self.send_with_escalation(build).await?
+        };
 
         trace!("Blob HEAD status: {:?}", res.status());
 
         match res.status() {
-            StatusCode::OK => Ok(true),
-            _ => Ok(false),
+            StatusCode::OK => Ok(Some(res)),
+            StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(response_error(res, "HEAD").await),
+        }
+    }
+
+    /// Retrieve the bytes `start` to `end`, inclusive, of a blob.
+    ///
+    /// This is meant to resume interrupted downloads. The partial content
+    /// cannot be checked against the blob digest. Registries which do not
+    /// support range requests fail with `ErrorKind::Unsupported`.
+    pub async fn get_blob_range(
+        &self,
+        name: &str,
+        digest: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<u8>> {
+        let span = self.span("get_blob_range", name, digest);
+        async move {
+            ensure!(
+                start <= end,
+                "blob range: start {} is after end {}",
+                start,
+                end
+            );
+            validate_name(name)?;
+            let ep = format!(
+                "{}{}/{}/blobs/{}",
+                self.base_url, self.api_base_path, name, digest
+            );
+            let url = reqwest::Url::parse(&ep)
+                .map_err(|e| Error::from(format!("failed to parse url from string: {}", e)))?;
+            let range = format!("bytes={}-{}", start, end);
+
+            self.refresh_auth_if_needed().await?;
+            let res = self
+                .send_with_escalation(|| {
+                    self.build_reqwest(Method::GET, url.clone())
+                        .header(reqwest::header::RANGE, range.as_str())
+                })
+                .await?;
+
+            trace!("GET {} ({}) status: {}", res.url(), range, res.status());
+            match res.status() {
+                StatusCode::PARTIAL_CONTENT => {}
+                StatusCode::OK => bail!(ErrorKind::Unsupported("blob range request".to_string())),
+                _ => return Err(fetch_error(res, "GET", format!("{}@{}", name, digest)).await),
+            }
+
+            let content_range = res
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .ok_or_else(|| Error::from("blob range: missing Content-Range"))?
+                .to_str()?
+                .to_string();
+            let (range_start, range_end) = parse_content_range(&content_range)?;
+            // The range is truncated when it extends past the end of the blob.
+            ensure!(
+                range_start == start && range_end <= end,
+                "blob range: requested {}, got Content-Range '{}'",
+                range,
+                content_range
+            );
+
+            let body = res.bytes().await?;
+            ensure!(
+                body.len() as u64 == range_end - range_start + 1,
+                "blob range: got {} bytes for Content-Range '{}'",
+                body.len(),
+                content_range
+            );
+            self.record_download(body.len() as u64);
+            Ok(body.to_vec())
         }
+        .instrument(span)
+        .await
     }
 
     /// Retrieve blob.
     pub async fn get_blob(&self, name: &str, digest: &str) -> Result<Vec<u8>> {
-        let digest = ContentDigest::try_new(digest.to_string())?;
+        let span = self.span("get_blob", name, digest);
+        async move {
+            let digest = ContentDigest::try_new(digest.to_string())?;
+            let mut verifier = digest.verifier();
+
+            let blob = {
+                validate_name(name)?;
+                let ep = format!(
+                    "{}{}/{}/blobs/{}",
+                    self.base_url, self.api_base_path, name, digest
+                );
+                let url = reqwest::Url::parse(&ep)
+                    .map_err(|e| Error::from(format!("failed to parse url from string: {}", e)))?;
+
+                self.refresh_auth_if_needed().await?;
+                let mut res = self
+                    .send_with_escalation(|| self.build_reqwest(Method::GET, url.clone()))
+                    .await?;
+
+                trace!("GET {} status: {}", res.url(), res.status());
+                if !res.status().is_success() {
+                    return Err(fetch_error(res, "GET", format!("{}@{}", name, digest)).await);
+                }
+
+                let mut body_vec = Vec::new();
+                while let Some(chunk) = res.chunk().await? {
+                    verifier.update(&chunk);
+                    body_vec.extend_from_slice(&chunk);
+                }
+                trace!("Successfully received blob with {} bytes ", body_vec.len());
+                body_vec
+            };
+
+            if self.verify_digests {
+                verifier.verify()?;
+            }
+            self.record_download(blob.len() as u64);
+            Ok(blob)
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Retrieve several blobs, with up to `concurrency` downloads in flight.
+    ///
+    /// Blobs are yielded with their digest in the order of `digests`, so that
+    /// they can be handled as they arrive rather than held in memory. The
+    /// stream ends after the first failed download, unless `continue_on_error`
+    /// is set, in which case each download result is yielded.
+    pub fn pull_layers<'a, D: AsRef<str>>(
+        &'a self,
+        name: &'a str,
+        digests: &'a [D],
+        concurrency: usize,
+        continue_on_error: bool,
+    ) -> impl Stream<Item = Result<(String, Vec<u8>)>> + 'a {
+        stream::iter(digests)
+            .map(move |digest| async move {
+                let digest = digest.as_ref();
+                let blob = self.get_blob(name, digest).await?;
+                Ok((digest.to_string(), blob))
+            })
+            .buffered(concurrency.max(1))
+            .scan(false, move |failed, result: Result<_>| {
+                if *failed {
+                    return future::ready(None);
+                }
+                *failed = result.is_err() && !continue_on_error;
+                future::ready(Some(result))
+            })
+    }
 
-        let blob = {
-            let ep = format!("{}/v2/{}/blobs/{}", self.base_url, name, digest);
+    /// Stream a blob into a writer, without buffering it in memory.
+    ///
+    /// Returns the number of bytes written and the digest of the written content.
+    pub async fn blob_to_writer<W>(
+        &self,
+        name: &str,
+        digest: &str,
+        writer: &mut W,
+    ) -> Result<(u64, String)>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.blob_to_writer_with_progress(name, digest, writer, |_, _| {})
+            .await
+    }
+
+    /// Stream a blob into a writer, reporting the download progress.
+    ///
+    /// `progress` is called after each received chunk with the number of bytes
+    /// written so far and the blob size, if the registry sent a `Content-Length`.
+    pub async fn blob_to_writer_with_progress<W, F>(
+        &self,
+        name: &str,
+        digest: &str,
+        writer: &mut W,
+        mut progress: F,
+    ) -> Result<(u64, String)>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(u64, Option<u64>),
+    {
+        let span = self.span("get_blob", name, digest);
+        async move {
+            let digest = ContentDigest::try_new(digest.to_string())?;
+            let mut verifier = digest.verifier();
+
+            validate_name(name)?;
+            let ep = format!(
+                "{}{}/{}/blobs/{}",
+                self.base_url, self.api_base_path, name, digest
+            );
             let url = reqwest::Url::parse(&ep)
                 .map_err(|e| Error::from(format!("failed to parse url from string: {}", e)))?;
 
-            let res = self.build_reqwest(Method::GET, url.clone()).send().await?;
+            self.refresh_auth_if_needed().await?;
+            let mut res = self
+                .send_with_escalation(|| self.build_reqwest(Method::GET, url.clone()))
+                .await?;
 
             trace!("GET {} status: {}", res.url(), res.status());
-            let status = res.status();
-
-            if !(status.is_success()
-                // Let client errors through to populate them with the body
-                || status.is_client_error())
-            {
-                return Err(Error::from(format!(
-                    "GET request failed with status '{}'",
-                    status
-                )));
+            if !res.status().is_success() {
+                return Err(fetch_error(res, "GET", format!("{}@{}", name, digest)).await);
             }
 
-            let status = res.status();
-            let body_vec = res.bytes().await?.to_vec();
-            let len = body_vec.len();
-
-            if status.is_success() {
-                trace!("Successfully received blob with {} bytes ", len);
-                Ok(body_vec)
-            } else if status.is_client_error() {
-                Err(Error::from(format!(
-                    "GET request failed with status '{}' and body of size {}: {:#?}",
-                    status,
-                    len,
-                    String::from_utf8_lossy(&body_vec)
-                )))
-            } else {
-                // We only want to handle success and client errors here
-                error!(
-                    "Received unexpected HTTP status '{}' after fetching the body. Please submit a bug report.",
-                    status
-                );
-                Err(Error::from(format!(
-                    "GET request failed with status '{}'",
-                    status
-                )))
+            let total = res.content_length();
+            let mut written = 0u64;
+            while let Some(chunk) = res.chunk().await? {
+                verifier.update(&chunk);
+                writer.write_all(&chunk).await?;
+                written += chunk.len() as u64;
+                progress(written, total);
+            }
+            writer.flush().await?;
+            trace!("Successfully streamed blob with {} bytes", written);
+
+            let actual = verifier.current_digest();
+            if self.verify_digests {
+                verifier.verify()?;
+            }
+            self.record_download(written);
+            Ok((written, actual.to_string()))
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Start downloading a blob, returning its content chunks as they arrive.
+    ///
+    /// The content is not checked against the digest.
+    pub(crate) async fn blob_chunks(
+        &self,
+        name: &str,
+        digest: &ContentDigest,
+    ) -> Result<BoxStream<'static, Result<Bytes>>> {
+        validate_name(name)?;
+        let ep = format!(
+            "{}{}/{}/blobs/{}",
+            self.base_url, self.api_base_path, name, digest
+        );
+        let url = reqwest::Url::parse(&ep)
+            .map_err(|e| Error::from(format!("failed to parse url from string: {}", e)))?;
+
+        self.refresh_auth_if_needed().await?;
+        let res = self
+            .send_with_escalation(|| self.build_reqwest(Method::GET, url.clone()))
+            .await?;
+
+        trace!("GET {} status: {}", res.url(), res.status());
+        if !res.status().is_success() {
+            return Err(fetch_error(res, "GET", format!("{}@{}", name, digest)).await);
+        }
+
+        Ok(stream::unfold(res, |mut res| async {
+            match res.chunk().await {
+                Ok(Some(chunk)) => Some((Ok(chunk), res)),
+                Ok(None) => None,
+                Err(e) => Some((Err(e.into()), res)),
             }
-        }?;
+        })
+        .boxed())
+    }
+
+    /// Delete a blob.
+    ///
+    /// A 404 is reported as `ErrorKind::NotFound`, and a 405 (deletion
+    /// disabled on the registry) as `ErrorKind::Unsupported`. Other
+    /// failures carry the registry errors from the response body, if any.
+    pub async fn delete_blob(&self, name: &str, digest: &str) -> Result<()> {
+        let digest = ContentDigest::try_new(digest.to_string())?;
+
+        validate_name(name)?;
+        let ep = format!(
+            "{}{}/{}/blobs/{}",
+            self.base_url, self.api_base_path, name, digest
+        );
+        let url = reqwest::Url::parse(&ep)
+            .map_err(|e| Error::from(format!("failed to parse url from string: {}", e)))?;
+
+        self.refresh_auth_if_needed().await?;
+        let res = self
+            .send_with_escalation(|| self.build_reqwest(Method::DELETE, url.clone()))
+            .await?;
+
+        trace!("DELETE {} status: {}", res.url(), res.status());
+        match res.status() {
+            StatusCode::ACCEPTED => Ok(()),
+            StatusCode::NOT_FOUND => {
+                Err(fetch_error(res, "DELETE", format!("{}@{}", name, digest)).await)
+            }
+            StatusCode::METHOD_NOT_ALLOWED => {
+                Err(ErrorKind::Unsupported("delete blob".to_string()).into())
+            }
+            _ => Err(response_error(res, "DELETE").await),
+        }
+    }
+}
+
+/// Parse the inclusive bounds of a `Content-Range` header, e.g. `bytes 0-1023/4096`.
+fn parse_content_range(content_range: &str) -> Result<(u64, u64)> {
+    let malformed = || Error::from(format!("malformed Content-Range '{}'", content_range));
+    let range = content_range
+        .trim()
+        .strip_prefix("bytes ")
+        .ok_or_else(malformed)?;
+    let bounds = range.split('/').next().ok_or_else(malformed)?;
+    let mut bounds = bounds.splitn(2, '-');
+    match (bounds.next(), bounds.next()) {
+        (Some(start), Some(end)) => {
+            let start = start.parse::<u64>().map_err(|_| malformed())?;
+            let end = end.parse::<u64>().map_err(|_| malformed())?;
+            ensure!(start <= end, malformed());
+            Ok((start, end))
+        }
+        _ => Err(malformed()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        digest.try_verify(&blob)?;
-        Ok(blob.to_vec())
+    #[test]
+    fn content_range_parses() {
+        assert_eq!(parse_content_range("bytes 0-1023/4096").unwrap(), (0, 1023));
+        assert_eq!(parse_content_range("bytes 10-19/*").unwrap(), (10, 19));
+        assert!(parse_content_range("bytes */4096").is_err());
+        assert!(parse_content_range("bytes 20-10/4096").is_err());
+        assert!(parse_content_range("0-1023/4096").is_err());
     }
 }
@@ -1,9 +1,9 @@
 use crate::errors::{Result, ResultExt};
 use crate::v2;
 use async_stream::try_stream;
-use futures::stream::Stream;
+use futures::stream::{Stream, StreamExt, TryStreamExt};
 use futures::{self};
-use reqwest::{Method, RequestBuilder, StatusCode};
+use reqwest::{Method, StatusCode, Url};
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 struct Catalog {
@@ -11,43 +11,91 @@ struct Catalog {
 }
 
 impl v2::Client {
+    /// List repositories in the registry.
+    ///
+    /// Pages of `paginate` entries are requested, or of the configured
+    /// `Config::page_size` if none is given. Paginated results are followed
+    /// through the `Link` header until all repositories have been returned.
     pub fn get_catalog<'a, 'b: 'a>(
         &'b self,
         paginate: Option<u32>,
     ) -> impl Stream<Item = Result<String>> + 'a {
-        let url = {
-            let suffix = if let Some(n) = paginate {
-                format!("?n={}", n)
-            } else {
-                "".to_string()
-            };
-            let ep = format!("{}/v2/_catalog{}", self.base_url.clone(), suffix);
-
-            reqwest::Url::parse(&ep)
-                .chain_err(|| format!("failed to parse url from string '{}'", ep))
-        };
+        let base_url = format!("{}{}/_catalog", self.base_url, self.api_base_path);
+        let paginate = paginate.or(self.page_size);
+        let mut link: Option<String> = None;
 
         try_stream! {
-            let req = self.build_reqwest(Method::GET, url?);
+            loop {
+                let url_paginated = match (paginate, &link) {
+                    (_, Some(query)) => format!("{}?{}", base_url, query),
+                    (Some(n), None) => format!("{}?n={}", base_url, n),
+                    (None, None) => base_url.clone(),
+                };
+                let url = Url::parse(&url_paginated)
+                    .chain_err(|| format!("failed to parse url from string '{}'", url_paginated))?;
 
-            let catalog = fetch_catalog(req).await?;
+                let (catalog, next) = self.fetch_catalog(url).await?;
+                for repo in catalog.repositories {
+                    yield repo;
+                }
 
-            for repo in catalog.repositories {
-                yield repo;
+                link = match next {
+                    Some(next) => Some(next),
+                    None => break,
+                };
             }
         }
     }
-}
 
-async fn fetch_catalog(req: RequestBuilder) -> Result<Catalog> {
-    let r = req.send().await?;
-    let status = r.status();
-    trace!("Got status: {:?}", status);
-    match status {
-        StatusCode::OK => r
-            .json::<Catalog>()
-            .await
-            .chain_err(|| "get_catalog: failed to fetch the whole body"),
-        _ => bail!("get_catalog: wrong HTTP status '{}'", status),
+    /// List repositories in the registry, requesting pages of the given size.
+    ///
+    /// Registries may cap the page size, the pages they return are followed as-is.
+    pub fn catalog_stream<'a, 'b: 'a>(
+        &'b self,
+        page_size: u32,
+    ) -> impl Stream<Item = Result<String>> + 'a {
+        self.get_catalog(Some(page_size))
+    }
+
+    /// List all repositories in the registry, along with their tags.
+    ///
+    /// The catalog is walked page by page, while the tags of up to `concurrency`
+    /// repositories are fetched at once. Repositories are returned in catalog order.
+    ///
+    /// This sends at least one request per repository, and can take a long time
+    /// on large registries.
+    pub fn enumerate<'a, 'b: 'a>(
+        &'b self,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<(String, Vec<String>)>> + 'a {
+        self.get_catalog(None)
+            .map(move |repo| async move {
+                let repo = repo?;
+                let tags = self.get_tags(&repo, None).try_collect().await?;
+                Ok((repo, tags))
+            })
+            .buffered(concurrency.max(1))
+    }
+
+    async fn fetch_catalog(&self, url: Url) -> Result<(Catalog, Option<String>)> {
+        self.refresh_auth_if_needed().await?;
+        let r = self
+            .send_with_escalation(|| self.build_reqwest(Method::GET, url.clone()))
+            .await?;
+        let status = r.status();
+        trace!("Got status: {:?}", status);
+        match status {
+            StatusCode::OK => {
+                let next = v2::parse_link(r.headers().get(reqwest::header::LINK));
+                trace!("next_page {:?}", next);
+
+                let catalog = r
+                    .json::<Catalog>()
+                    .await
+                    .chain_err(|| "get_catalog: failed to fetch the whole body")?;
+                Ok((catalog, next))
+            }
+            _ => Err(v2::response_error(r, "get_catalog: GET").await),
+        }
     }
 }
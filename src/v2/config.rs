@@ -1,14 +1,66 @@
+use crate::mediatypes::MediaTypes;
+use crate::reference::Reference;
+use crate::v2::warnings::WarningHandler;
 use crate::v2::*;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Largest page size requested by `Config::page_size`.
+const MAX_PAGE_SIZE: u32 = 10_000;
+
+/// Default for `Config::max_manifest_size`, the size registries must accept.
+const DEFAULT_MAX_MANIFEST_SIZE: usize = 4 * 1024 * 1024;
+
+/// Most redirects followed per request, e.g. from a blob to its storage URL.
+const DEFAULT_MAX_REDIRECTS: usize = 10;
 
 /// Configuration for a `Client`.
 #[derive(Debug)]
 pub struct Config {
     index: String,
+    docker_hub_compat: bool,
+    api_base_path: String,
+    mirrors: Vec<String>,
     insecure_registry: bool,
     user_agent: Option<String>,
+    extra_headers: HeaderMap,
     username: Option<String>,
     password: Option<String>,
     accept_invalid_certs: bool,
+    token_refresh_skew: Duration,
+    oauth2_post: bool,
+    token_client_id: Option<String>,
+    token_cache: bool,
+    auto_escalate_scopes: bool,
+    verify_digests: bool,
+    page_size: Option<u32>,
+    max_manifest_size: usize,
+    accept_media_types: Option<Vec<MediaTypes>>,
+    retry_policy: RetryPolicy,
+    request_timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    http2_prior_knowledge: bool,
+    http1_only: bool,
+    tcp_nodelay: bool,
+    local_address: Option<IpAddr>,
+    follow_redirects: bool,
+    max_redirects: usize,
+    proxies: Vec<Proxy>,
+    no_proxy: Vec<String>,
+    proxy_from_env: bool,
+    #[cfg(any(feature = "reqwest-default-tls", feature = "reqwest-rustls"))]
+    root_certificates: Vec<Certificate>,
+    #[cfg(any(feature = "reqwest-default-tls", feature = "reqwest-rustls"))]
+    identity: Option<Identity>,
+    reqwest_client: Option<reqwest::Client>,
+    #[cfg(feature = "ecr")]
+    ecr: bool,
+    #[cfg(feature = "metrics")]
+    metrics: std::sync::Arc<dyn crate::v2::metrics::Metrics>,
+    warning_handler: Option<WarningHandler>,
 }
 
 impl Config {
@@ -16,38 +68,154 @@ impl Config {
     pub fn default() -> Self {
         Self {
             index: "registry-1.docker.io".into(),
+            docker_hub_compat: true,
+            api_base_path: "/v2/".into(),
+            mirrors: vec![],
             insecure_registry: false,
             accept_invalid_certs: false,
             user_agent: Some(crate::USER_AGENT.to_owned()),
+            extra_headers: HeaderMap::new(),
             username: None,
             password: None,
+            token_refresh_skew: Duration::from_secs(30),
+            oauth2_post: false,
+            token_client_id: None,
+            token_cache: true,
+            auto_escalate_scopes: true,
+            verify_digests: true,
+            page_size: None,
+            max_manifest_size: DEFAULT_MAX_MANIFEST_SIZE,
+            accept_media_types: None,
+            retry_policy: RetryPolicy::default(),
+            request_timeout: None,
+            connect_timeout: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            http2_prior_knowledge: false,
+            http1_only: false,
+            tcp_nodelay: true,
+            local_address: None,
+            follow_redirects: true,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            proxies: vec![],
+            no_proxy: vec![],
+            proxy_from_env: true,
+            #[cfg(any(feature = "reqwest-default-tls", feature = "reqwest-rustls"))]
+            root_certificates: vec![],
+            #[cfg(any(feature = "reqwest-default-tls", feature = "reqwest-rustls"))]
+            identity: None,
+            reqwest_client: None,
+            #[cfg(feature = "ecr")]
+            ecr: false,
+            #[cfg(feature = "metrics")]
+            metrics: std::sync::Arc::new(crate::v2::metrics::NoopMetrics),
+            warning_handler: None,
         }
     }
 
     /// Set registry service to use (vhost or IP).
+    ///
+    /// An `http://` or `https://` scheme prefix also sets whether the registry is insecure.
     pub fn registry(mut self, reg: &str) -> Self {
-        self.index = reg.to_owned();
+        let reg = reg.trim_end_matches('/');
+        self.index = if let Some(host) = reg.strip_prefix("http://") {
+            self.insecure_registry = true;
+            host.to_owned()
+        } else if let Some(host) = reg.strip_prefix("https://") {
+            self.insecure_registry = false;
+            host.to_owned()
+        } else {
+            reg.to_owned()
+        };
+        self
+    }
+
+    /// Set whether Docker Hub aliases point to its API host, `true` by default.
+    ///
+    /// When enabled, the `docker.io` and `index.docker.io` registries are
+    /// accessed at `registry-1.docker.io`. Its token service is discovered
+    /// from the authentication challenge as usual.
+    pub fn docker_hub_compat(mut self, docker_hub_compat: bool) -> Self {
+        self.docker_hub_compat = docker_hub_compat;
+        self
+    }
+
+    /// Initialize `Config` for the registry of an image reference, e.g. `quay.io/foo/bar:v1`.
+    ///
+    /// References without a registry host point to Docker Hub. Registries are
+    /// accessed over HTTPS, unless `insecure_registry` is set on the returned
+    /// config. The parsed reference is returned along, to get the repository
+    /// and version from.
+    pub fn from_reference(reference: &str) -> Result<(Self, Reference)> {
+        let reference = Reference::parse(reference)?;
+        let config = Self::default().registry(&reference.registry());
+
+        Ok((config, reference))
+    }
+
+    /// Set the path the distribution API is served under, `/v2/` by default.
+    ///
+    /// This is meant for registries behind a gateway, e.g. `/registry/v2/`.
+    pub fn api_base_path(mut self, api_base_path: String) -> Self {
+        self.api_base_path = api_base_path;
+        self
+    }
+
+    /// Set mirrors of the registry, tried in order before it for reads.
+    ///
+    /// Manifest, blob and tag `GET` and `HEAD` requests are sent to each mirror
+    /// in turn, falling through to the next one on connection errors, `5xx`,
+    /// `401`, `403`, `404` and `429` responses, and finally to the registry.
+    /// Mirrors are accessed anonymously, without extra headers. Uploads, and
+    /// the `has_blob` checks of what to upload, always go to the registry.
+    /// Mirrors without an `http://` or `https://` scheme use the same scheme
+    /// as the registry.
+    pub fn mirrors(mut self, mirrors: Vec<String>) -> Self {
+        self.mirrors = mirrors;
         self
     }
 
     /// Whether to use an insecure HTTP connection to the registry.
+    ///
+    /// Credentials and tokens are then sent in clear text and responses are not
+    /// authenticated, so this is only meant for local or test registries.
+    /// Plain-HTTP token endpoints are only accepted for insecure registries.
     pub fn insecure_registry(mut self, insecure: bool) -> Self {
         self.insecure_registry = insecure;
         self
     }
 
     /// Set whether or not to accept invalid certificates.
+    ///
+    /// This is meant for development only, as any server can then impersonate the registry.
     pub fn accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
         self.accept_invalid_certs = accept_invalid_certs;
         self
     }
 
-    /// Set the user-agent to be used for registry authentication.
+    /// Set the user-agent sent with every request, including token requests.
+    ///
+    /// This defaults to `dkregistry-rs/<version>`, `None` sends no user-agent.
     pub fn user_agent(mut self, user_agent: Option<String>) -> Self {
         self.user_agent = user_agent;
         self
     }
 
+    /// Add a header sent with every request, e.g. a tenant ID or an API gateway key.
+    ///
+    /// Extra headers are only sent to the registry URL, not to token endpoints
+    /// or mirrors on other hosts. The same header can be added multiple times.
+    /// `Authorization` headers are ignored, as they are managed by the
+    /// authentication flow.
+    pub fn extra_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        if name == reqwest::header::AUTHORIZATION {
+            warn!("ignoring extra Authorization header");
+        } else {
+            self.extra_headers.append(name, value);
+        }
+        self
+    }
+
     /// Set the username to be used for registry authentication.
     pub fn username(mut self, user: Option<String>) -> Self {
         self.username = user;
@@ -60,6 +228,285 @@ impl Config {
         self
     }
 
+    /// Set basic credentials whose password is a token.
+    ///
+    /// This covers registries which take a token as the password of a
+    /// fixed or account username, e.g. a GitHub personal access token for
+    /// `ghcr.io`, or `AWS` and an ECR authorization token. An empty token
+    /// authenticates with the username only.
+    pub fn basic_token(self, username: &str, token: &str) -> Self {
+        self.username(Some(username.to_string()))
+            .password(Some(token.to_string()))
+    }
+
+    /// Set how long before its expiry a bearer token gets renewed.
+    pub fn token_refresh_skew(mut self, skew: Duration) -> Self {
+        self.token_refresh_skew = skew;
+        self
+    }
+
+    /// Whether to request bearer tokens via an OAuth2 `password` grant POST.
+    pub fn oauth2_post(mut self, oauth2_post: bool) -> Self {
+        self.oauth2_post = oauth2_post;
+        self
+    }
+
+    /// Set the `client_id` sent in token requests.
+    ///
+    /// Token requests then also carry the configured username as `account`,
+    /// as some token servers require. OAuth2 requests default to `dkregistry`.
+    pub fn token_client_id(mut self, client_id: String) -> Self {
+        self.token_client_id = Some(client_id);
+        self
+    }
+
+    /// Set whether bearer tokens are cached and reused per scope set.
+    pub fn token_cache(mut self, token_cache: bool) -> Self {
+        self.token_cache = token_cache;
+        self
+    }
+
+    /// Whether to re-authenticate when the registry asks for scopes the token lacks.
+    ///
+    /// A request failing with such a challenge is retried once, with a token
+    /// for the union of the current and the requested scopes.
+    pub fn auto_escalate_scopes(mut self, auto_escalate_scopes: bool) -> Self {
+        self.auto_escalate_scopes = auto_escalate_scopes;
+        self
+    }
+
+    /// Whether to verify downloaded blobs against their digest.
+    pub fn verify_digests(mut self, verify_digests: bool) -> Self {
+        self.verify_digests = verify_digests;
+        self
+    }
+
+    /// Set the page size requested when listing tags and repositories.
+    ///
+    /// This sets the `n` query parameter of listing requests which don't
+    /// specify their own. The value is clamped to `1..=10000`; registries
+    /// may still ignore it or cap it further. By default no page size is sent.
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = Some(page_size.clamp(1, MAX_PAGE_SIZE));
+        self
+    }
+
+    /// Set the largest manifest size, in bytes, read from the registry.
+    ///
+    /// Larger manifests and indexes fail with `ErrorKind::ResponseTooLarge`
+    /// before being fully read. This defaults to 4 MiB.
+    pub fn max_manifest_size(mut self, max_manifest_size: usize) -> Self {
+        self.max_manifest_size = max_manifest_size;
+        self
+    }
+
+    /// Set the media types accepted when fetching manifests, most preferred first.
+    ///
+    /// They are sent in the `Accept` header with decreasing q values, so that
+    /// e.g. OCI indexes can be preferred over Docker manifest lists. By default,
+    /// Docker and OCI image manifests are accepted; `resolve_manifest`,
+    /// `digest_of` and image copies also accept Docker manifest lists and OCI
+    /// indexes.
+    pub fn accept_media_types(mut self, media_types: Vec<MediaTypes>) -> Self {
+        self.accept_media_types = Some(media_types);
+        self
+    }
+
+    /// Set the policy for retrying requests which failed with a transient error.
+    ///
+    /// By default, idempotent requests are attempted up to 3 times.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set a timeout for each request, from connecting until the response body is read.
+    ///
+    /// This applies to token requests too. Requests which time out fail with `ErrorKind::Timeout`.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Set a timeout for connecting to the registry and the token endpoints.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the most idle connections kept open to each host, unlimited by default.
+    ///
+    /// Registry workloads are mostly many small requests (manifests, tags,
+    /// blob checks) along with a few large transfers. Keeping enough idle
+    /// connections for the request concurrency, e.g. of `pull_layers`,
+    /// avoids reconnecting between requests.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Set how long idle connections are kept open, 90 seconds by default.
+    ///
+    /// This should exceed the pauses between requests, e.g. while processing
+    /// each page of a catalog walk, for connections to be reused.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set whether to speak HTTP/2 right away, without negotiating it.
+    ///
+    /// By default HTTP/2 is only used if negotiated during the TLS handshake,
+    /// HTTP/1.1 otherwise. This also allows HTTP/2 to plain-HTTP registries.
+    pub fn http2_prior_knowledge(mut self, http2_prior_knowledge: bool) -> Self {
+        self.http2_prior_knowledge = http2_prior_knowledge;
+        self
+    }
+
+    /// Set whether to only speak HTTP/1.1, overriding `http2_prior_knowledge`.
+    ///
+    /// This works around registries misbehaving over HTTP/2, e.g. resetting
+    /// streams under load. The default TLS backend never negotiates HTTP/2,
+    /// the rustls one does and building a client fails with this option then.
+    pub fn http1_only(mut self, http1_only: bool) -> Self {
+        self.http1_only = http1_only;
+        self
+    }
+
+    /// Set whether to disable Nagle's algorithm on connections, `true` by default.
+    ///
+    /// This sends small requests, e.g. tag listings and blob checks, without
+    /// waiting for more data to coalesce them with.
+    pub fn tcp_nodelay(mut self, tcp_nodelay: bool) -> Self {
+        self.tcp_nodelay = tcp_nodelay;
+        self
+    }
+
+    /// Set the local address connections are made from.
+    ///
+    /// On multi-homed hosts, this pins registry traffic to the network
+    /// interface having this address. By default the system picks it.
+    pub fn local_address(mut self, address: IpAddr) -> Self {
+        self.local_address = Some(address);
+        self
+    }
+
+    /// Set whether to follow redirects, e.g. from a blob to its storage URL.
+    ///
+    /// When disabled, redirects fail with `ErrorKind::Redirect`, carrying
+    /// the redirect location. This defaults to `true`.
+    pub fn follow_redirects(mut self, follow_redirects: bool) -> Self {
+        self.follow_redirects = follow_redirects;
+        self
+    }
+
+    /// Set the most redirects followed per request, 10 by default.
+    ///
+    /// Requests redirected more often fail with `ErrorKind::Redirect`.
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Add a proxy for outgoing requests.
+    ///
+    /// Proxies are tried in the order they are added, and disable the proxies
+    /// from the environment.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxies.push(proxy);
+        self
+    }
+
+    /// Set the hosts reached without going through the configured proxies.
+    ///
+    /// Entries match a host and its subdomains, `*` matches every host.
+    pub fn no_proxy(mut self, hosts: Vec<String>) -> Self {
+        self.no_proxy = hosts;
+        self
+    }
+
+    /// Whether to use the proxies from the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY`
+    /// environment variables, if no proxy is configured.
+    ///
+    /// This is enabled by default.
+    pub fn proxy_from_env(mut self, proxy_from_env: bool) -> Self {
+        self.proxy_from_env = proxy_from_env;
+        self
+    }
+
+    /// Trust an additional root certificate, e.g. a corporate CA.
+    ///
+    /// This applies to the registry and to its token endpoints.
+    #[cfg(any(feature = "reqwest-default-tls", feature = "reqwest-rustls"))]
+    pub fn add_root_certificate(mut self, certificate: Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Set the client certificate presented to registries requiring mutual TLS.
+    #[cfg(any(feature = "reqwest-default-tls", feature = "reqwest-rustls"))]
+    pub fn client_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Use a pre-built `reqwest::Client` for all requests, sharing its connection pool.
+    ///
+    /// Authentication, user-agent and extra headers are still added to each request.
+    /// Options configuring the HTTP client itself are then ignored: certificate
+    /// validation, root certificates and identity, timeouts, connection pool,
+    /// TCP options, redirects and proxies.
+    pub fn reqwest_client(mut self, client: reqwest::Client) -> Self {
+        self.reqwest_client = Some(client);
+        self
+    }
+
+    /// Whether to obtain credentials from AWS ECR when authenticating.
+    ///
+    /// Credentials are fetched for the configured registry via `GetAuthorizationToken`,
+    /// using the standard AWS credential providers (environment, profile, web identity,
+    /// container and instance metadata), and renewed before they expire.
+    #[cfg(feature = "ecr")]
+    pub fn ecr(mut self, ecr: bool) -> Self {
+        self.ecr = ecr;
+        self
+    }
+
+    /// Set the receiver of metrics about requests and blob transfers.
+    ///
+    /// By default, metrics are ignored.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, metrics: std::sync::Arc<dyn crate::v2::metrics::Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Set a callback receiving the `Warning` headers sent by the registry, e.g. deprecation notices.
+    ///
+    /// Warnings are logged in any case.
+    pub fn warning_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.warning_handler = Some(WarningHandler(std::sync::Arc::new(handler)));
+        self
+    }
+
+    /// Initialize `Config` for a registry, with credentials from the docker-client configuration.
+    ///
+    /// The configuration is read from `$DOCKER_CONFIG/config.json`, or `~/.docker/config.json`.
+    pub fn from_docker_config(registry: &str) -> Result<Self> {
+        let path = crate::credentials::docker_config_path()?;
+        let file = std::fs::File::open(&path)
+            .chain_err(|| format!("failed to open docker config '{}'", path.display()))?;
+        let (username, password) = crate::get_credentials(std::io::BufReader::new(file), registry)?;
+
+        Ok(Self::default()
+            .registry(registry)
+            .username(username)
+            .password(password))
+    }
+
     /// Read credentials from a JSON config file
     pub fn read_credentials<T: ::std::io::Read>(mut self, reader: T) -> Self {
         if let Ok(creds) = crate::get_credentials(reader, &self.index) {
@@ -70,7 +517,10 @@ impl Config {
     }
 
     /// Return a `Client` to interact with a v2 registry.
-    pub fn build(self) -> Result<Client> {
+    pub fn build(mut self) -> Result<Client> {
+        if self.docker_hub_compat && is_docker_hub_alias(&self.index) {
+            self.index = crate::reference::DEFAULT_REGISTRY.to_string();
+        }
         let base = if self.insecure_registry {
             "http://".to_string() + &self.index
         } else {
@@ -82,6 +532,25 @@ impl Config {
             base,
             self.username
         );
+        let api_base_path = match self.api_base_path.trim_matches('/') {
+            "" => String::new(),
+            path => format!("/{}", path),
+        };
+        let mirrors = self
+            .mirrors
+            .iter()
+            .map(|mirror| {
+                let mirror = mirror.trim_end_matches('/');
+                let url = if mirror.starts_with("http://") || mirror.starts_with("https://") {
+                    mirror.to_string()
+                } else if self.insecure_registry {
+                    format!("http://{}", mirror)
+                } else {
+                    format!("https://{}", mirror)
+                };
+                Url::parse(&url).chain_err(|| format!("failed to parse mirror '{}'", mirror))
+            })
+            .collect::<Result<Vec<_>>>()?;
         let creds = match (self.username, self.password) {
             (None, None) => None,
             (u, p) => Some((
@@ -89,18 +558,139 @@ impl Config {
                 p.unwrap_or_else(|| "".into()),
             )),
         };
-        let client = reqwest::ClientBuilder::new()
-            .danger_accept_invalid_certs(self.accept_invalid_certs)
-            .build()?;
+        let client = if let Some(client) = self.reqwest_client {
+            client
+        } else {
+            // Blobs are often redirected to pre-signed storage URLs, which reject
+            // registry credentials. reqwest drops the `Authorization` header
+            // when a redirect leaves the registry host or port.
+            let redirect = if self.follow_redirects {
+                reqwest::redirect::Policy::limited(self.max_redirects)
+            } else {
+                reqwest::redirect::Policy::none()
+            };
+            let mut builder = reqwest::ClientBuilder::new()
+                .danger_accept_invalid_certs(self.accept_invalid_certs)
+                .redirect(redirect);
+            if let Some(ua) = &self.user_agent {
+                builder = builder.user_agent(ua.as_str());
+            }
+            if let Some(timeout) = self.request_timeout {
+                builder = builder.timeout(timeout);
+            }
+            if let Some(timeout) = self.connect_timeout {
+                builder = builder.connect_timeout(timeout);
+            }
+            if let Some(max) = self.pool_max_idle_per_host {
+                builder = builder.pool_max_idle_per_host(max);
+            }
+            if let Some(timeout) = self.pool_idle_timeout {
+                builder = builder.pool_idle_timeout(timeout);
+            }
+            // rustls offers HTTP/2 during the handshake, which reqwest cannot disable.
+            #[cfg(all(feature = "reqwest-rustls", not(feature = "reqwest-default-tls")))]
+            ensure!(
+                !self.http1_only,
+                "HTTP/1.1-only clients are not supported with the rustls backend"
+            );
+            builder = builder
+                .tcp_nodelay_(self.tcp_nodelay)
+                .local_address(self.local_address);
+            if self.http2_prior_knowledge && !self.http1_only {
+                builder = builder.http2_prior_knowledge();
+            }
+            #[cfg(any(feature = "reqwest-default-tls", feature = "reqwest-rustls"))]
+            {
+                for certificate in self.root_certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+                if let Some(identity) = self.identity {
+                    builder = builder.identity(identity);
+                }
+            }
+            if !self.proxy_from_env {
+                builder = builder.no_proxy();
+            }
+            let no_proxy = std::sync::Arc::new(self.no_proxy);
+            for proxy in self.proxies {
+                builder = builder.proxy(proxy.into_reqwest(no_proxy.clone()));
+            }
+            builder.build()?
+        };
 
         let c = Client {
             base_url: base,
+            api_base_path,
             credentials: creds,
             index: self.index,
+            mirrors,
             user_agent: self.user_agent,
-            auth: None,
+            extra_headers: self.extra_headers,
+            auth: Default::default(),
+            token_refresh_skew: self.token_refresh_skew,
+            oauth2_post: self.oauth2_post,
+            token_client_id: self.token_client_id,
+            token_cache: if self.token_cache {
+                Some(Default::default())
+            } else {
+                None
+            },
+            auto_escalate_scopes: self.auto_escalate_scopes,
+            last_auth_challenge: None,
+            verify_digests: self.verify_digests,
+            page_size: self.page_size,
+            max_manifest_size: self.max_manifest_size,
+            accept_media_types: self.accept_media_types,
+            retry_policy: self.retry_policy,
+            #[cfg(feature = "ecr")]
+            ecr: self.ecr,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+            warning_handler: self.warning_handler,
+            rate_limit: Default::default(),
             client: client,
         };
         Ok(c)
     }
 }
+
+/// Check whether a registry is an alias of the Docker Hub API host.
+fn is_docker_hub_alias(registry: &str) -> bool {
+    registry.eq_ignore_ascii_case("docker.io") || registry.eq_ignore_ascii_case("index.docker.io")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn docker_hub_aliases_are_rewritten() -> Result<()> {
+        for registry in &["docker.io", "index.docker.io", "https://docker.io/"] {
+            let client = Config::default().registry(registry).build()?;
+            assert_eq!(client.base_url, "https://registry-1.docker.io");
+        }
+
+        let client = Config::default()
+            .registry("docker.io")
+            .docker_hub_compat(false)
+            .build()?;
+        assert_eq!(client.base_url, "https://docker.io");
+
+        let client = Config::default().registry("quay.io").build()?;
+        assert_eq!(client.base_url, "https://quay.io");
+
+        Ok(())
+    }
+
+    #[test]
+    fn references_default_to_https() -> Result<()> {
+        let (config, _) = Config::from_reference("localhost:5000/foo/bar:v1")?;
+        assert_eq!(config.build()?.base_url, "https://localhost:5000");
+
+        let (config, _) = Config::from_reference("localhost:5000/foo/bar:v1")?;
+        let client = config.insecure_registry(true).build()?;
+        assert_eq!(client.base_url, "http://localhost:5000");
+
+        Ok(())
+    }
+}
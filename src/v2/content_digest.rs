@@ -1,4 +1,4 @@
-use crate::errors::Result;
+use crate::errors::{ErrorKind, Result};
 /// Implements types and methods for content verification
 use sha2::{self, Digest};
 
@@ -14,6 +14,15 @@ pub(crate) struct ContentDigest {
 enum DigestAlgorithm {
     #[strum(to_string = "sha256")]
     Sha256,
+    #[strum(to_string = "sha512")]
+    Sha512,
+}
+
+/// Hasher holds the running hash state of a DigestAlgorithm
+#[derive(Clone, Debug)]
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
 }
 
 impl ContentDigest {
@@ -21,7 +30,8 @@ impl ContentDigest {
     ///
     /// Success depends on
     /// - the string having a "algorithm:" prefix
-    /// - the algorithm being supported by DigestAlgorithm
+    /// - the algorithm being supported by DigestAlgorithm, or else
+    ///   `ErrorKind::UnsupportedDigestAlgorithm` is returned
     pub fn try_new(digest: String) -> Result<Self> {
         let digest_split = digest.split(':').collect::<Vec<&str>>();
 
@@ -29,8 +39,8 @@ impl ContentDigest {
             return Err(format!("digest '{}' does not have an algorithm prefix", digest).into());
         }
 
-        let algorithm =
-            std::str::FromStr::from_str(digest_split[0]).map_err(|e| format!("{}", e))?;
+        let algorithm = std::str::FromStr::from_str(digest_split[0])
+            .map_err(|_| ErrorKind::UnsupportedDigestAlgorithm(digest_split[0].to_string()))?;
         Ok(ContentDigest {
             digest: digest_split[1].to_string(),
             algorithm,
@@ -41,19 +51,58 @@ impl ContentDigest {
     ///
     /// Success depends on the result of the comparison
     pub fn try_verify(&self, input: &[u8]) -> Result<()> {
-        let hash = self.algorithm.hash(input);
-        let layer_digest = Self::try_new(hash)?;
-
-        if self != &layer_digest {
-            return Err(format!(
-                "content verification failed. expected '{}', got '{}'",
-                self.to_owned(),
-                layer_digest.to_owned()
-            )
-            .into());
+        let mut verifier = self.verifier();
+        verifier.update(input);
+        verifier.verify()
+    }
+
+    /// verifier returns a DigestVerifier to hash content incrementally against this digest
+    pub fn verifier(&self) -> DigestVerifier {
+        DigestVerifier {
+            expected: self.clone(),
+            hasher: self.algorithm.hasher(),
+        }
+    }
+}
+
+/// DigestVerifier hashes content chunk by chunk and compares it with an expected digest
+#[derive(Clone, Debug)]
+pub(crate) struct DigestVerifier {
+    expected: ContentDigest,
+    hasher: Hasher,
+}
+
+impl DigestVerifier {
+    /// update hashes the next chunk of content
+    pub fn update(&mut self, chunk: &[u8]) {
+        match &mut self.hasher {
+            Hasher::Sha256(hasher) => hasher.update(chunk),
+            Hasher::Sha512(hasher) => hasher.update(chunk),
         }
+    }
+
+    /// current_digest returns the digest of all content so far
+    pub fn current_digest(&self) -> ContentDigest {
+        ContentDigest {
+            digest: match self.hasher.clone() {
+                Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+                Hasher::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+            },
+            algorithm: self.expected.algorithm.clone(),
+        }
+    }
+
+    /// verify compares the hash of all content so far with the expected digest
+    pub fn verify(self) -> Result<()> {
+        let actual = self.current_digest();
 
-        trace!("content verification succeeded for '{}'", &layer_digest);
+        if self.expected != actual {
+            return Err(
+                ErrorKind::DigestMismatch(self.expected.to_string(), actual.to_string()).into(),
+            );
+        }
+
+        trace!("content verification succeeded for '{}'", &actual);
         Ok(())
     }
 }
@@ -65,12 +114,18 @@ impl std::fmt::Display for ContentDigest {
 }
 
 impl DigestAlgorithm {
+    fn hasher(&self) -> Hasher {
+        match self {
+            DigestAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            DigestAlgorithm::Sha512 => Hasher::Sha512(sha2::Sha512::new()),
+        }
+    }
+
+    #[cfg(test)]
     fn hash(&self, input: &[u8]) -> String {
         match self {
-            DigestAlgorithm::Sha256 => {
-                let hash = sha2::Sha256::digest(input);
-                format!("{}:{:x}", self, hash)
-            }
+            DigestAlgorithm::Sha256 => format!("{}:{:x}", self, sha2::Sha256::digest(input)),
+            DigestAlgorithm::Sha512 => format!("{}:{:x}", self, sha2::Sha512::digest(input)),
         }
     }
 }
@@ -82,9 +137,10 @@ mod tests {
 
     #[test]
     fn try_new_succeeds_with_correct_digest() -> Fallible<()> {
-        for correct_digest in
-            &["sha256:0000000000000000000000000000000000000000000000000000000000000000"]
-        {
+        for correct_digest in &[
+            "sha256:0000000000000000000000000000000000000000000000000000000000000000",
+            "sha512:00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+        ] {
             ContentDigest::try_new(correct_digest.to_string())?;
         }
 
@@ -118,6 +174,40 @@ mod tests {
         ContentDigest::try_new(digest)?.try_verify(&blob)
     }
 
+    #[test]
+    fn verifier_succeeds_with_chunked_content() -> Fallible<()> {
+        let digest = DigestAlgorithm::Sha256.hash(b"somecontent");
+
+        let mut verifier = ContentDigest::try_new(digest)?.verifier();
+        verifier.update(b"some");
+        verifier.update(b"content");
+
+        verifier.verify()
+    }
+
+    #[test]
+    fn try_verify_dispatches_on_algorithm() -> Fallible<()> {
+        let blob: &[u8] = b"somecontent";
+        let digest = DigestAlgorithm::Sha512.hash(blob);
+        assert!(digest.starts_with("sha512:"));
+        ContentDigest::try_new(digest.clone())?.try_verify(blob)?;
+
+        let as_sha256 = digest.replacen("sha512", "sha256", 1);
+        assert!(ContentDigest::try_new(as_sha256)?.try_verify(blob).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_new_fails_with_unsupported_algorithm() {
+        let err =
+            ContentDigest::try_new("md5:d41d8cd98f00b204e9800998ecf8427e".to_string()).unwrap_err();
+        match err.kind() {
+            ErrorKind::UnsupportedDigestAlgorithm(algorithm) => assert_eq!(algorithm, "md5"),
+            other => panic!("unexpected error kind: {:?}", other),
+        }
+    }
+
     #[test]
     fn try_verify_fails_with_different_content() -> Fallible<()> {
         let blob: &[u8] = b"somecontent";
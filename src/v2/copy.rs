@@ -0,0 +1,175 @@
+use crate::errors::{ErrorKind, Result};
+use crate::mediatypes::MediaTypes;
+use crate::reference::Reference;
+use crate::v2::manifest::{Descriptor, ManifestList, ManifestSchema2Spec, Platform, RawManifest};
+use crate::v2::push::push_blobs;
+use crate::v2::*;
+use futures::future::{BoxFuture, FutureExt};
+
+/// Maximum nesting of manifest lists followed when copying an image.
+const MAX_LIST_DEPTH: usize = 4;
+
+/// Progress of an image copy, reported after each blob.
+#[derive(Clone, Debug)]
+pub struct CopyProgress {
+    /// Digest of the blob.
+    pub digest: String,
+    /// How the blob was made available in the destination repository.
+    pub transfer: BlobTransfer,
+    /// Number of blobs handled so far, including this one.
+    pub done: usize,
+    /// Number of blobs to handle.
+    pub total: usize,
+}
+
+/// Copy an image from one repository to another, possibly on another registry.
+///
+/// The registries of the references are ignored, images are read through `src`
+/// and written through `dst`. Returns the digest of the manifest pushed to `dst_ref`.
+/// See `copy_image_with_progress` for how the image is copied.
+pub async fn copy_image(
+    src: &Client,
+    dst: &Client,
+    src_ref: &Reference,
+    dst_ref: &Reference,
+    platform: Option<&Platform>,
+) -> Result<String> {
+    copy_image_with_progress(src, dst, src_ref, dst_ref, platform, |_| {}).await
+}
+
+/// Copy an image from one repository to another, reporting the copy progress.
+///
+/// If the source is a manifest list and a platform is given, only the image
+/// for that platform is copied. Otherwise all manifests of the list are
+/// copied, recursing into nested lists, and each list is pushed once the
+/// manifests it references exist. Manifests are copied unchanged, so that
+/// their digests are preserved.
+///
+/// Blobs are only transferred if missing from the destination, so that an
+/// interrupted copy can be resumed by running it again: they are mounted
+/// from the source repository when both are on the same registry, and
+/// streamed from the source registry otherwise. `progress` is called after
+/// each blob.
+pub async fn copy_image_with_progress<F>(
+    src: &Client,
+    dst: &Client,
+    src_ref: &Reference,
+    dst_ref: &Reference,
+    platform: Option<&Platform>,
+    mut progress: F,
+) -> Result<String>
+where
+    F: FnMut(&CopyProgress),
+{
+    let src_name = src_ref.repository();
+    let dst_name = dst_ref.repository();
+
+    let mut top = src
+        .fetch_raw_manifest(&src_name, &src_ref.version(), true)
+        .await?;
+    if let (Some(platform), true) = (platform, top.media_type.is_manifest_list()) {
+        let list: ManifestList = serde_json::from_slice(&top.body)?;
+        let entry = list
+            .manifests
+            .iter()
+            .find(|m| m.platform.matches(platform))
+            .ok_or_else(|| format!("no manifest for platform '{}' in {}", platform, src_ref))?;
+        top = src
+            .fetch_raw_manifest(&src_name, &entry.digest, true)
+            .await?;
+    }
+    let mut children = Vec::new();
+    fetch_children(src, &src_name, &top, 0, &mut children).await?;
+
+    let mut blobs: Vec<Descriptor> = Vec::new();
+    for manifest in children.iter().map(|(_, child)| child).chain(Some(&top)) {
+        for blob in image_blobs(manifest)? {
+            if !blobs.iter().any(|b| b.digest == blob.digest) {
+                blobs.push(blob);
+            }
+        }
+    }
+
+    let from_repos = if src.base_url == dst.base_url && src_name != dst_name {
+        vec![src_name.clone()]
+    } else {
+        vec![]
+    };
+    let total = blobs.len();
+    for (i, blob) in blobs.iter().enumerate() {
+        let source = BlobSource::Registry {
+            client: src.clone(),
+            name: src_name.clone(),
+        };
+        let transfer = dst
+            .ensure_blob(&dst_name, blob, &from_repos, source)
+            .await?;
+        trace!("copy: {} {:?}", blob.digest, transfer);
+        progress(&CopyProgress {
+            digest: blob.digest.clone(),
+            transfer,
+            done: i + 1,
+            total,
+        });
+    }
+
+    // Registries reject manifests referencing unknown ones, push children first.
+    for (digest, child) in children {
+        dst.put_raw_manifest(&dst_name, &digest, &child.media_type, child.body)
+            .await?;
+    }
+    dst.put_raw_manifest(&dst_name, &dst_ref.version(), &top.media_type, top.body)
+        .await
+}
+
+/// Fetch the manifests referenced by a manifest list, recursing into nested lists.
+///
+/// Manifests are appended to `children` after the ones they reference,
+/// each of them once. Image manifests have no children.
+fn fetch_children<'a>(
+    src: &'a Client,
+    name: &'a str,
+    manifest: &'a RawManifest,
+    depth: usize,
+    children: &'a mut Vec<(String, RawManifest)>,
+) -> BoxFuture<'a, Result<()>> {
+    async move {
+        if !manifest.media_type.is_manifest_list() {
+            return Ok(());
+        }
+        ensure!(
+            depth < MAX_LIST_DEPTH,
+            "manifest lists nested more than {} levels deep",
+            MAX_LIST_DEPTH
+        );
+
+        let list: ManifestList = serde_json::from_slice(&manifest.body)?;
+        for entry in &list.manifests {
+            if children.iter().any(|(digest, _)| *digest == entry.digest) {
+                continue;
+            }
+            let child = src.fetch_raw_manifest(name, &entry.digest, true).await?;
+            fetch_children(src, name, &child, depth + 1, children).await?;
+            children.push((entry.digest.clone(), child));
+        }
+        Ok(())
+    }
+    .boxed()
+}
+
+/// List the blobs referenced by a manifest.
+///
+/// Manifest lists reference no blobs, only other manifests.
+fn image_blobs(manifest: &RawManifest) -> Result<Vec<Descriptor>> {
+    match manifest.media_type {
+        MediaTypes::ManifestV2S2 | MediaTypes::OciImageManifestV1 => {
+            let spec: ManifestSchema2Spec = serde_json::from_slice(&manifest.body)?;
+            Ok(push_blobs(&spec))
+        }
+        MediaTypes::ManifestList | MediaTypes::OciImageIndexV1 => Ok(Vec::new()),
+        ref other => bail!(ErrorKind::Unsupported(format!(
+            "copying {} manifests",
+            other
+        ))),
+    }
+}
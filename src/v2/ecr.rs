@@ -0,0 +1,277 @@
+//! Credentials for AWS Elastic Container Registry.
+//!
+//! ECR registries use Basic authentication with short-lived credentials,
+//! obtained through the `GetAuthorizationToken` API call. The AWS
+//! credentials for that call come from a web identity token when
+//! `AWS_WEB_IDENTITY_TOKEN_FILE` is set, and otherwise from the standard
+//! chain: environment, profile files, container and instance metadata.
+
+use crate::errors::{Result, ResultExt};
+use crate::v2::{request_error, response_error};
+use reqwest::StatusCode;
+use rusoto_credential::{AwsCredentials, ChainProvider, ProvideAwsCredentials};
+use rusoto_signature::{Region, SignedRequest};
+use rusoto_sts::WebIdentityProvider;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+static ECR_SERVICE: &str = "ecr";
+static ECR_TARGET: &str = "AmazonEC2ContainerRegistry_V20150921.GetAuthorizationToken";
+static ECR_CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+/// Credentials returned by `GetAuthorizationToken`.
+#[derive(Debug, Clone)]
+pub(crate) struct EcrCredentials {
+    pub(crate) user: String,
+    pub(crate) password: String,
+    pub(crate) expires_at: Instant,
+}
+
+/// Registry host components, e.g. `<account>.dkr.ecr.<region>.amazonaws.com`.
+#[derive(Debug, PartialEq)]
+struct EcrRegistry<'a> {
+    account: &'a str,
+    region: &'a str,
+    domain: &'a str,
+}
+
+impl<'a> EcrRegistry<'a> {
+    fn parse(registry: &'a str) -> Result<Self> {
+        let host = registry.split(':').next().unwrap_or(registry);
+        let parts = host.splitn(5, '.').collect::<Vec<_>>();
+        match parts.as_slice() {
+            [account, "dkr", "ecr", region, domain] => Ok(EcrRegistry {
+                account,
+                region,
+                domain,
+            }),
+            _ => bail!("'{}' is not an ECR registry host", registry),
+        }
+    }
+
+    fn api_host(&self) -> String {
+        format!("api.ecr.{}.{}", self.region, self.domain)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthorizationTokenResponse {
+    #[serde(rename = "authorizationData")]
+    authorization_data: Vec<AuthorizationData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthorizationData {
+    #[serde(rename = "authorizationToken")]
+    authorization_token: String,
+    /// Expiration as fractional seconds since the epoch.
+    #[serde(rename = "expiresAt")]
+    expires_at: f64,
+}
+
+/// Fetch Basic credentials for an ECR registry host.
+pub(crate) async fn get_authorization_token(
+    client: &reqwest::Client,
+    registry: &str,
+) -> Result<EcrCredentials> {
+    let ecr_registry = EcrRegistry::parse(registry)?;
+    let aws_credentials = aws_credentials().await?;
+
+    let host = ecr_registry.api_host();
+    let url = reqwest::Url::parse(&format!("https://{}/", host))
+        .chain_err(|| format!("failed to parse ECR endpoint for '{}'", host))?;
+
+    trace!("ecr: requesting authorization token for {}", registry);
+    request_authorization_token(client, url, &ecr_registry, &aws_credentials).await
+}
+
+/// Look up the AWS credentials to sign `GetAuthorizationToken` with.
+async fn aws_credentials() -> Result<AwsCredentials> {
+    if std::env::var_os("AWS_WEB_IDENTITY_TOKEN_FILE").is_some() {
+        return WebIdentityProvider::from_k8s_env()
+            .credentials()
+            .await
+            .chain_err(|| "ecr: failed to assume role with web identity");
+    }
+
+    ChainProvider::new()
+        .credentials()
+        .await
+        .chain_err(|| "ecr: no AWS credentials found")
+}
+
+/// Send a signed `GetAuthorizationToken` request to `url`.
+async fn request_authorization_token(
+    client: &reqwest::Client,
+    url: reqwest::Url,
+    ecr_registry: &EcrRegistry<'_>,
+    aws_credentials: &AwsCredentials,
+) -> Result<EcrCredentials> {
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => bail!("ecr: endpoint '{}' has no host", url),
+    };
+    let region = Region::Custom {
+        name: ecr_registry.region.to_string(),
+        endpoint: host.clone(),
+    };
+    let payload = format!(r#"{{"registryIds":["{}"]}}"#, ecr_registry.account);
+
+    let mut signed = SignedRequest::new("POST", ECR_SERVICE, &region, "/");
+    signed.set_hostname(Some(host));
+    signed.set_content_type(ECR_CONTENT_TYPE.to_string());
+    signed.add_header("x-amz-target", ECR_TARGET);
+    signed.set_payload(Some(payload.clone()));
+    signed.sign(aws_credentials);
+
+    let mut req = client.post(url).body(payload);
+    for (name, values) in signed.headers() {
+        // reqwest derives these from the URL and the body.
+        if name == "host" || name == "content-length" {
+            continue;
+        }
+        for value in values {
+            req = req.header(name.as_str(), value.as_slice());
+        }
+    }
+
+    let r = req.send().await.map_err(request_error)?;
+    if r.status() != StatusCode::OK {
+        return Err(response_error(r, "ecr: GetAuthorizationToken").await);
+    }
+
+    let response = r
+        .json::<AuthorizationTokenResponse>()
+        .await
+        .map_err(request_error)?;
+    let data = response
+        .authorization_data
+        .into_iter()
+        .next()
+        .ok_or("ecr: no authorization data returned")?;
+
+    let decoded = String::from_utf8(base64::decode(&data.authorization_token)?)?;
+    let mut creds = decoded.splitn(2, ':');
+    let user = creds.next().unwrap_or_default().to_string();
+    let password = creds
+        .next()
+        .ok_or("ecr: malformed authorization token")?
+        .to_string();
+
+    let lifetime = (UNIX_EPOCH + Duration::from_secs_f64(data.expires_at))
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+
+    Ok(EcrCredentials {
+        user,
+        password,
+        expires_at: Instant::now() + lifetime,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_host_parses() -> Result<()> {
+        assert_eq!(
+            EcrRegistry {
+                account: "123456789012",
+                region: "eu-west-1",
+                domain: "amazonaws.com",
+            },
+            EcrRegistry::parse("123456789012.dkr.ecr.eu-west-1.amazonaws.com")?
+        );
+        assert!(EcrRegistry::parse("quay.io").is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authorization_token_request_is_signed() -> Result<()> {
+        let ecr_registry = EcrRegistry::parse("123456789012.dkr.ecr.eu-west-1.amazonaws.com")?;
+        let aws_credentials = AwsCredentials::new(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            Some("session-token".to_string()),
+            None,
+        );
+        let expires_at =
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap() + Duration::from_secs(3600);
+        let body = format!(
+            r#"{{"authorizationData":[{{"authorizationToken":"{}","expiresAt":{}}}]}}"#,
+            base64::encode("AWS:password"),
+            expires_at.as_secs_f64()
+        );
+
+        let authorization = concat!(
+            r"^AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/\d{8}/eu-west-1/ecr/aws4_request, ",
+            r"SignedHeaders=content-type;host;x-amz-content-sha256;x-amz-date;",
+            r"x-amz-security-token;x-amz-target, Signature=[0-9a-f]{64}$",
+        );
+        let m = mockito::mock("POST", "/")
+            .match_header(
+                "authorization",
+                mockito::Matcher::Regex(authorization.into()),
+            )
+            .match_header("content-type", ECR_CONTENT_TYPE)
+            .match_header("x-amz-target", ECR_TARGET)
+            .match_header("x-amz-security-token", "session-token")
+            .match_body(r#"{"registryIds":["123456789012"]}"#)
+            .with_status(200)
+            .with_body(body)
+            .create();
+
+        let url = reqwest::Url::parse(&mockito::server_url()).unwrap();
+        let creds = request_authorization_token(
+            &reqwest::Client::new(),
+            url,
+            &ecr_registry,
+            &aws_credentials,
+        )
+        .await?;
+
+        m.assert();
+        assert_eq!("AWS", creds.user);
+        assert_eq!("password", creds.password);
+        assert!(creds.expires_at > Instant::now() + Duration::from_secs(3500));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn authorization_token_failure_reports_status() -> Result<()> {
+        let ecr_registry = EcrRegistry::parse("123456789012.dkr.ecr.eu-west-1.amazonaws.com")?;
+        let aws_credentials = AwsCredentials::new("AKIDREJECTED", "secret", None, None);
+
+        let m = mockito::mock("POST", "/")
+            .match_header(
+                "authorization",
+                mockito::Matcher::Regex("Credential=AKIDREJECTED/".into()),
+            )
+            .with_status(400)
+            .with_body(r#"{"__type":"UnrecognizedClientException"}"#)
+            .create();
+
+        let url = reqwest::Url::parse(&mockito::server_url()).unwrap();
+        let err = request_authorization_token(
+            &reqwest::Client::new(),
+            url,
+            &ecr_registry,
+            &aws_credentials,
+        )
+        .await
+        .unwrap_err();
+
+        m.assert();
+        match err.kind() {
+            crate::errors::ErrorKind::Status(_, status, _) => {
+                assert_eq!(StatusCode::BAD_REQUEST, *status)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+use crate::errors::Result;
+use crate::v2::content_digest::DigestVerifier;
+use crate::v2::manifest::LayerDescriptor;
+use crate::v2::*;
+use bytes::Bytes;
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::stream::BoxStream;
+use futures::task::AtomicWaker;
+use libflate::gzip;
+use ruzstd::frame::ReadFrameHeaderError;
+use ruzstd::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::task::{Context, Poll};
+use tokio::io::AsyncRead;
+use tracing::Instrument;
+
+/// Compression of a layer, as told by its media type.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn from_media_type(media_type: &str) -> Result<Self> {
+        if media_type.ends_with("gzip") {
+            Ok(Compression::Gzip)
+        } else if media_type.ends_with("zstd") {
+            Ok(Compression::Zstd)
+        } else if media_type.ends_with("tar") {
+            Ok(Compression::None)
+        } else {
+            bail!("unknown layer media type '{}'", media_type)
+        }
+    }
+}
+
+/// Uncompressed content of a layer, read while it is downloaded.
+///
+/// Gzip and zstd-compressed content is decompressed on a dedicated thread,
+/// a few chunks ahead of the reader. The compressed content is checked against
+/// the layer digest when reaching its end, unless digest verification is
+/// disabled.
+pub struct LayerReader {
+    chunks: BoxStream<'static, Result<Bytes>>,
+    verifier: Option<DigestVerifier>,
+    /// Content not returned to the reader yet.
+    buffer: Bytes,
+    decoder: Option<Decoder>,
+}
+
+/// Handle to a decompression thread.
+struct Decoder {
+    /// Sender of compressed chunks, dropped once all of them were sent.
+    input: Option<SyncSender<Bytes>>,
+    /// Chunk waiting for the thread to accept more input.
+    pending: Option<Bytes>,
+    /// Woken when the thread takes a chunk.
+    input_waker: Arc<AtomicWaker>,
+    output: mpsc::Receiver<io::Result<Bytes>>,
+}
+
+/// Number of chunks buffered on each side of the decompression thread.
+const DECODER_CHUNKS: usize = 4;
+
+/// Size of the chunks of uncompressed content sent by the decompression thread.
+const DECODED_CHUNK_SIZE: usize = 64 * 1024;
+
+impl Decoder {
+    fn spawn(compression: Compression) -> Self {
+        let (input, input_rx) = sync_channel(DECODER_CHUNKS);
+        let (mut output_tx, output) = mpsc::channel(DECODER_CHUNKS);
+        let input_waker = Arc::new(AtomicWaker::new());
+
+        let reader = ChannelReader {
+            input: input_rx,
+            chunk: Bytes::new(),
+            waker: input_waker.clone(),
+        };
+        std::thread::spawn(move || {
+            // Returns false once the reader is gone.
+            let mut send = |chunk: Vec<u8>| block_on(output_tx.send(Ok(chunk.into()))).is_ok();
+            let result = match compression {
+                Compression::Gzip => gzip::Decoder::new(reader).and_then(|d| forward(d, &mut send)),
+                Compression::Zstd => decode_zstd(reader, &mut send),
+                Compression::None => Ok(()),
+            };
+            if let Err(e) = result {
+                let _ = block_on(output_tx.send(Err(e)));
+            }
+        });
+
+        Self {
+            input: Some(input),
+            pending: None,
+            input_waker,
+            output,
+        }
+    }
+}
+
+/// Send the content read from `decoder` in chunks, until the reader is gone.
+fn forward<R: Read>(mut decoder: R, send: &mut dyn FnMut(Vec<u8>) -> bool) -> io::Result<()> {
+    loop {
+        let mut buf = vec![0; DECODED_CHUNK_SIZE];
+        let n = decoder.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.truncate(n);
+        if !send(buf) {
+            return Ok(());
+        }
+    }
+}
+
+/// Decompress zstd content, sending it in chunks until the reader is gone.
+///
+/// Layers may hold several frames, e.g. `zstd:chunked` ones, whose
+/// skippable frames carry metadata and are left out of the content.
+fn decode_zstd(mut reader: ChannelReader, send: &mut dyn FnMut(Vec<u8>) -> bool) -> io::Result<()> {
+    let invalid = |e: FrameDecoderError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+
+    let mut frame = FrameDecoder::new();
+    while reader.has_more() {
+        match frame.reset(&mut reader) {
+            Ok(()) => {}
+            Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                length,
+                ..
+            })) => {
+                let skipped = io::copy(&mut (&mut reader).take(length.into()), &mut io::sink())?;
+                if skipped != u64::from(length) {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                continue;
+            }
+            Err(e) => return Err(invalid(e)),
+        }
+
+        loop {
+            if !frame.is_finished() {
+                frame
+                    .decode_blocks(
+                        &mut reader,
+                        BlockDecodingStrategy::UptoBytes(DECODED_CHUNK_SIZE),
+                    )
+                    .map_err(invalid)?;
+            }
+            let available = frame.can_collect();
+            if available > 0 {
+                let mut buf = vec![0; available];
+                let n = frame.read(&mut buf)?;
+                buf.truncate(n);
+                if !send(buf) {
+                    return Ok(());
+                }
+            } else if frame.is_finished() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Blocking reader of the compressed chunks sent to a decompression thread.
+struct ChannelReader {
+    input: Receiver<Bytes>,
+    chunk: Bytes,
+    waker: Arc<AtomicWaker>,
+}
+
+impl ChannelReader {
+    /// Wait for more content, returning `false` at the end of it.
+    fn has_more(&mut self) -> bool {
+        while self.chunk.is_empty() {
+            match self.input.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.waker.wake();
+                }
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.has_more() {
+            return Ok(0);
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk.split_to(n));
+        Ok(n)
+    }
+}
+
+impl LayerReader {
+    fn new(
+        chunks: BoxStream<'static, Result<Bytes>>,
+        compression: Compression,
+        verifier: Option<DigestVerifier>,
+    ) -> Self {
+        Self {
+            chunks,
+            verifier,
+            buffer: Bytes::new(),
+            decoder: match compression {
+                Compression::None => None,
+                compression => Some(Decoder::spawn(compression)),
+            },
+        }
+    }
+}
+
+/// Poll the next downloaded chunk, verifying the digest at the end of the content.
+fn poll_chunk(
+    chunks: &mut BoxStream<'static, Result<Bytes>>,
+    verifier: &mut Option<DigestVerifier>,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<Option<Bytes>>> {
+    match futures::ready!(chunks.as_mut().poll_next(cx)) {
+        Some(Ok(chunk)) => {
+            if let Some(verifier) = verifier {
+                verifier.update(&chunk);
+            }
+            Poll::Ready(Ok(Some(chunk)))
+        }
+        Some(Err(e)) => Poll::Ready(Err(io::Error::other(e.to_string()))),
+        None => {
+            if let Some(verifier) = verifier.take() {
+                if let Err(e) = verifier.verify() {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        e.to_string(),
+                    )));
+                }
+            }
+            Poll::Ready(Ok(None))
+        }
+    }
+}
+
+impl AsyncRead for LayerReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let LayerReader {
+            chunks,
+            verifier,
+            buffer,
+            decoder,
+        } = self.get_mut();
+
+        loop {
+            if !buffer.is_empty() {
+                let n = buf.len().min(buffer.len());
+                buf[..n].copy_from_slice(&buffer.split_to(n));
+                return Poll::Ready(Ok(n));
+            }
+
+            let decoder = match decoder {
+                Some(decoder) => decoder,
+                None => match futures::ready!(poll_chunk(chunks, verifier, cx))? {
+                    Some(chunk) => {
+                        *buffer = chunk;
+                        continue;
+                    }
+                    None => return Poll::Ready(Ok(0)),
+                },
+            };
+
+            match decoder.output.poll_next_unpin(cx) {
+                Poll::Ready(Some(output)) => {
+                    *buffer = output?;
+                    continue;
+                }
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => {}
+            }
+
+            // Feed the thread until it has enough input, it is then woken by its output.
+            while let Some(input) = &decoder.input {
+                let chunk = match decoder.pending.take() {
+                    Some(chunk) => chunk,
+                    None => match futures::ready!(poll_chunk(chunks, verifier, cx))? {
+                        Some(chunk) => chunk,
+                        None => {
+                            decoder.input = None;
+                            break;
+                        }
+                    },
+                };
+                decoder.input_waker.register(cx.waker());
+                match input.try_send(chunk) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(chunk)) => {
+                        decoder.pending = Some(chunk);
+                        break;
+                    }
+                    Err(TrySendError::Disconnected(_)) => decoder.input = None,
+                }
+            }
+            return Poll::Pending;
+        }
+    }
+}
+
+impl std::fmt::Debug for LayerReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("LayerReader")
+            .field("compressed", &self.decoder.is_some())
+            .finish()
+    }
+}
+
+impl Client {
+    /// Download a layer, reading its uncompressed content as it arrives.
+    ///
+    /// The decompression is picked from the layer media type, e.g.
+    /// `application/vnd.oci.image.layer.v1.tar+gzip` or `...tar+zstd`;
+    /// uncompressed `tar` layers are read as they are. Use `get_blob` or
+    /// `blob_to_writer` for the compressed content.
+    pub async fn get_layer_reader(
+        &self,
+        name: &str,
+        layer: &LayerDescriptor,
+    ) -> Result<LayerReader> {
+        let span = self.span("get_layer", name, &layer.digest);
+        async move {
+            let compression = Compression::from_media_type(&layer.media_type)?;
+
+            let digest = ContentDigest::try_new(layer.digest.clone())?;
+            let chunks = self.blob_chunks(name, &digest).await?;
+
+            let verifier = if self.verify_digests {
+                Some(digest.verifier())
+            } else {
+                None
+            };
+            Ok(LayerReader::new(chunks, compression, verifier))
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Digest;
+
+    #[test]
+    fn compression_from_media_type() {
+        for (media_type, compression) in &[
+            (
+                "application/vnd.docker.image.rootfs.diff.tar.gzip",
+                Compression::Gzip,
+            ),
+            (
+                "application/vnd.oci.image.layer.v1.tar+gzip",
+                Compression::Gzip,
+            ),
+            (
+                "application/vnd.oci.image.layer.v1.tar+zstd",
+                Compression::Zstd,
+            ),
+            ("application/vnd.oci.image.layer.v1.tar", Compression::None),
+        ] {
+            assert_eq!(
+                Compression::from_media_type(media_type).unwrap(),
+                *compression
+            );
+        }
+        assert!(Compression::from_media_type("application/json").is_err());
+    }
+
+    fn gzip_layer(content: &[u8]) -> (Vec<u8>, DigestVerifier) {
+        use std::io::Write;
+
+        let mut encoder = gzip::Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(content).unwrap();
+        let compressed = encoder.finish().into_result().unwrap();
+        let digest = format!("sha256:{:x}", sha2::Sha256::digest(&compressed));
+        let verifier = ContentDigest::try_new(digest).unwrap().verifier();
+        (compressed, verifier)
+    }
+
+    fn read_layer(
+        compressed: &[u8],
+        compression: Compression,
+        chunk_size: usize,
+        verifier: DigestVerifier,
+    ) -> io::Result<Vec<u8>> {
+        use tokio::io::AsyncReadExt;
+
+        let chunks = compressed
+            .chunks(chunk_size)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect::<Vec<_>>();
+        let mut reader =
+            LayerReader::new(stream::iter(chunks).boxed(), compression, Some(verifier));
+
+        let mut uncompressed = Vec::new();
+        block_on(reader.read_to_end(&mut uncompressed))?;
+        Ok(uncompressed)
+    }
+
+    #[test]
+    fn gzip_layer_is_decompressed_across_chunks() {
+        let content = (0..200_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let (compressed, verifier) = gzip_layer(&content);
+
+        for chunk_size in &[1, 7, 16 * 1024] {
+            let uncompressed = read_layer(
+                &compressed,
+                Compression::Gzip,
+                *chunk_size,
+                verifier.clone(),
+            )
+            .unwrap();
+            assert!(uncompressed == content);
+        }
+    }
+
+    #[test]
+    fn gzip_layer_digest_is_verified() {
+        let (compressed, _) = gzip_layer(b"layer content");
+        let (_, other) = gzip_layer(b"other content");
+
+        let err = read_layer(&compressed, Compression::Gzip, 7, other).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn zstd_layer_is_decompressed_across_chunks() {
+        // Two frames and a skippable one, as in `zstd:chunked` layers.
+        let compressed = include_bytes!("../../tests/fixtures/oci_image_layer_v1.tar.zst");
+        let digest = format!("sha256:{:x}", sha2::Sha256::digest(&compressed[..]));
+        let verifier = ContentDigest::try_new(digest).unwrap().verifier();
+        let numbers = (0..2000).map(|i| format!("{}\n", i)).collect::<String>();
+
+        for chunk_size in &[1, 7, 16 * 1024] {
+            let uncompressed =
+                read_layer(compressed, Compression::Zstd, *chunk_size, verifier.clone()).unwrap();
+            assert_eq!(uncompressed.len(), 20480);
+
+            let mut archive = tar::Archive::new(&uncompressed[..]);
+            let entries = archive
+                .entries()
+                .unwrap()
+                .map(|entry| {
+                    let mut entry = entry.unwrap();
+                    let path = entry.path().unwrap().display().to_string();
+                    let mut content = String::new();
+                    entry.read_to_string(&mut content).unwrap();
+                    (path, content)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                entries,
+                vec![
+                    (
+                        "hello.txt".to_string(),
+                        "hello from a zstd layer\n".to_string()
+                    ),
+                    ("numbers.txt".to_string(), numbers.clone()),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_zstd_layer_is_an_error() {
+        let compressed = include_bytes!("../../tests/fixtures/oci_image_layer_v1.tar.zst");
+        let truncated = &compressed[..compressed.len() / 2];
+        let digest = format!("sha256:{:x}", sha2::Sha256::digest(truncated));
+        let verifier = ContentDigest::try_new(digest).unwrap().verifier();
+
+        assert!(read_layer(truncated, Compression::Zstd, 7, verifier).is_err());
+    }
+}
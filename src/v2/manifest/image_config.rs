@@ -0,0 +1,144 @@
+use crate::errors::Result;
+use crate::v2::manifest::{Manifest, Platform};
+use crate::v2::*;
+use std::collections::HashMap;
+
+/// Image configuration, as referenced by the config descriptor of a manifest.
+///
+/// This covers both `application/vnd.docker.container.image.v1+json` and
+/// `application/vnd.oci.image.config.v1+json` blobs, see the
+/// [OCI image spec](https://github.com/opencontainers/image-spec/blob/v1.0.1/config.md).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageConfig {
+    #[serde(default)]
+    pub architecture: String,
+    #[serde(default)]
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Execution parameters for containers based on the image.
+    #[serde(default)]
+    pub config: ContainerConfig,
+    #[serde(default)]
+    pub rootfs: RootFs,
+    #[serde(default)]
+    pub history: Vec<History>,
+}
+
+/// Execution parameters of an image.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmd: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exposed_ports: Option<HashMap<String, serde_json::Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<HashMap<String, serde_json::Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_signal: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<HashMap<String, String>>,
+}
+
+/// Layer content addresses of an image.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RootFs {
+    #[serde(rename = "type", default)]
+    pub fs_type: String,
+    /// Digests of the uncompressed layers, ordered starting with the base image first.
+    #[serde(default)]
+    pub diff_ids: Vec<String>,
+}
+
+/// History entry of an image layer.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct History {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// Whether this entry did not create a layer.
+    #[serde(default)]
+    pub empty_layer: bool,
+}
+
+impl ImageConfig {
+    /// Return the image labels.
+    pub fn labels(&self) -> HashMap<String, String> {
+        self.config.labels.clone().unwrap_or_default()
+    }
+}
+
+impl Client {
+    /// Fetch the image config referenced by a manifest.
+    ///
+    /// The downloaded blob is checked against the size and, unless digest
+    /// verification is disabled, the digest of the manifest config descriptor.
+    pub async fn get_config(&self, name: &str, manifest: &Manifest) -> Result<ImageConfig> {
+        let descriptor = manifest.config()?;
+        let blob = self.get_blob(name, &descriptor.digest).await?;
+        ensure!(
+            blob.len() as u64 == descriptor.size,
+            "config blob '{}' has size {}, expected {}",
+            descriptor.digest,
+            blob.len(),
+            descriptor.size
+        );
+
+        Ok(serde_json::from_slice(&blob)?)
+    }
+
+    /// Fetch the labels of an image.
+    ///
+    /// Multi-platform images are resolved to their `linux/amd64` manifest.
+    pub async fn get_labels(&self, name: &str, reference: &str) -> Result<HashMap<String, String>> {
+        let manifest = self
+            .resolve_manifest(name, reference, &Platform::new("linux", "amd64"))
+            .await?;
+        let config = self.get_config(name, &manifest).await?;
+
+        Ok(config.labels())
+    }
+
+    /// Fetch the layers of an image, as pairs of blob digest and uncompressed `diff_id`.
+    ///
+    /// Layers are ordered starting with the base image first. Multi-platform
+    /// images are resolved to their `linux/amd64` manifest.
+    pub async fn layer_pairs(&self, name: &str, reference: &str) -> Result<Vec<(String, String)>> {
+        let manifest = self
+            .resolve_manifest(name, reference, &Platform::new("linux", "amd64"))
+            .await?;
+        let layers = manifest.layers()?;
+        let config = self.get_config(name, &manifest).await?;
+        let diff_ids = config.rootfs.diff_ids;
+        ensure!(
+            layers.len() == diff_ids.len(),
+            "{}:{} has {} layers but {} diff_ids",
+            name,
+            reference,
+            layers.len(),
+            diff_ids.len()
+        );
+
+        Ok(layers
+            .into_iter()
+            .map(|layer| layer.digest)
+            .zip(diff_ids)
+            .collect())
+    }
+}
@@ -3,6 +3,8 @@ use std::collections::HashMap;
 /// Manifest version 2 schema 1, signed.
 ///
 /// Specification is at https://docs.docker.com/registry/spec/manifest-v2-1/.
+/// This also represents unsigned schema1 manifests, without `signatures`.
+/// Schema1 is deprecated and only supported for reading.
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ManifestSchema1Signed {
     #[serde(rename = "schemaVersion")]
@@ -13,6 +15,7 @@ pub struct ManifestSchema1Signed {
     #[serde(rename = "fsLayers")]
     fs_layers: Vec<S1Layer>,
     history: Vec<V1Compat>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     signatures: Vec<Signature>,
 }
 
@@ -1,17 +1,39 @@
 use crate::errors::{Error, Result};
 use reqwest::Method;
+use std::collections::HashMap;
+
+/// Media types of image configs, as opposed to artifact configs.
+const IMAGE_CONFIG_MEDIA_TYPES: &[&str] = &[
+    "application/vnd.docker.container.image.v1+json",
+    "application/vnd.oci.image.config.v1+json",
+];
 
 /// Manifest version 2 schema 2.
 ///
 /// Specification is at https://docs.docker.com/registry/spec/manifest-v2-2/.
+/// This also represents OCI image manifests, which share the same structure.
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ManifestSchema2Spec {
     #[serde(rename = "schemaVersion")]
     schema_version: u16,
-    #[serde(rename = "mediaType")]
-    media_type: String,
+    #[serde(
+        rename = "mediaType",
+        default,
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub(crate) media_type: String,
+    #[serde(
+        rename = "artifactType",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    artifact_type: Option<String>,
     config: Config,
-    layers: Vec<S2Layer>,
+    layers: Vec<LayerDescriptor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    subject: Option<Descriptor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    annotations: Option<HashMap<String, String>>,
 }
 
 /// Super-type for combining a ManifestSchema2 with a ConfigBlob.
@@ -27,6 +49,19 @@ pub struct Config {
     pub media_type: String,
     pub size: u64,
     pub digest: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
+}
+
+/// Descriptor of a manifest, e.g. the subject an artifact refers to.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Descriptor {
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub size: u64,
+    pub digest: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
 }
 
 /// Partial representation of a container image (application/vnd.docker.container.image.v1+json).
@@ -36,26 +71,35 @@ pub struct Config {
 /// [image-spec-v1]: https://github.com/moby/moby/blob/a30990b3c8d0d42280fa501287859e1d2393a951/image/spec/v1.md#image-json-description
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ConfigBlob {
+    #[serde(default)]
     architecture: String,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
-struct S2Layer {
+/// Descriptor of an image layer.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LayerDescriptor {
     #[serde(rename = "mediaType")]
-    media_type: String,
-    size: u64,
-    digest: String,
-    urls: Option<Vec<String>>,
+    pub media_type: String,
+    pub size: u64,
+    pub digest: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urls: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
 }
 
 /// Manifest List.
+///
+/// This also represents OCI image indexes, which share the same structure.
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ManifestList {
     #[serde(rename = "schemaVersion")]
     schema_version: u16,
-    #[serde(rename = "mediaType")]
-    media_type: String,
+    #[serde(rename = "mediaType", default)]
+    pub(crate) media_type: String,
     pub manifests: Vec<ManifestObj>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
 }
 
 /// Manifest object.
@@ -65,7 +109,13 @@ pub struct ManifestObj {
     media_type: String,
     size: u64,
     pub digest: String,
+    #[serde(default)]
     pub platform: Platform,
+    /// Type of the artifact, for entries of a referrers index.
+    #[serde(rename = "artifactType", skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
 }
 
 /// Platform-related manifest entries.
@@ -73,32 +123,156 @@ pub struct ManifestObj {
 pub struct Platform {
     pub architecture: String,
     pub os: String,
-    #[serde(rename = "os.version")]
+    #[serde(rename = "os.version", skip_serializing_if = "Option::is_none")]
     pub os_version: Option<String>,
-    #[serde(rename = "os.features")]
+    #[serde(rename = "os.features", skip_serializing_if = "Option::is_none")]
     pub os_features: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub variant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub features: Option<Vec<String>>,
 }
 
+impl Platform {
+    /// Initialize a platform from its OS and architecture, e.g. `linux` and `amd64`.
+    pub fn new(os: &str, architecture: &str) -> Self {
+        Self {
+            os: os.to_string(),
+            architecture: architecture.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether this platform satisfies the requested one.
+    ///
+    /// The variant is only compared if the requested platform has one.
+    pub fn matches(&self, requested: &Platform) -> bool {
+        self.os == requested.os
+            && self.architecture == requested.architecture
+            && (requested.variant.is_none() || self.variant == requested.variant)
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+impl LayerDescriptor {
+    /// Get the media type of this layer, e.g. to find its compression.
+    pub fn get_media_type(&self) -> crate::mediatypes::MediaTypes {
+        self.media_type
+            .parse()
+            .unwrap_or_else(|_| crate::mediatypes::MediaTypes::Other(self.media_type.clone()))
+    }
+}
+
+impl ManifestList {
+    /// Initialize an OCI image index without entries.
+    pub(crate) fn empty_index() -> Self {
+        Self {
+            schema_version: 2,
+            media_type: crate::mediatypes::MediaTypes::OciImageIndexV1.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Keep only the entries of the given artifact type.
+    pub(crate) fn with_artifact_type(mut self, artifact_type: &str) -> Self {
+        self.manifests
+            .retain(|m| m.artifact_type.as_deref() == Some(artifact_type));
+        self
+    }
+}
+
 impl ManifestSchema2Spec {
+    /// Initialize an OCI image manifest from its config and layers.
+    ///
+    /// OCI artifacts can use any config media type, and set their
+    /// `artifact_type` and `subject`.
+    pub fn new(config: Config, layers: Vec<LayerDescriptor>) -> Self {
+        Self {
+            schema_version: 2,
+            media_type: crate::mediatypes::MediaTypes::OciImageManifestV1.to_string(),
+            config,
+            layers,
+            ..Default::default()
+        }
+    }
+
+    /// Set the type of the artifact this manifest describes.
+    pub fn artifact_type(mut self, artifact_type: &str) -> Self {
+        self.artifact_type = Some(artifact_type.to_string());
+        self
+    }
+
+    /// Set the manifest this artifact refers to, e.g. the image it signs.
+    pub fn subject(mut self, subject: Descriptor) -> Self {
+        self.subject = Some(subject);
+        self
+    }
+
+    /// Set the annotations of this manifest.
+    pub fn annotations(mut self, annotations: HashMap<String, String>) -> Self {
+        self.annotations = Some(annotations);
+        self
+    }
+
+    /// Get the type of the artifact this manifest describes, if any.
+    pub fn get_artifact_type(&self) -> Option<&str> {
+        self.artifact_type.as_deref()
+    }
+
+    /// Get the manifest this artifact refers to, if any.
+    pub fn get_subject(&self) -> Option<&Descriptor> {
+        self.subject.as_ref()
+    }
+
+    /// Get the annotations of this manifest, if any.
+    pub fn get_annotations(&self) -> Option<&HashMap<String, String>> {
+        self.annotations.as_ref()
+    }
+
     /// Get `Config` object referenced by this manifest.
     pub fn config(&self) -> &Config {
         &self.config
     }
 
+    /// Get the descriptors of all layers referenced by this manifest.
+    ///
+    /// The returned layers list is ordered starting with the base image first.
+    pub fn layers(&self) -> &[LayerDescriptor] {
+        &self.layers
+    }
+
     /// Fetch the config blob for this manifest
     pub(crate) async fn fetch_config_blob(
         self,
         client: crate::v2::Client,
         repo: String,
     ) -> Result<ManifestSchema2> {
+        // Artifacts may reference any config, which is not an image config.
+        if !IMAGE_CONFIG_MEDIA_TYPES.contains(&self.config.media_type.as_str()) {
+            trace!(
+                "not fetching config blob of media type '{}'",
+                self.config.media_type
+            );
+            return Ok(ManifestSchema2 {
+                manifest_spec: self,
+                config_blob: ConfigBlob::default(),
+            });
+        }
+
         let url = {
+            crate::v2::validate_name(&repo)?;
             let ep = format!(
-                "{}/v2/{}/blobs/{}",
-                client.base_url.clone(),
-                repo,
-                self.config.digest
+                "{}{}/{}/blobs/{}",
+                client.base_url, client.api_base_path, repo, self.config.digest
             );
             match reqwest::Url::parse(&ep) {
                 Ok(url) => url,
@@ -111,16 +285,16 @@ impl ManifestSchema2Spec {
             }
         };
 
+        client.refresh_auth_if_needed().await?;
         let r = client
-            .build_reqwest(Method::GET, url.clone())
-            .send()
+            .send_with_escalation(|| client.build_reqwest(Method::GET, url.clone()))
             .await?;
 
         let status = r.status();
         trace!("GET {:?}: {}", url, &status);
 
         if !status.is_success() {
-            return Err(format!("wrong HTTP status '{}'", status).into());
+            return Err(crate::v2::status_error(&r, "GET"));
         }
 
         let config_blob = r.json::<ConfigBlob>().await?;
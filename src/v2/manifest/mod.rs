@@ -1,10 +1,12 @@
-use crate::errors::{Error, Result};
+use crate::errors::{Error, ErrorKind, Result};
 use crate::mediatypes;
 use crate::v2::*;
 use mime;
 use reqwest::{self, header, StatusCode, Url};
+use sha2::Digest;
 use std::iter::FromIterator;
 use std::str::FromStr;
+use tracing::Instrument;
 
 mod manifest_schema1;
 pub use self::manifest_schema1::*;
@@ -12,6 +14,9 @@ pub use self::manifest_schema1::*;
 mod manifest_schema2;
 pub use self::manifest_schema2::*;
 
+mod image_config;
+pub use self::image_config::*;
+
 impl Client {
     /// Fetch an image manifest.
     ///
@@ -32,105 +37,355 @@ impl Client {
         name: &str,
         reference: &str,
     ) -> Result<(Manifest, Option<String>)> {
-        let url = self.build_url(name, reference)?;
+        self.get_manifest_response(name, reference)
+            .await
+            .map(|response| (response.manifest, response.digest))
+    }
 
-        let accept_headers = build_accept_headers(&self.index);
+    /// Fetch an image manifest, along with its media type and digest.
+    ///
+    /// The name and reference parameters identify the image.
+    /// The reference may be either a tag or digest.
+    /// See `get_raw_manifest` for how the content is verified.
+    pub async fn get_manifest_response(
+        &self,
+        name: &str,
+        reference: &str,
+    ) -> Result<ManifestResponse> {
+        self.fetch_manifest_response(name, reference, false).await
+    }
 
-        let client_spare0 = self.clone();
+    /// Fetch and parse a manifest, accepting manifest lists if `lists` is set.
+    async fn fetch_manifest_response(
+        &self,
+        name: &str,
+        reference: &str,
+        lists: bool,
+    ) -> Result<ManifestResponse> {
+        let raw = self.fetch_raw_manifest(name, reference, lists).await?;
+
+        let manifest = match raw.media_type {
+            mediatypes::MediaTypes::ManifestV2S1Signed => {
+                serde_json::from_slice::<ManifestSchema1Signed>(&raw.body)
+                    .map(Manifest::S1Signed)?
+            }
+            mediatypes::MediaTypes::ManifestV2S1 => {
+                serde_json::from_slice::<ManifestSchema1Signed>(&raw.body).map(Manifest::S1)?
+            }
+            mediatypes::MediaTypes::ManifestV2S2 | mediatypes::MediaTypes::OciImageManifestV1 => {
+                let m = serde_json::from_slice::<ManifestSchema2Spec>(&raw.body)?;
+                m.fetch_config_blob(self.clone(), name.to_string())
+                    .await
+                    .map(Manifest::S2)?
+            }
+            mediatypes::MediaTypes::ManifestList | mediatypes::MediaTypes::OciImageIndexV1 => {
+                serde_json::from_slice::<ManifestList>(&raw.body).map(Manifest::ML)?
+            }
+            ref unsupported => {
+                return Err(Error::from(format!(
+                    "unsupported mediatype '{:?}'",
+                    unsupported
+                )))
+            }
+        };
 
-        let res = self
-            .build_reqwest(Method::GET, url.clone())
-            .headers(accept_headers)
-            .send()
-            .await?;
+        Ok(ManifestResponse {
+            manifest,
+            media_type: raw.media_type,
+            digest: raw.digest,
+        })
+    }
 
-        let status = res.status();
-        trace!("GET '{}' status: {:?}", res.url(), status);
+    /// Fetch an image manifest as served by the registry, without parsing it.
+    ///
+    /// The name and reference parameters identify the image.
+    /// The reference may be either a tag or digest.
+    /// A manifest fetched by digest must match it, or this fails with
+    /// `ErrorKind::DigestMismatch`. Otherwise, a warning is logged if the
+    /// digest returned by the registry does not match the manifest content.
+    pub async fn get_raw_manifest(&self, name: &str, reference: &str) -> Result<RawManifest> {
+        self.fetch_raw_manifest(name, reference, false).await
+    }
 
-        match status {
-            StatusCode::OK => {}
-            _ => return Err(format!("GET {}: wrong HTTP status '{}'", res.url(), status).into()),
+    /// Fetch a raw manifest, accepting manifest lists if `lists` is set.
+    pub(crate) async fn fetch_raw_manifest(
+        &self,
+        name: &str,
+        reference: &str,
+        lists: bool,
+    ) -> Result<RawManifest> {
+        let span = self.span("get_manifest", name, reference);
+        async move {
+            let url = self.build_url(name, reference)?;
+            let res = self.send_manifest_request(Method::GET, &url, lists).await?;
+
+            let status = res.status();
+            trace!("GET '{}' status: {:?}", res.url(), status);
+
+            if status != StatusCode::OK {
+                return Err(fetch_error(res, "GET", format!("{}:{}", name, reference)).await);
+            }
+
+            let headers = res.headers();
+            let content_digest = match headers.get("docker-content-digest") {
+                Some(content_digest_value) => Some(
+                    content_digest_value
+                        .to_str()
+                        .map_err(|e| Error::from(format!("{}", e)))?
+                        .to_string(),
+                ),
+                None => {
+                    debug!("cannot find manifestref in headers");
+                    None
+                }
+            };
+
+            let header_content_type = headers.get(header::CONTENT_TYPE);
+            let media_type = evaluate_media_type(header_content_type, &url)?;
+
+            trace!(
+                "content-type: {:?}, media-type: {:?}",
+                header_content_type,
+                media_type
+            );
+
+            let body = read_body_limited(res, self.max_manifest_size).await?;
+
+            // Signed schema1 digests are computed over the payload without signatures.
+            if media_type != mediatypes::MediaTypes::ManifestV2S1Signed {
+                if reference.contains(':') {
+                    // Whatever the registry claims, content fetched by digest must match it.
+                    ContentDigest::try_new(reference.to_string())?.try_verify(&body)?;
+                } else if let Some(digest) = &content_digest {
+                    if let Err(e) =
+                        ContentDigest::try_new(digest.clone()).and_then(|d| d.try_verify(&body))
+                    {
+                        warn!("manifest digest does not match its content: {}", e);
+                    }
+                }
+            }
+
+            Ok(RawManifest {
+                body,
+                media_type,
+                digest: content_digest,
+            })
         }
+        .instrument(span)
+        .await
+    }
 
-        let headers = res.headers();
-        let content_digest = match headers.get("docker-content-digest") {
-            Some(content_digest_value) => Some(
-                content_digest_value
+    /// Fetch the image manifest for a platform.
+    ///
+    /// Manifest lists and OCI indexes are accepted along with the configured
+    /// media types. If the reference points to one, the entry matching the
+    /// platform is fetched by digest. Image manifests are returned as they are.
+    pub async fn resolve_manifest(
+        &self,
+        name: &str,
+        reference: &str,
+        platform: &Platform,
+    ) -> Result<Manifest> {
+        let response = self.fetch_manifest_response(name, reference, true).await?;
+        let list = match response.manifest {
+            Manifest::ML(list) => list,
+            manifest => return Ok(manifest),
+        };
+
+        let digest = match list.manifests.iter().find(|m| m.platform.matches(platform)) {
+            Some(entry) => entry.digest.clone(),
+            None => bail!(
+                "no manifest for platform '{}' in {}:{}, available platforms: {}",
+                platform,
+                name,
+                reference,
+                list.manifests
+                    .iter()
+                    .map(|m| m.platform.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+
+        trace!("resolved platform '{}' to {}", platform, digest);
+        self.get_manifest(name, &digest).await
+    }
+
+    /// Upload an image manifest, and return its digest.
+    ///
+    /// The manifest is serialized to JSON and sent with the content type of
+    /// its media type. The reference may be either a tag or digest.
+    pub async fn put_manifest(
+        &self,
+        name: &str,
+        reference: &str,
+        manifest: &Manifest,
+    ) -> Result<String> {
+        self.put_raw_manifest(name, reference, &manifest.media_type(), manifest.to_json()?)
+            .await
+    }
+
+    /// Upload manifest bytes unchanged, e.g. as fetched by `get_raw_manifest`,
+    /// and return their digest.
+    ///
+    /// The body is sent with the content type of the given media type.
+    pub async fn put_raw_manifest(
+        &self,
+        name: &str,
+        reference: &str,
+        media_type: &mediatypes::MediaTypes,
+        body: Vec<u8>,
+    ) -> Result<String> {
+        let span = self.span("put_manifest", name, reference);
+        async move {
+            let url = self.build_url(name, reference)?;
+            let body = bytes::Bytes::from(body);
+            let content_type = media_type.to_string();
+
+            self.refresh_auth_if_needed().await?;
+            let res = self
+                .send_with_escalation(|| {
+                    self.build_reqwest(Method::PUT, url.clone())
+                        .header(header::CONTENT_TYPE, content_type.as_str())
+                        .body(body.clone())
+                })
+                .await?;
+
+            let status = res.status();
+            trace!("PUT '{}' status: {:?}", res.url(), status);
+
+            if status != StatusCode::CREATED {
+                return Err(response_error(res, "PUT").await);
+            }
+
+            match res.headers().get("docker-content-digest") {
+                Some(content_digest_value) => Ok(content_digest_value
                     .to_str()
                     .map_err(|e| Error::from(format!("{}", e)))?
-                    .to_string(),
-            ),
-            None => {
-                debug!("cannot find manifestref in headers");
-                None
+                    .to_string()),
+                None => {
+                    debug!("cannot find manifestref in headers, computing it");
+                    Ok(format!("sha256:{:x}", sha2::Sha256::digest(&body)))
+                }
             }
-        };
+        }
+        .instrument(span)
+        .await
+    }
 
-        let header_content_type = headers.get(header::CONTENT_TYPE);
-        let media_type = evaluate_media_type(header_content_type, &url)?;
+    /// Delete an image manifest.
+    ///
+    /// Registries only allow deleting manifests by digest, not by tag.
+    pub async fn delete_manifest(&self, name: &str, digest: &str) -> Result<()> {
+        let digest = ContentDigest::try_new(digest.to_string())
+            .chain_err(|| "manifests can only be deleted by digest")?;
+        let url = self.build_url(name, &digest.to_string())?;
 
-        trace!(
-            "content-type: {:?}, media-type: {:?}",
-            header_content_type,
-            media_type
-        );
+        self.refresh_auth_if_needed().await?;
+        let res = self
+            .send_with_escalation(|| self.build_reqwest(Method::DELETE, url.clone()))
+            .await?;
 
-        match media_type {
-            mediatypes::MediaTypes::ManifestV2S1Signed => Ok((
-                res.json::<ManifestSchema1Signed>()
-                    .await
-                    .map(Manifest::S1Signed)?,
-                content_digest,
-            )),
-            mediatypes::MediaTypes::ManifestV2S2 => {
-                let m = res.json::<ManifestSchema2Spec>().await?;
-                Ok((
-                    m.fetch_config_blob(client_spare0, name.to_string())
-                        .await
-                        .map(Manifest::S2)?,
-                    content_digest,
-                ))
+        let status = res.status();
+        trace!("DELETE '{}' status: {:?}", res.url(), status);
+
+        match status {
+            StatusCode::ACCEPTED => Ok(()),
+            StatusCode::NOT_FOUND => {
+                Err(ErrorKind::NotFound(format!("{}@{}", name, digest)).into())
             }
-            mediatypes::MediaTypes::ManifestList => Ok((
-                res.json::<ManifestList>().await.map(Manifest::ML)?,
-                content_digest,
-            )),
-            unsupported => Err(Error::from(format!(
-                "unsupported mediatype '{:?}'",
-                unsupported
-            ))),
+            StatusCode::METHOD_NOT_ALLOWED => {
+                Err(ErrorKind::Unsupported("delete manifest".to_string()).into())
+            }
+            _ => Err(status_error(&res, "DELETE")),
         }
     }
 
+    /// Send a manifest request, accepting the configured media types, and
+    /// manifest lists if `lists` is set.
+    ///
+    /// Some registries answer `406 Not Acceptable` to Accept headers listing
+    /// media types they do not know, e.g. OCI ones. For compatibility, such
+    /// requests are sent again accepting only schema2 manifests, and lists.
+    async fn send_manifest_request(
+        &self,
+        method: Method,
+        url: &Url,
+        lists: bool,
+    ) -> Result<reqwest::Response> {
+        let accept_headers =
+            build_accept_headers(&self.index, self.accept_media_types.as_deref(), lists);
+        let res = self.send_accepting(&method, url, &accept_headers).await?;
+        if res.status() != StatusCode::NOT_ACCEPTABLE {
+            return Ok(res);
+        }
+
+        debug!(
+            "{} '{}' not acceptable, retrying with schema2 manifests only",
+            method, url
+        );
+        let schema2: &[_] = if lists {
+            &[
+                mediatypes::MediaTypes::ManifestV2S2,
+                mediatypes::MediaTypes::ManifestList,
+            ]
+        } else {
+            &[mediatypes::MediaTypes::ManifestV2S2]
+        };
+        let accept_headers = build_accept_headers(&self.index, Some(schema2), false);
+        self.send_accepting(&method, url, &accept_headers).await
+    }
+
+    /// Send a manifest request with the given `Accept` header, renewing the
+    /// token first if it is about to expire.
+    async fn send_accepting(
+        &self,
+        method: &Method,
+        url: &Url,
+        accept_headers: &header::HeaderMap,
+    ) -> Result<reqwest::Response> {
+        self.refresh_auth_if_needed().await?;
+        self.send_with_escalation(|| {
+            self.build_reqwest(method.clone(), url.clone())
+                .headers(accept_headers.clone())
+        })
+        .await
+    }
+
     fn build_url(&self, name: &str, reference: &str) -> Result<Url> {
+        validate_name(name)?;
         let ep = format!(
-            "{}/v2/{}/manifests/{}",
-            self.base_url.clone(),
-            name,
-            reference
+            "{}{}/{}/manifests/{}",
+            self.base_url, self.api_base_path, name, reference
         );
         reqwest::Url::parse(&ep)
             .map_err(|e| format!("failed to parse url from string '{}': {}", ep, e).into())
     }
 
     /// Fetch content digest for a particular tag.
+    ///
+    /// A missing manifest is reported as `ErrorKind::NotFound`.
     pub async fn get_manifestref(&self, name: &str, reference: &str) -> Result<Option<String>> {
-        let url = self.build_url(name, reference)?;
-
-        let accept_headers = build_accept_headers(&self.index);
+        self.fetch_manifestref(name, reference, false).await
+    }
 
+    /// Fetch a content digest, accepting manifest lists if `lists` is set.
+    async fn fetch_manifestref(
+        &self,
+        name: &str,
+        reference: &str,
+        lists: bool,
+    ) -> Result<Option<String>> {
+        let url = self.build_url(name, reference)?;
         let res = self
-            .build_reqwest(Method::HEAD, url)
-            .headers(accept_headers)
-            .send()
+            .send_manifest_request(Method::HEAD, &url, lists)
             .await?;
 
         let status = res.status();
         trace!("HEAD '{}' status: {:?}", res.url(), status);
 
-        match status {
-            StatusCode::OK => {}
-            _ => return Err(format!("HEAD {}: wrong HTTP status '{}'", res.url(), status).into()),
+        if status != StatusCode::OK {
+            return Err(fetch_error(res, "HEAD", format!("{}:{}", name, reference)).await);
         }
 
         let headers = res.headers();
@@ -149,16 +404,34 @@ impl Client {
         Ok(content_digest)
     }
 
-    /// Check if an image manifest exists.
+    /// Return the digest of a manifest, without downloading the image.
+    ///
+    /// The digest is taken from the `Docker-Content-Digest` header of a `HEAD`
+    /// request. Registries which do not send it are asked for the manifest,
+    /// whose digest is then computed over the raw bytes as served. For
+    /// multi-platform tags, this is the digest of the manifest list or index.
+    pub async fn digest_of(&self, name: &str, reference: &str) -> Result<String> {
+        if let Some(digest) = self.fetch_manifestref(name, reference, true).await? {
+            return Ok(digest);
+        }
+
+        let raw = self.fetch_raw_manifest(name, reference, true).await?;
+        Ok(format!("sha256:{:x}", sha2::Sha256::digest(&raw.body)))
+    }
+
+    /// Check if an image manifest exists, and return its media type and digest.
     ///
     /// The name and reference parameters identify the image.
     /// The reference may be either a tag or digest.
+    /// This issues a `HEAD` request, so the manifest body is not transferred.
+    /// The digest is `None` if the registry omits the `Docker-Content-Digest`
+    /// header; `digest_of` computes it from the manifest in that case.
     pub async fn has_manifest(
         &self,
         name: &str,
         reference: &str,
         mediatypes: Option<&[&str]>,
-    ) -> Result<Option<mediatypes::MediaTypes>> {
+    ) -> Result<Option<(mediatypes::MediaTypes, Option<String>)>> {
         let url = self.build_url(name, reference)?;
         let accept_types = match {
             match mediatypes {
@@ -193,32 +466,45 @@ impl Client {
 
         trace!("HEAD {:?}", url);
 
+        self.refresh_auth_if_needed().await?;
         let r = self
-            .build_reqwest(Method::GET, url.clone())
-            .headers(accept_headers)
-            .send()
-            .await
-            .map_err(Error::from)?;
+            .send_with_escalation(|| {
+                self.build_reqwest(Method::HEAD, url.clone())
+                    .headers(accept_headers.clone())
+            })
+            .await?;
 
         let status = r.status();
-        let media_type = evaluate_media_type(r.headers().get(header::CONTENT_TYPE), &r.url())?;
-
         trace!(
-            "Manifest check status '{:?}', headers '{:?}, media-type: {:?}",
-            r.status(),
+            "Manifest check status '{:?}', headers '{:?}",
+            status,
             r.headers(),
-            media_type
         );
 
-        let res = match status {
+        match status {
             StatusCode::MOVED_PERMANENTLY
             | StatusCode::TEMPORARY_REDIRECT
             | StatusCode::FOUND
-            | StatusCode::OK => Some(media_type),
-            StatusCode::NOT_FOUND => None,
-            _ => bail!("has_manifest: wrong HTTP status '{}'", &status),
+            | StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Ok(None),
+            _ => return Err(status_error(&r, "has_manifest: HEAD")),
         };
-        Ok(res)
+
+        let media_type = evaluate_media_type(r.headers().get(header::CONTENT_TYPE), r.url())?;
+        let content_digest = match r.headers().get("docker-content-digest") {
+            Some(content_digest_value) => Some(
+                content_digest_value
+                    .to_str()
+                    .map_err(|e| Error::from(format!("{}", e)))?
+                    .to_string(),
+            ),
+            None => {
+                debug!("has_manifest: missing Docker-Content-Digest header");
+                None
+            }
+        };
+
+        Ok(Some((media_type, content_digest)))
     }
 }
 
@@ -286,27 +572,46 @@ fn evaluate_media_type(
     }
 }
 
-fn build_accept_headers(registry: &str) -> header::HeaderMap {
+/// Build the `Accept` header for manifest requests.
+///
+/// Explicit media types are sent as they are. Otherwise, the default image
+/// manifest types are sent, along with manifest lists if `lists` is set.
+pub(crate) fn build_accept_headers(
+    registry: &str,
+    media_types: Option<&[mediatypes::MediaTypes]>,
+    lists: bool,
+) -> header::HeaderMap {
     // GCR incorrectly parses `q` parameters, so we use special Accept for it.
     // Bug: https://issuetracker.google.com/issues/159827510.
     // TODO: when bug is fixed, this workaround should be removed.
     let no_q = registry == "gcr.io" || registry.ends_with(".gcr.io");
 
-    let accepted_types = vec![
-        // accept header types and their q value, as documented in
-        // https://tools.ietf.org/html/rfc7231#section-5.3.2
-        (mediatypes::MediaTypes::ManifestV2S2, 0.5),
-        (mediatypes::MediaTypes::ManifestV2S1Signed, 0.4),
-        // TODO(steveeJ): uncomment this when all the Manifest methods work for it
-        // mediatypes::MediaTypes::ManifestList,
-    ];
+    let accepted_types = match media_types {
+        // Preferred types come first, with decreasing q values.
+        Some(media_types) => media_types
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| {
+                (
+                    ty.clone(),
+                    f64::from(10u8.saturating_sub(i as u8).max(1)) / 10.0,
+                )
+            })
+            .collect(),
+        None if lists => {
+            let mut types = default_accepted_types();
+            types.extend(list_accepted_types());
+            types
+        }
+        None => default_accepted_types(),
+    };
 
     let accepted_types_string = accepted_types
         .into_iter()
         .map(|(ty, q)| {
             format!(
                 "{}{}",
-                ty.to_string(),
+                ty,
                 if no_q {
                     String::default()
                 } else {
@@ -325,23 +630,102 @@ fn build_accept_headers(registry: &str) -> header::HeaderMap {
     )])
 }
 
+/// Media types accepted by default when fetching manifests, with their q value.
+fn default_accepted_types() -> Vec<(mediatypes::MediaTypes, f64)> {
+    vec![
+        // accept header types and their q value, as documented in
+        // https://tools.ietf.org/html/rfc7231#section-5.3.2
+        (mediatypes::MediaTypes::ManifestV2S2, 0.5),
+        (mediatypes::MediaTypes::OciImageManifestV1, 0.5),
+        (mediatypes::MediaTypes::ManifestV2S1Signed, 0.4),
+        (mediatypes::MediaTypes::ManifestV2S1, 0.3),
+    ]
+}
+
+/// Manifest list media types, accepted when resolving them, with their q value.
+fn list_accepted_types() -> Vec<(mediatypes::MediaTypes, f64)> {
+    vec![
+        (mediatypes::MediaTypes::ManifestList, 0.5),
+        (mediatypes::MediaTypes::OciImageIndexV1, 0.5),
+    ]
+}
+
+/// A fetched manifest, with the metadata returned by the registry.
+#[derive(Debug)]
+pub struct ManifestResponse {
+    pub manifest: Manifest,
+    pub media_type: mediatypes::MediaTypes,
+    /// Content digest from the `Docker-Content-Digest` header, if any.
+    pub digest: Option<String>,
+}
+
+/// A fetched manifest, as served by the registry.
+#[derive(Debug)]
+pub struct RawManifest {
+    /// Response body, untouched.
+    pub body: Vec<u8>,
+    pub media_type: mediatypes::MediaTypes,
+    /// Content digest from the `Docker-Content-Digest` header, if any.
+    pub digest: Option<String>,
+}
+
 /// Umbrella type for common actions on the different manifest schema types
+// Boxing the schema2 variant would break matching on it.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum Manifest {
     S1Signed(manifest_schema1::ManifestSchema1Signed),
+    /// Unsigned schema1 manifest, which has no `signatures`.
+    S1(manifest_schema1::ManifestSchema1Signed),
     S2(manifest_schema2::ManifestSchema2),
     ML(manifest_schema2::ManifestList),
 }
 
 impl Manifest {
+    /// The media type of this manifest.
+    pub fn media_type(&self) -> mediatypes::MediaTypes {
+        match self {
+            Manifest::S1Signed(_) => mediatypes::MediaTypes::ManifestV2S1Signed,
+            Manifest::S1(_) => mediatypes::MediaTypes::ManifestV2S1,
+            Manifest::S2(m)
+                if m.manifest_spec.media_type
+                    == mediatypes::MediaTypes::OciImageManifestV1.to_string() =>
+            {
+                mediatypes::MediaTypes::OciImageManifestV1
+            }
+            Manifest::S2(_) => mediatypes::MediaTypes::ManifestV2S2,
+            Manifest::ML(m)
+                if m.media_type == mediatypes::MediaTypes::OciImageIndexV1.to_string() =>
+            {
+                mediatypes::MediaTypes::OciImageIndexV1
+            }
+            Manifest::ML(_) => mediatypes::MediaTypes::ManifestList,
+        }
+    }
+
+    /// Serialize this manifest to JSON, as uploaded to a registry.
+    ///
+    /// Signed schema1 manifests are not supported, as re-serializing them
+    /// would invalidate their signatures. Schema1 is deprecated, so unsigned
+    /// schema1 manifests are read-only too.
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        match self {
+            Manifest::S1Signed(_) => bail!("serializing signed schema1 manifests is not supported"),
+            Manifest::S1(_) => bail!("serializing schema1 manifests is not supported"),
+            Manifest::S2(m) => serde_json::to_vec(&m.manifest_spec).map_err(Into::into),
+            Manifest::ML(m) => serde_json::to_vec(m).map_err(Into::into),
+        }
+    }
+
     /// List digests of all layers referenced by this manifest, if available.
     ///
     /// The returned layers list is ordered starting with the base image first.
     pub fn layers_digests(&self, architecture: Option<&str>) -> Result<Vec<String>> {
         match (self, self.architectures(), architecture) {
-            (Manifest::S1Signed(m), _, None) => Ok(m.get_layers()),
+            (Manifest::S1Signed(m), _, None) | (Manifest::S1(m), _, None) => Ok(m.get_layers()),
             (Manifest::S2(m), _, None) => Ok(m.get_layers()),
-            (Manifest::S1Signed(m), Ok(ref self_architectures), Some(ref a)) => {
+            (Manifest::S1Signed(m), Ok(ref self_architectures), Some(ref a))
+            | (Manifest::S1(m), Ok(ref self_architectures), Some(ref a)) => {
                 let self_a = self_architectures
                     .first()
                     .ok_or("no architecture in manifest")?;
@@ -364,10 +748,56 @@ impl Manifest {
         }
     }
 
+    /// Descriptors of all layers referenced by this manifest.
+    ///
+    /// The returned layers list is ordered starting with the base image first.
+    /// Manifest lists need to be resolved to a platform first, and signed
+    /// schema1 manifests do not carry layer descriptors.
+    pub fn layers(&self) -> Result<Vec<LayerDescriptor>> {
+        match self {
+            Manifest::S2(m) => Ok(m.manifest_spec.layers().to_vec()),
+            _ => Err(format!(
+                "Manifest {:?} doesn't support the 'layers' method",
+                self.media_type()
+            )
+            .into()),
+        }
+    }
+
+    /// Descriptor of the config blob referenced by this manifest.
+    pub fn config(&self) -> Result<manifest_schema2::Config> {
+        match self {
+            Manifest::S2(m) => Ok(m.manifest_spec.config().clone()),
+            _ => Err(format!(
+                "Manifest {:?} doesn't support the 'config' method",
+                self.media_type()
+            )
+            .into()),
+        }
+    }
+
+    /// Total size in bytes of the layers and config blob referenced by this manifest.
+    pub fn total_size(&self) -> Result<u64> {
+        let layers_size = self.layers()?.iter().map(|l| l.size).sum::<u64>();
+        Ok(layers_size + self.config()?.size)
+    }
+
+    /// Value of the given annotation of this manifest, if set.
+    ///
+    /// Only OCI image manifests and indexes carry annotations.
+    pub fn annotation(&self, key: &str) -> Option<&str> {
+        let annotations = match self {
+            Manifest::S1Signed(_) | Manifest::S1(_) => None,
+            Manifest::S2(m) => m.manifest_spec.get_annotations(),
+            Manifest::ML(m) => m.annotations.as_ref(),
+        };
+        annotations.and_then(|a| a.get(key)).map(String::as_str)
+    }
+
     /// The architectures of the image the manifest points to, if available.
     pub fn architectures(&self) -> Result<Vec<String>> {
         match self {
-            Manifest::S1Signed(m) => Ok([m.architecture.clone()].to_vec()),
+            Manifest::S1Signed(m) | Manifest::S1(m) => Ok([m.architecture.clone()].to_vec()),
             Manifest::S2(m) => Ok([m.architecture()].to_vec()),
             // Manifest::ML(_) => TODO(steveeJ),
             _ => Err(format!(
@@ -378,3 +808,42 @@ impl Manifest {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_headers_follow_preference() {
+        let preferred = [
+            mediatypes::MediaTypes::OciImageIndexV1,
+            mediatypes::MediaTypes::ManifestList,
+        ];
+
+        let headers = build_accept_headers("quay.io", Some(&preferred), true);
+        assert_eq!(
+            headers[header::ACCEPT],
+            "application/vnd.oci.image.index.v1+json; q=1,application/vnd.docker.distribution.manifest.list.v2+json; q=0.9"
+        );
+
+        let headers = build_accept_headers("gcr.io", Some(&preferred), false);
+        assert_eq!(
+            headers[header::ACCEPT],
+            "application/vnd.oci.image.index.v1+json,application/vnd.docker.distribution.manifest.list.v2+json"
+        );
+    }
+
+    #[test]
+    fn accept_headers_include_manifest_lists_on_request() {
+        let headers = build_accept_headers("registry-1.docker.io", None, false);
+        let accept = headers[header::ACCEPT].to_str().unwrap();
+        assert!(accept.contains("application/vnd.docker.distribution.manifest.v2+json; q=0.5"));
+        assert!(!accept.contains("manifest.list.v2"));
+        assert!(!accept.contains("image.index.v1"));
+
+        let headers = build_accept_headers("registry-1.docker.io", None, true);
+        let accept = headers[header::ACCEPT].to_str().unwrap();
+        assert!(accept.contains("application/vnd.docker.distribution.manifest.list.v2+json; q=0.5"));
+        assert!(accept.contains("application/vnd.oci.image.index.v1+json; q=0.5"));
+    }
+}
@@ -0,0 +1,132 @@
+//! Hooks to collect metrics about the requests sent to registries.
+
+use crate::v2::*;
+use std::fmt::Debug;
+
+/// Receiver of metrics about the requests sent to a registry.
+///
+/// Methods are called inline and should not block. All of them
+/// do nothing by default.
+pub trait Metrics: Debug + Send + Sync {
+    /// Called once a request to the registry completed or failed.
+    fn request(&self, _event: &RequestEvent) {}
+
+    /// Called once a blob has been transferred, with its size in bytes.
+    fn blob_transfer(&self, _operation: BlobOperation, _bytes: u64) {}
+}
+
+/// Metrics receiver ignoring all metrics.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopMetrics;
+
+impl Metrics for NoopMetrics {}
+
+/// A request sent to a registry.
+#[derive(Debug)]
+pub struct RequestEvent<'a> {
+    pub method: &'a Method,
+    pub endpoint: Endpoint,
+    /// Response status, or `None` if no response was received.
+    pub status: Option<StatusCode>,
+    /// Size of the request body.
+    pub bytes_sent: u64,
+    /// Size of the response body, if announced by the registry.
+    pub bytes_received: Option<u64>,
+}
+
+/// Kind of API endpoint a request is sent to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Endpoint {
+    /// API version check.
+    Base,
+    Catalog,
+    Tags,
+    Manifest,
+    Blob,
+    BlobUpload,
+    Referrers,
+    Other,
+}
+
+impl Endpoint {
+    /// Classify a request from its URL path, relative to the API base path.
+    pub(crate) fn from_path(path: &str) -> Self {
+        if path == "/" {
+            Endpoint::Base
+        } else if path == "/_catalog" {
+            Endpoint::Catalog
+        } else if path.contains("/blobs/uploads/") {
+            Endpoint::BlobUpload
+        } else if path.contains("/blobs/") {
+            Endpoint::Blob
+        } else if path.contains("/manifests/") {
+            Endpoint::Manifest
+        } else if path.contains("/referrers/") {
+            Endpoint::Referrers
+        } else if path.ends_with("/tags/list") {
+            Endpoint::Tags
+        } else {
+            Endpoint::Other
+        }
+    }
+}
+
+/// Direction of a blob transfer.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BlobOperation {
+    Download,
+    Upload,
+}
+
+impl Client {
+    /// Report a request to the metrics receiver.
+    pub(crate) fn record_request(
+        &self,
+        method: &Method,
+        url: &Url,
+        bytes_sent: u64,
+        response: Option<&reqwest::Response>,
+    ) {
+        self.metrics.request(&RequestEvent {
+            method,
+            endpoint: url
+                .path()
+                .strip_prefix(&self.api_base_path)
+                .map_or(Endpoint::Other, Endpoint::from_path),
+            status: response.map(reqwest::Response::status),
+            bytes_sent,
+            bytes_received: response.and_then(reqwest::Response::content_length),
+        });
+    }
+
+    /// Report a blob download, in bytes, to the metrics receiver.
+    pub(crate) fn record_download(&self, bytes: u64) {
+        self.metrics.blob_transfer(BlobOperation::Download, bytes);
+    }
+
+    /// Report a blob upload, in bytes, to the metrics receiver.
+    pub(crate) fn record_upload(&self, bytes: u64) {
+        self.metrics.blob_transfer(BlobOperation::Upload, bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_from_path() {
+        for (path, endpoint) in &[
+            ("/", Endpoint::Base),
+            ("/_catalog", Endpoint::Catalog),
+            ("/library/busybox/tags/list", Endpoint::Tags),
+            ("/library/busybox/manifests/latest", Endpoint::Manifest),
+            ("/library/busybox/blobs/sha256:00", Endpoint::Blob),
+            ("/library/busybox/blobs/uploads/", Endpoint::BlobUpload),
+            ("/library/busybox/referrers/sha256:00", Endpoint::Referrers),
+            ("/../token", Endpoint::Other),
+        ] {
+            assert_eq!(Endpoint::from_path(path), *endpoint);
+        }
+    }
+}
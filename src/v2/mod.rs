@@ -2,7 +2,7 @@
 //!
 //! This module provides a `Client` which can be used to list
 //! images and tags, to check for the presence of blobs (manifests,
-//! layers and other objects) by digest, and to retrieve them.
+//! layers and other objects) by digest, to retrieve them and to upload them.
 //!
 //! ## Example
 //!
@@ -30,6 +30,8 @@
 use crate::errors::*;
 use futures::prelude::*;
 use reqwest::{Method, StatusCode, Url};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 mod config;
 pub use self::config::Config;
@@ -37,24 +39,93 @@ pub use self::config::Config;
 mod catalog;
 
 mod auth;
+pub use self::auth::{AuthChallenge, AuthKind, AuthStatus, Scope, ScopeBuilder};
 
 pub mod manifest;
 
 mod tags;
 
+mod referrers;
+
 mod blobs;
 
+mod layer_reader;
+pub use self::layer_reader::LayerReader;
+
+mod pull;
+pub use self::pull::PulledImage;
+
+mod upload;
+pub use self::upload::{BlobUpload, UploadedBlob};
+
+mod push;
+pub use self::push::{BlobSource, BlobTransfer, PushPlan};
+
+mod copy;
+pub use self::copy::{copy_image, copy_image_with_progress, CopyProgress};
+
+mod retry;
+pub use self::retry::RetryPolicy;
+
+mod proxy;
+pub use self::proxy::Proxy;
+
+mod name;
+pub use self::name::{normalize_name, validate_name};
+
+mod warnings;
+
+mod ratelimit;
+pub use self::ratelimit::RateLimit;
+
+#[cfg(any(feature = "reqwest-default-tls", feature = "reqwest-rustls"))]
+pub use reqwest::{Certificate, Identity};
+
+#[cfg(feature = "ecr")]
+mod ecr;
+
+#[cfg(feature = "negotiate")]
+mod negotiate;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
 mod content_digest;
 pub(crate) use self::content_digest::ContentDigest;
 
 /// A Client to make outgoing API requests to a registry.
+///
+/// Cloning a `Client` is cheap, and clones reuse the same connections.
 #[derive(Clone, Debug)]
 pub struct Client {
     base_url: String,
+    /// Path of the distribution API, without trailing slash, e.g. `/v2`.
+    api_base_path: String,
     credentials: Option<(String, String)>,
     index: String,
+    mirrors: Vec<Url>,
     user_agent: Option<String>,
-    auth: Option<auth::Auth>,
+    extra_headers: reqwest::header::HeaderMap,
+    auth: Arc<RwLock<Option<auth::Auth>>>,
+    token_refresh_skew: Duration,
+    oauth2_post: bool,
+    token_client_id: Option<String>,
+    token_cache: Option<auth::TokenCache>,
+    auto_escalate_scopes: bool,
+    last_auth_challenge: Option<auth::AuthChallenge>,
+    verify_digests: bool,
+    page_size: Option<u32>,
+    max_manifest_size: usize,
+    accept_media_types: Option<Vec<crate::mediatypes::MediaTypes>>,
+    retry_policy: RetryPolicy,
+    #[cfg(feature = "ecr")]
+    ecr: bool,
+    #[cfg(feature = "metrics")]
+    metrics: Arc<dyn metrics::Metrics>,
+    warning_handler: Option<warnings::WarningHandler>,
+    /// Rate limit last reported by the registry, shared by clones.
+    rate_limit: Arc<RwLock<Option<RateLimit>>>,
+    /// HTTP client built once by `Config::build`, clones share its connection pool.
     client: reqwest::Client,
 }
 
@@ -74,39 +145,81 @@ impl Client {
 
     /// Check whether remote registry supports v2 API.
     pub async fn is_v2_supported(&self) -> Result<bool> {
-        let api_header = "Docker-Distribution-API-Version";
         let api_version = "registry/2.0";
 
+        let b = self.api_version().await?.as_deref() == Some(api_version);
+        trace!("v2 API supported: {:?}", b);
+
+        Ok(b)
+    }
+
+    /// Return the API version advertised by the registry, e.g. `registry/2.0`.
+    ///
+    /// This does not require authentication, as registries also advertise
+    /// their version when answering `401 Unauthorized`.
+    pub async fn api_version(&self) -> Result<Option<String>> {
+        let api_header = "Docker-Distribution-API-Version";
+
         // GET request to bare v2 endpoint.
-        let v2_endpoint = format!("{}/v2/", self.base_url);
-        let request = reqwest::Url::parse(&v2_endpoint)
-            .chain_err(|| format!("failed to parse url string '{}'", &v2_endpoint))
-            .map(|url| {
-                trace!("GET {:?}", url);
-                self.build_reqwest(Method::GET, url)
-            })?;
-
-        let response = request.send().await?;
-
-        let b = match (response.status(), response.headers().get(api_header)) {
-            (StatusCode::OK, Some(x)) => Ok(x == api_version),
-            (StatusCode::UNAUTHORIZED, Some(x)) => Ok(x == api_version),
+        let v2_endpoint = format!("{}{}/", self.base_url, self.api_base_path);
+        let url = reqwest::Url::parse(&v2_endpoint)
+            .chain_err(|| format!("failed to parse url string '{}'", &v2_endpoint))?;
+        trace!("GET {:?}", url);
+
+        let response = self
+            .send_with_retry(&|| self.build_reqwest(Method::GET, url.clone()))
+            .await?;
+
+        let version = match (response.status(), response.headers().get(api_header)) {
+            (StatusCode::OK, Some(x)) | (StatusCode::UNAUTHORIZED, Some(x)) => {
+                Some(x.to_str()?.to_string())
+            }
             (s, v) => {
                 trace!("Got unexpected status {}, header version {:?}", s, v);
-                Ok(false)
+                None
             }
         };
 
-        trace!("v2 API supported: {:?}", b);
+        Ok(version)
+    }
 
-        b
+    /// Create the span of a high-level operation on a repository.
+    fn span(&self, operation: &'static str, repository: &str, reference: &str) -> tracing::Span {
+        info_span!(
+            "registry_operation",
+            operation,
+            registry = %self.index,
+            repository,
+            reference
+        )
     }
 
+    // Without the `metrics` feature, metrics are dropped.
+    #[cfg(not(feature = "metrics"))]
+    fn record_request(&self, _: &Method, _: &Url, _: u64, _: Option<&reqwest::Response>) {}
+
+    #[cfg(not(feature = "metrics"))]
+    fn record_download(&self, _: u64) {}
+
+    #[cfg(not(feature = "metrics"))]
+    fn record_upload(&self, _: u64) {}
+
     /// Takes reqwest's async RequestBuilder and injects an authentication header if a token is present
+    ///
+    /// Extra headers are only sent to the registry itself, not e.g. to the
+    /// token endpoint of another host named by an authentication challenge.
     fn build_reqwest(&self, method: Method, url: Url) -> reqwest::RequestBuilder {
+        let to_registry =
+            matches!(Url::parse(&self.base_url), Ok(base) if base.origin() == url.origin());
         let mut builder = self.client.request(method, url);
 
-        if let Some(auth) = &self.auth {
+        if to_registry {
+            for (name, value) in self.extra_headers.iter() {
+                builder = builder.header(name, value);
+            }
+        }
+
+        if let Some(auth) = self.auth.read().expect("auth lock poisoned").as_ref() {
             builder = auth.add_auth_headers(builder);
         };
 
@@ -118,14 +231,372 @@ impl Client {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
-struct ApiError {
-    code: String,
-    message: String,
-    detail: String,
+/// Convert a request error, reporting timeouts as `ErrorKind::Timeout`,
+/// TLS failures as `ErrorKind::Tls` and too many redirects as `ErrorKind::Redirect`.
+pub(crate) fn request_error(e: reqwest::Error) -> Error {
+    let url = e.url().map(ToString::to_string).unwrap_or_default();
+    if e.is_timeout() {
+        Error::with_chain(e, ErrorKind::Timeout(url))
+    } else if is_tls_error(&e) {
+        Error::with_chain(e, ErrorKind::Tls(url))
+    } else if e.is_redirect() {
+        Error::with_chain(e, ErrorKind::Redirect(url))
+    } else {
+        e.into()
+    }
+}
+
+/// Whether a request failed during the TLS handshake, e.g. on certificate validation.
+///
+/// rustls reports handshake failures as invalid data I/O errors.
+pub(crate) fn is_tls_error(e: &reqwest::Error) -> bool {
+    if !e.is_connect() {
+        return false;
+    }
+    let mut source = std::error::Error::source(e);
+    while let Some(err) = source {
+        #[cfg(feature = "reqwest-default-tls")]
+        {
+            if err.is::<native_tls::Error>() {
+                return true;
+            }
+        }
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            if io.kind() == std::io::ErrorKind::InvalidData {
+                return true;
+            }
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Parse the `Retry-After` header into a delay from now.
+///
+/// Both delay-seconds and HTTP-date values are supported.
+pub(crate) fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(std::time::SystemTime::now())
+            .unwrap_or_default(),
+    )
+}
+
+/// Parse a `Link` header, returning the query of the next page URL.
+///
+/// Format is described at https://docs.docker.com/registry/spec/api/#pagination
+/// and in RFC 5988. The query carries the pagination parameters, e.g. `n` and
+/// `last` (or `next_page` on some registries).
+pub(crate) fn parse_link(hdr: Option<&reqwest::header::HeaderValue>) -> Option<String> {
+    let sval = hdr?.to_str().ok()?;
+
+    sval.split(',').find_map(|link| {
+        let mut params = link.split(';');
+        let uri = params
+            .next()?
+            .trim()
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        let is_next = params.any(|p| {
+            let p = p.trim();
+            p == r#"rel="next""# || p == "rel=next"
+        });
+        if !is_next {
+            return None;
+        }
+
+        uri.split_once('?')
+            .map(|(_, query)| query)
+            .filter(|query| !query.is_empty())
+            .map(ToString::to_string)
+    })
+}
+
+/// Read a response body, failing with `ErrorKind::ResponseTooLarge` once it exceeds `limit` bytes.
+pub(crate) async fn read_body_limited(mut res: reqwest::Response, limit: usize) -> Result<Vec<u8>> {
+    let too_large = |url: &Url| Error::from(ErrorKind::ResponseTooLarge(url.to_string(), limit));
+    if matches!(res.content_length(), Some(len) if len > limit as u64) {
+        return Err(too_large(res.url()));
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(too_large(res.url()));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Build the error for an unexpected response status.
+///
+/// If the body holds a registry error envelope, the errors are reported
+/// as `ErrorKind::Registry`. Unfollowed redirects are reported as
+/// `ErrorKind::Redirect`, with their absolute location.
+pub(crate) async fn response_error(res: reqwest::Response, context: &str) -> Error {
+    let status = res.status();
+    let url = res.url().clone();
+    let headers = ResponseHeaders::from_headers(res.headers());
+    if status.is_redirection() {
+        if let Some(location) = res.headers().get(reqwest::header::LOCATION) {
+            let location = location.to_str().unwrap_or_default();
+            let location = url
+                .join(location)
+                .map(String::from)
+                .unwrap_or_else(|_| location.to_string());
+            return ErrorKind::Redirect(location).into();
+        }
+    }
+    let errors = match res.bytes().await {
+        Ok(body) => serde_json::from_slice::<Errors>(&body)
+            .map(|envelope| envelope.errors)
+            .unwrap_or_default(),
+        Err(e) => {
+            debug!("{}: failed to read error body: {}", context, e);
+            vec![]
+        }
+    };
+
+    if errors.is_empty() {
+        ErrorKind::Status(format!("{} {}", context, url), status, headers).into()
+    } else {
+        ErrorKind::Registry(status.as_u16(), errors, headers).into()
+    }
+}
+
+/// Build the error for an unexpected response status, without reading the body.
+pub(crate) fn status_error(res: &reqwest::Response, context: &str) -> Error {
+    ErrorKind::Status(
+        format!("{} {}", context, res.url()),
+        res.status(),
+        ResponseHeaders::from_headers(res.headers()),
+    )
+    .into()
+}
+
+/// Build the error for an unexpected response status to a fetch of `resource`.
+///
+/// A 404 is reported as `ErrorKind::NotFound`, chained to the registry errors if any.
+pub(crate) async fn fetch_error(res: reqwest::Response, context: &str, resource: String) -> Error {
+    let status = res.status();
+    let error = response_error(res, context).await;
+    if status == StatusCode::NOT_FOUND {
+        Error::with_chain(error, ErrorKind::NotFound(resource))
+    } else {
+        error
+    }
+}
+
+/// Error code of a registry error, as listed in
+/// https://docs.docker.com/registry/spec/api/#errors-2.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum ErrorCode {
+    BlobUnknown,
+    BlobUploadInvalid,
+    BlobUploadUnknown,
+    DigestInvalid,
+    ManifestBlobUnknown,
+    ManifestInvalid,
+    ManifestUnknown,
+    ManifestUnverified,
+    NameInvalid,
+    NameUnknown,
+    SizeInvalid,
+    TagInvalid,
+    Unauthorized,
+    Denied,
+    Unsupported,
+    TooManyRequests,
+    /// A code not defined by the specification.
+    Other(String),
+}
+
+impl From<String> for ErrorCode {
+    fn from(code: String) -> Self {
+        match code.as_str() {
+            "BLOB_UNKNOWN" => ErrorCode::BlobUnknown,
+            "BLOB_UPLOAD_INVALID" => ErrorCode::BlobUploadInvalid,
+            "BLOB_UPLOAD_UNKNOWN" => ErrorCode::BlobUploadUnknown,
+            "DIGEST_INVALID" => ErrorCode::DigestInvalid,
+            "MANIFEST_BLOB_UNKNOWN" => ErrorCode::ManifestBlobUnknown,
+            "MANIFEST_INVALID" => ErrorCode::ManifestInvalid,
+            "MANIFEST_UNKNOWN" => ErrorCode::ManifestUnknown,
+            "MANIFEST_UNVERIFIED" => ErrorCode::ManifestUnverified,
+            "NAME_INVALID" => ErrorCode::NameInvalid,
+            "NAME_UNKNOWN" => ErrorCode::NameUnknown,
+            "SIZE_INVALID" => ErrorCode::SizeInvalid,
+            "TAG_INVALID" => ErrorCode::TagInvalid,
+            "UNAUTHORIZED" => ErrorCode::Unauthorized,
+            "DENIED" => ErrorCode::Denied,
+            "UNSUPPORTED" => ErrorCode::Unsupported,
+            "TOOMANYREQUESTS" => ErrorCode::TooManyRequests,
+            _ => ErrorCode::Other(code),
+        }
+    }
+}
+
+impl From<ErrorCode> for String {
+    fn from(code: ErrorCode) -> Self {
+        code.to_string()
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let code = match self {
+            ErrorCode::BlobUnknown => "BLOB_UNKNOWN",
+            ErrorCode::BlobUploadInvalid => "BLOB_UPLOAD_INVALID",
+            ErrorCode::BlobUploadUnknown => "BLOB_UPLOAD_UNKNOWN",
+            ErrorCode::DigestInvalid => "DIGEST_INVALID",
+            ErrorCode::ManifestBlobUnknown => "MANIFEST_BLOB_UNKNOWN",
+            ErrorCode::ManifestInvalid => "MANIFEST_INVALID",
+            ErrorCode::ManifestUnknown => "MANIFEST_UNKNOWN",
+            ErrorCode::ManifestUnverified => "MANIFEST_UNVERIFIED",
+            ErrorCode::NameInvalid => "NAME_INVALID",
+            ErrorCode::NameUnknown => "NAME_UNKNOWN",
+            ErrorCode::SizeInvalid => "SIZE_INVALID",
+            ErrorCode::TagInvalid => "TAG_INVALID",
+            ErrorCode::Unauthorized => "UNAUTHORIZED",
+            ErrorCode::Denied => "DENIED",
+            ErrorCode::Unsupported => "UNSUPPORTED",
+            ErrorCode::TooManyRequests => "TOOMANYREQUESTS",
+            ErrorCode::Other(code) => code,
+        };
+        f.write_str(code)
+    }
+}
+
+/// An error reported by the registry in a response body.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RegistryError {
+    pub code: ErrorCode,
+    #[serde(default)]
+    pub message: String,
+    /// Unstructured details, whose content depends on the error code.
+    #[serde(default)]
+    pub detail: Option<serde_json::Value>,
+}
+
+impl std::fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+/// Response headers kept on errors, to help debugging with the registry operator.
+///
+/// Only the content digest, request id and rate-limit headers are kept.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResponseHeaders(Vec<(String, String)>);
+
+impl ResponseHeaders {
+    pub(crate) fn from_headers(headers: &reqwest::header::HeaderMap) -> Self {
+        let kept = headers
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.as_str();
+                name == "docker-content-digest"
+                    || name == "x-request-id"
+                    || name.starts_with("ratelimit-")
+            })
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        Self(kept)
+    }
+
+    /// Get the value of a kept header, by case-insensitive name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Iterate over the kept headers, as pairs of lowercase name and value.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Whether none of the kept headers were in the response.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Display for ResponseHeaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let headers = self
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect::<Vec<_>>();
+        write!(f, " ({})", headers.join(", "))
+    }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 struct Errors {
-    errors: Vec<ApiError>,
+    errors: Vec<RegistryError>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_errors_parse() -> Result<()> {
+        let body = r#"{"errors": [
+            {"code": "MANIFEST_UNKNOWN", "message": "manifest unknown", "detail": {"Tag": "v1"}},
+            {"code": "SOMETHING_ELSE", "message": "other"}
+        ]}"#;
+        let envelope = serde_json::from_str::<Errors>(body)?;
+
+        assert_eq!(envelope.errors[0].code, ErrorCode::ManifestUnknown);
+        assert_eq!(
+            envelope.errors[0].to_string(),
+            "MANIFEST_UNKNOWN: manifest unknown"
+        );
+        assert_eq!(
+            envelope.errors[1].code,
+            ErrorCode::Other("SOMETHING_ELSE".to_string())
+        );
+        assert_eq!(envelope.errors[1].detail, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn response_headers_keep_debugging_headers() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("X-Request-Id", "abc".parse().unwrap());
+        headers.insert("RateLimit-Remaining", "0".parse().unwrap());
+        headers.insert("Content-Type", "application/json".parse().unwrap());
+
+        let kept = ResponseHeaders::from_headers(&headers);
+        assert_eq!(kept.get("x-request-id"), Some("abc"));
+        assert_eq!(kept.get("RateLimit-Remaining"), Some("0"));
+        assert_eq!(kept.get("content-type"), None);
+        assert_eq!(kept.iter().count(), 2);
+
+        let error = Error::from(ErrorKind::Status(
+            "GET /v2/".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+            kept,
+        ));
+        assert_eq!(
+            error.to_string(),
+            "GET /v2/: wrong HTTP status '503 Service Unavailable' (x-request-id: abc, ratelimit-remaining: 0)"
+        );
+        assert!(ResponseHeaders::default().to_string().is_empty());
+    }
 }
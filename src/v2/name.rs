@@ -0,0 +1,126 @@
+//! Validation of repository names.
+
+use crate::errors::{ErrorKind, Result};
+
+/// Check a repository name against the distribution spec grammar.
+///
+/// Names are made of path components separated by `/`. Components are
+/// lowercase letters and digits, separated by `.`, `_`, `__` or any number
+/// of `-`. See the [OCI distribution spec](https://github.com/opencontainers/distribution-spec/blob/v1.0.1/spec.md#pulling-manifests).
+pub fn validate_name(name: &str) -> Result<()> {
+    let invalid = |reason: String| Err(ErrorKind::InvalidName(name.to_string(), reason).into());
+
+    if name.is_empty() {
+        return invalid("empty name".to_string());
+    }
+    if name.len() > 255 {
+        return invalid(format!("{} characters, at most 255 allowed", name.len()));
+    }
+    for component in name.split('/') {
+        if let Err(reason) = validate_component(component) {
+            return invalid(reason);
+        }
+    }
+    Ok(())
+}
+
+fn validate_component(component: &str) -> std::result::Result<(), String> {
+    if component.is_empty() {
+        return Err("empty path component".to_string());
+    }
+    if let Some(c) = component
+        .chars()
+        .find(|c| !matches!(c, 'a'..='z' | '0'..='9' | '.' | '_' | '-'))
+    {
+        return Err(if c.is_ascii_uppercase() {
+            format!("uppercase letter '{}' in '{}'", c, component)
+        } else {
+            format!("invalid character '{}' in '{}'", c, component)
+        });
+    }
+
+    let is_alphanumeric = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    if !component.starts_with(is_alphanumeric) || !component.ends_with(is_alphanumeric) {
+        return Err(format!(
+            "'{}' must start and end with a lowercase letter or digit",
+            component
+        ));
+    }
+    for separator in component.split(is_alphanumeric).filter(|s| !s.is_empty()) {
+        let valid = matches!(separator, "." | "_" | "__") || separator.chars().all(|c| c == '-');
+        if !valid {
+            return Err(format!(
+                "invalid separator '{}' in '{}'",
+                separator, component
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Normalize a repository name, returning an error if it is still invalid.
+///
+/// Letters are lowercased, surrounding whitespace and slashes are trimmed
+/// and repeated slashes collapsed.
+pub fn normalize_name(name: &str) -> Result<String> {
+    let normalized = name
+        .trim()
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+        .to_lowercase();
+    validate_name(&normalized)?;
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_names() {
+        for name in &[
+            "busybox",
+            "library/busybox",
+            "quay.io-mirror/a/b/c",
+            "foo_bar/baz__qux",
+            "my--image.v1/0",
+        ] {
+            assert!(validate_name(name).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn invalid_names() {
+        for name in &[
+            "",
+            "Library/busybox",
+            "library//busybox",
+            "/busybox",
+            "busybox/",
+            "bus box",
+            "busybox:latest",
+            "-busybox",
+            "busybox.",
+            "foo..bar",
+            "foo___bar",
+            "foo._bar",
+        ] {
+            match validate_name(name) {
+                Err(crate::errors::Error(ErrorKind::InvalidName(..), _)) => {}
+                other => panic!("{:?}: unexpected {:?}", name, other),
+            }
+        }
+        assert!(validate_name(&"a".repeat(256)).is_err());
+    }
+
+    #[test]
+    fn names_are_normalized() {
+        assert_eq!(
+            normalize_name(" /Library//BusyBox/ ").unwrap(),
+            "library/busybox"
+        );
+        assert!(normalize_name("library/bus box").is_err());
+    }
+}
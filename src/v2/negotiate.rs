@@ -0,0 +1,262 @@
+//! SPNEGO tokens for `Negotiate` (Kerberos) HTTP authentication, see RFC 4559.
+//!
+//! Security contexts are established through the system GSSAPI library,
+//! using the default credentials of the current user (e.g. from `kinit`)
+//! for the `HTTP@<host>` service principal.
+
+use crate::errors::{Result, ResultExt};
+use crate::v2::*;
+use libgssapi::context::{ClientCtx, CtxFlags, SecurityContext};
+use libgssapi::name::Name;
+use libgssapi::oid::{GSS_MECH_SPNEGO, GSS_NT_HOSTBASED_SERVICE};
+use reqwest::header::{HeaderMap, AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{StatusCode, Url};
+
+/// Upper bound on the tokens sent during a single handshake.
+const MAX_HANDSHAKE_LEGS: usize = 8;
+
+/// Client side of a SPNEGO security context.
+pub(crate) trait NegotiateContext {
+    /// Process the last token from the server, if any, and return the next token to send.
+    ///
+    /// `None` is returned once the context is established.
+    fn step(&mut self, server_token: Option<&[u8]>) -> Result<Option<Vec<u8>>>;
+
+    /// Whether the context is established.
+    fn is_complete(&self) -> bool;
+}
+
+/// Security context backed by the system GSSAPI library.
+pub(crate) struct GssContext {
+    host: String,
+    ctx: ClientCtx,
+}
+
+impl GssContext {
+    /// Create a context for the `HTTP` service of the given registry host.
+    pub(crate) fn new(host: &str) -> Result<Self> {
+        let host = host.split(':').next().unwrap_or(host);
+        let service = format!("HTTP@{}", host);
+        let target = Name::new(service.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE))
+            .chain_err(|| format!("negotiate: failed to import name '{}'", service))?;
+        let ctx = ClientCtx::new(
+            None,
+            target,
+            CtxFlags::GSS_C_MUTUAL_FLAG,
+            Some(&GSS_MECH_SPNEGO),
+        );
+
+        Ok(GssContext {
+            host: host.to_string(),
+            ctx,
+        })
+    }
+}
+
+impl NegotiateContext for GssContext {
+    fn step(&mut self, server_token: Option<&[u8]>) -> Result<Option<Vec<u8>>> {
+        let token = self.ctx.step(server_token, None).chain_err(|| {
+            format!(
+                "negotiate: failed to initialize context for '{}'",
+                self.host
+            )
+        })?;
+        Ok(token.map(|token| token.to_vec()))
+    }
+
+    fn is_complete(&self) -> bool {
+        self.ctx.is_complete()
+    }
+}
+
+/// Generate a base64-encoded initial SPNEGO token for the given registry host.
+pub(crate) fn spnego_token(host: &str) -> Result<String> {
+    let token = GssContext::new(host)?
+        .step(None)?
+        .ok_or("negotiate: no initial token produced")?;
+    Ok(base64::encode(token))
+}
+
+/// Extract the token of a `WWW-Authenticate: Negotiate <token>` header, if any.
+fn server_token(headers: &HeaderMap) -> Result<Option<Vec<u8>>> {
+    for value in headers.get_all(WWW_AUTHENTICATE) {
+        let value = value.to_str().unwrap_or_default().trim();
+        let mut parts = value.splitn(2, char::is_whitespace);
+        let scheme = parts.next().unwrap_or_default();
+        if !scheme.eq_ignore_ascii_case("negotiate") {
+            continue;
+        }
+        let token = parts.next().unwrap_or_default().trim();
+        if !token.is_empty() {
+            return Ok(Some(base64::decode(token)?));
+        }
+    }
+    Ok(None)
+}
+
+impl Client {
+    /// Establish a security context with the registry.
+    ///
+    /// Tokens are exchanged with the API base endpoint for as long as the
+    /// registry answers with a 401 carrying a continuation token. A final
+    /// token sent along a successful response (mutual authentication) is
+    /// verified too.
+    pub(crate) async fn negotiate<C: NegotiateContext>(&self, context: &mut C) -> Result<()> {
+        let url = {
+            let ep = format!("{}{}/", self.base_url, self.api_base_path);
+            Url::parse(&ep)
+                .map_err(|e| format!("failed to parse url from string '{}': {}", ep, e))?
+        };
+
+        let mut server_token_in = None;
+        for _ in 0..MAX_HANDSHAKE_LEGS {
+            let token = match context.step(server_token_in.as_deref())? {
+                Some(token) => base64::encode(token),
+                None => bail!("negotiate: registry rejected the established context"),
+            };
+
+            let r = self
+                .send_to_primary(&|| {
+                    self.build_reqwest(Method::GET, url.clone())
+                        .header(AUTHORIZATION, format!("Negotiate {}", token))
+                })
+                .await?;
+            trace!("negotiate: GET '{}' status: {:?}", r.url(), r.status());
+
+            let server_token_out = server_token(r.headers())?;
+            match r.status() {
+                StatusCode::OK => {
+                    if let Some(final_token) = server_token_out {
+                        if !context.is_complete() {
+                            context.step(Some(&final_token))?;
+                        }
+                    }
+                    return Ok(());
+                }
+                StatusCode::UNAUTHORIZED
+                    if server_token_out.is_some() && !context.is_complete() =>
+                {
+                    server_token_in = server_token_out;
+                }
+                _ => return Err(response_error(r, "negotiate: GET").await),
+            }
+        }
+
+        bail!(
+            "negotiate: handshake not completed after {} tokens",
+            MAX_HANDSHAKE_LEGS
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    /// Context replaying a fixed exchange of tokens.
+    struct ScriptedContext {
+        /// Expected server token and token to reply with, for each leg.
+        legs: Vec<(Option<&'static str>, Option<&'static str>)>,
+    }
+
+    impl NegotiateContext for ScriptedContext {
+        fn step(&mut self, server_token: Option<&[u8]>) -> Result<Option<Vec<u8>>> {
+            if self.legs.is_empty() {
+                bail!("unexpected step");
+            }
+            let (expected, reply) = self.legs.remove(0);
+            assert_eq!(expected.map(str::as_bytes), server_token);
+            Ok(reply.map(|token| token.as_bytes().to_vec()))
+        }
+
+        fn is_complete(&self) -> bool {
+            self.legs.is_empty()
+        }
+    }
+
+    fn negotiate_header(token: &str) -> String {
+        format!("Negotiate {}", base64::encode(token))
+    }
+
+    #[test]
+    fn server_token_parses() -> Result<()> {
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, server_token(&headers)?);
+
+        headers.append(WWW_AUTHENTICATE, HeaderValue::from_static("Negotiate"));
+        assert_eq!(None, server_token(&headers)?);
+
+        headers.append(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_static("negotiate dG9rZW4="),
+        );
+        assert_eq!(Some(b"token".to_vec()), server_token(&headers)?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn negotiate_follows_continuation_tokens() -> Result<()> {
+        let first = mockito::mock("GET", "/v2/")
+            .match_header("authorization", negotiate_header("client-1").as_str())
+            .with_status(401)
+            .with_header("WWW-Authenticate", &negotiate_header("server-1"))
+            .expect(1)
+            .create();
+        let second = mockito::mock("GET", "/v2/")
+            .match_header("authorization", negotiate_header("client-2").as_str())
+            .with_status(200)
+            .with_header("WWW-Authenticate", &negotiate_header("server-2"))
+            .expect(1)
+            .create();
+
+        let client = Client::configure()
+            .registry(&mockito::server_address().to_string())
+            .insecure_registry(true)
+            .build()?;
+        let mut context = ScriptedContext {
+            legs: vec![
+                (None, Some("client-1")),
+                (Some("server-1"), Some("client-2")),
+                (Some("server-2"), None),
+            ],
+        };
+        client.negotiate(&mut context).await?;
+
+        first.assert();
+        second.assert();
+        assert!(context.is_complete());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn negotiate_stops_on_rejected_token() -> Result<()> {
+        let rejected = mockito::mock("GET", "/v2/")
+            .match_header("authorization", negotiate_header("rejected").as_str())
+            .with_status(401)
+            .with_header("WWW-Authenticate", "Negotiate")
+            .expect(1)
+            .create();
+
+        let client = Client::configure()
+            .registry(&mockito::server_address().to_string())
+            .insecure_registry(true)
+            .build()?;
+        let mut context = ScriptedContext {
+            legs: vec![(None, Some("rejected"))],
+        };
+        let err = client.negotiate(&mut context).await.unwrap_err();
+
+        rejected.assert();
+        match err.kind() {
+            crate::errors::ErrorKind::Status(_, status, _) => {
+                assert_eq!(StatusCode::UNAUTHORIZED, *status)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use crate::errors::Result;
+use crate::v2::manifest::{ImageConfig, LayerDescriptor, Manifest, Platform};
+use crate::v2::*;
+use tokio::io::AsyncWrite;
+
+/// An image resolved for a platform, with its parsed config.
+///
+/// Layers are not downloaded until requested, one at a time.
+#[derive(Debug)]
+pub struct PulledImage {
+    client: Client,
+    name: String,
+    layers: Vec<LayerDescriptor>,
+    pub manifest: Manifest,
+    pub config: ImageConfig,
+}
+
+impl PulledImage {
+    /// Get the descriptors of the image layers.
+    ///
+    /// The returned layers list is ordered starting with the base image first.
+    pub fn layers(&self) -> &[LayerDescriptor] {
+        &self.layers
+    }
+
+    /// Download a layer, reading its uncompressed content as it arrives.
+    ///
+    /// See `Client::get_layer_reader`.
+    pub async fn layer_reader(&self, layer: &LayerDescriptor) -> Result<LayerReader> {
+        self.client.get_layer_reader(&self.name, layer).await
+    }
+
+    /// Stream the compressed content of a layer into a writer.
+    ///
+    /// See `Client::blob_to_writer`.
+    pub async fn layer_to_writer<W>(
+        &self,
+        layer: &LayerDescriptor,
+        writer: &mut W,
+    ) -> Result<(u64, String)>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.client
+            .blob_to_writer(&self.name, &layer.digest, writer)
+            .await
+    }
+}
+
+impl Client {
+    /// Fetch what is needed to reconstruct an image for a platform.
+    ///
+    /// This resolves the manifest for the platform and fetches the image
+    /// config. Layers are then downloaded on demand from the returned image.
+    /// Schema1 manifests are not supported, as they lack layer descriptors.
+    pub async fn pull(
+        &self,
+        name: &str,
+        reference: &str,
+        platform: &Platform,
+    ) -> Result<PulledImage> {
+        let manifest = self.resolve_manifest(name, reference, platform).await?;
+        let layers = manifest.layers()?;
+        let config = self.get_config(name, &manifest).await?;
+
+        Ok(PulledImage {
+            client: self.clone(),
+            name: name.to_string(),
+            layers,
+            manifest,
+            config,
+        })
+    }
+}
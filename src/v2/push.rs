@@ -0,0 +1,174 @@
+use crate::errors::{ErrorKind, Result};
+use crate::mediatypes::MediaTypes;
+use crate::v2::content_digest::ContentDigest;
+use crate::v2::manifest::{Descriptor, Manifest, ManifestSchema2Spec};
+use crate::v2::*;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use tokio::io::AsyncRead;
+
+/// Blobs of an image to push, split by whether the registry already has them.
+#[derive(Debug, Default)]
+pub struct PushPlan {
+    /// Digests of the blobs the repository already has.
+    pub existing: Vec<String>,
+    /// Descriptors of the blobs to upload, config first then layers.
+    pub missing: Vec<Descriptor>,
+}
+
+impl PushPlan {
+    /// Total size in bytes of the blobs to upload.
+    pub fn missing_size(&self) -> u64 {
+        self.missing.iter().map(|d| d.size).sum()
+    }
+}
+
+/// Content of a blob to upload, when it cannot be mounted.
+// Sources are passed once per blob, boxing the client is not worth it.
+#[allow(clippy::large_enum_variant)]
+pub enum BlobSource {
+    /// The blob content.
+    Bytes(Bytes),
+    /// A stream of the blob content.
+    Reader(Box<dyn AsyncRead + Send + Unpin>),
+    /// The blob in a repository of another registry, streamed from there.
+    Registry { client: Client, name: String },
+}
+
+impl std::fmt::Debug for BlobSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BlobSource::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            BlobSource::Reader(_) => f.debug_tuple("Reader").finish(),
+            BlobSource::Registry { client, name } => f
+                .debug_struct("Registry")
+                .field("registry", &client.base_url)
+                .field("name", name)
+                .finish(),
+        }
+    }
+}
+
+/// How `ensure_blob` made a blob available in a repository.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlobTransfer {
+    /// The repository already had the blob.
+    Existing,
+    /// The blob was mounted from the given repository.
+    Mounted(String),
+    /// The blob was uploaded.
+    Uploaded,
+}
+
+impl Client {
+    /// Check which blobs of an image need to be uploaded, without transferring any.
+    ///
+    /// The config and layers referenced by the manifest are checked with
+    /// `has_blob`, on the registry itself rather than on its mirrors. Foreign
+    /// layers are skipped, as they are not pushed to registries. Manifest
+    /// lists need to be planned per image manifest.
+    pub async fn plan_push(&self, name: &str, manifest: &Manifest) -> Result<PushPlan> {
+        let blobs = match manifest {
+            Manifest::S2(m) => push_blobs(&m.manifest_spec),
+            _ => bail!(
+                "plan_push: {:?} manifests have no blobs to push",
+                manifest.media_type()
+            ),
+        };
+
+        let found =
+            future::try_join_all(blobs.iter().map(|b| self.has_blob(name, &b.digest))).await?;
+
+        let mut plan = PushPlan::default();
+        for (blob, found) in blobs.into_iter().zip(found) {
+            if found {
+                plan.existing.push(blob.digest);
+            } else {
+                plan.missing.push(blob);
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Make a blob available in a repository, transferring it only if needed.
+    ///
+    /// Nothing is done if the repository already has the blob, as checked on
+    /// the registry itself rather than on its mirrors. Otherwise it is
+    /// mounted from the first of `from_repos` the registry agrees to mount it
+    /// from, and uploaded from `source` as a last resort. Streamed content is
+    /// checked against the descriptor digest, which has to be a `sha256` one.
+    pub async fn ensure_blob(
+        &self,
+        name: &str,
+        descriptor: &Descriptor,
+        from_repos: &[String],
+        source: BlobSource,
+    ) -> Result<BlobTransfer> {
+        let digest = &descriptor.digest;
+        if self.has_blob(name, digest).await? {
+            trace!("{} already has {}", name, digest);
+            return Ok(BlobTransfer::Existing);
+        }
+
+        for from_repo in from_repos.iter().filter(|repo| *repo != name) {
+            match self.mount_blob(name, digest, from_repo).await {
+                Ok(true) => return Ok(BlobTransfer::Mounted(from_repo.clone())),
+                Ok(false) => {}
+                Err(e) => debug!("mount of {} from {} failed: {}", digest, from_repo, e),
+            }
+        }
+
+        let uploaded = match source {
+            BlobSource::Bytes(bytes) => {
+                self.upload_blob(name, digest, bytes).await?;
+                return Ok(BlobTransfer::Uploaded);
+            }
+            BlobSource::Reader(reader) => self.upload_blob_stream(name, reader).await?,
+            BlobSource::Registry {
+                client,
+                name: source_name,
+            } => {
+                let chunks = client
+                    .blob_chunks(&source_name, &ContentDigest::try_new(digest.clone())?)
+                    .await?
+                    .map_err(|e| std::io::Error::other(e.to_string()))
+                    // Readers may be polled again after reaching the end.
+                    .fuse();
+                self.upload_blob_stream(name, tokio::io::stream_reader(chunks))
+                    .await?
+            }
+        };
+        if uploaded.digest != *digest {
+            bail!(ErrorKind::DigestMismatch(digest.clone(), uploaded.digest));
+        }
+        Ok(BlobTransfer::Uploaded)
+    }
+}
+
+/// List the blobs to push for an image, config first then layers.
+///
+/// Foreign layers are skipped, and repeated layers listed once.
+pub(crate) fn push_blobs(manifest: &ManifestSchema2Spec) -> Vec<Descriptor> {
+    let config = manifest.config();
+    let mut blobs = vec![Descriptor {
+        media_type: config.media_type.clone(),
+        size: config.size,
+        digest: config.digest.clone(),
+        annotations: config.annotations.clone(),
+    }];
+    for layer in manifest.layers() {
+        if layer.get_media_type() == MediaTypes::ImageLayerForeignTgz {
+            continue;
+        }
+        if blobs.iter().any(|b| b.digest == layer.digest) {
+            continue;
+        }
+        blobs.push(Descriptor {
+            media_type: layer.media_type.clone(),
+            size: layer.size,
+            digest: layer.digest.clone(),
+            annotations: layer.annotations.clone(),
+        });
+    }
+    blobs
+}
@@ -0,0 +1,140 @@
+//! Registry rate-limit headers.
+
+use crate::v2::*;
+use reqwest::header::HeaderMap;
+
+/// Rate limit of a registry, as reported by the headers of a response.
+///
+/// Docker Hub reports its pull limit on manifest requests, e.g.
+/// `RateLimit-Limit: 100;w=21600` and `RateLimit-Remaining: 76;w=21600`
+/// for 76 pulls left out of 100 per 6-hour window.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    /// Requests allowed per window.
+    pub limit: Option<u64>,
+    /// Requests left in the current window.
+    pub remaining: Option<u64>,
+    /// Length of the window, from the `w` parameter of the headers.
+    pub window: Option<Duration>,
+    /// Time until the window resets, from `RateLimit-Reset` if sent.
+    pub reset: Option<Duration>,
+    /// What the limit is accounted to, from `Docker-RateLimit-Source`,
+    /// e.g. the client address or the account.
+    pub source: Option<String>,
+    /// The `RateLimit-Limit` header, as sent.
+    pub raw_limit: Option<String>,
+    /// The `RateLimit-Remaining` header, as sent.
+    pub raw_remaining: Option<String>,
+}
+
+impl RateLimit {
+    /// Parse the rate-limit headers of a response, if it has any.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_string())
+        };
+        let raw_limit = header("ratelimit-limit");
+        let raw_remaining = header("ratelimit-remaining");
+        if raw_limit.is_none() && raw_remaining.is_none() {
+            return None;
+        }
+
+        let (limit, limit_window) = raw_limit.as_deref().map(parse_quota).unwrap_or_default();
+        let (remaining, remaining_window) = raw_remaining
+            .as_deref()
+            .map(parse_quota)
+            .unwrap_or_default();
+        Some(RateLimit {
+            limit,
+            remaining,
+            window: limit_window.or(remaining_window),
+            reset: header("ratelimit-reset")
+                .and_then(|reset| reset.parse().ok())
+                .map(Duration::from_secs),
+            source: header("docker-ratelimit-source"),
+            raw_limit,
+            raw_remaining,
+        })
+    }
+}
+
+impl Client {
+    /// Return the rate limit reported by the registry in its last response having one.
+    ///
+    /// This is updated after each request to the registry, not to its mirrors.
+    /// Registries usually only report it on some requests, e.g. Docker Hub on
+    /// manifest ones: other responses leave it unchanged.
+    pub fn last_rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit
+            .read()
+            .expect("rate limit lock poisoned")
+            .clone()
+    }
+
+    /// Track the rate limit reported by a response, if any.
+    pub(crate) fn record_rate_limit(&self, res: &reqwest::Response) {
+        if let Some(rate_limit) = RateLimit::from_headers(res.headers()) {
+            trace!("rate limit for '{}': {:?}", res.url(), rate_limit);
+            *self.rate_limit.write().expect("rate limit lock poisoned") = Some(rate_limit);
+        }
+    }
+}
+
+/// Parse a quota header value, e.g. `100;w=21600`, into a count and a window.
+///
+/// Only the first of several comma-separated quota policies is read.
+fn parse_quota(value: &str) -> (Option<u64>, Option<Duration>) {
+    let policy = value.split(',').next().unwrap_or_default();
+    let mut params = policy.split(';').map(str::trim);
+    let count = params.next().and_then(|count| count.parse().ok());
+    let window = params
+        .find_map(|param| param.strip_prefix("w="))
+        .and_then(|window| window.parse().ok())
+        .map(Duration::from_secs);
+    (count, window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn docker_hub_headers_are_parsed() {
+        let mut headers = HeaderMap::new();
+        headers.insert("ratelimit-limit", HeaderValue::from_static("100;w=21600"));
+        headers.insert(
+            "ratelimit-remaining",
+            HeaderValue::from_static("76;w=21600"),
+        );
+        headers.insert(
+            "docker-ratelimit-source",
+            HeaderValue::from_static("192.0.2.1"),
+        );
+
+        let rate_limit = RateLimit::from_headers(&headers).unwrap();
+        assert_eq!(rate_limit.limit, Some(100));
+        assert_eq!(rate_limit.remaining, Some(76));
+        assert_eq!(rate_limit.window, Some(Duration::from_secs(21600)));
+        assert_eq!(rate_limit.reset, None);
+        assert_eq!(rate_limit.source.as_deref(), Some("192.0.2.1"));
+        assert_eq!(rate_limit.raw_limit.as_deref(), Some("100;w=21600"));
+        assert_eq!(rate_limit.raw_remaining.as_deref(), Some("76;w=21600"));
+
+        assert_eq!(RateLimit::from_headers(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn quota_policies_are_parsed() {
+        assert_eq!(parse_quota("100"), (Some(100), None));
+        assert_eq!(parse_quota("10, 10;w=1, 1000;w=3600"), (Some(10), None));
+        assert_eq!(
+            parse_quota("0;w=60;comment=\"pulls\""),
+            (Some(0), Some(Duration::from_secs(60)))
+        );
+        assert_eq!(parse_quota("unknown"), (None, None));
+    }
+}
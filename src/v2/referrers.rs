@@ -0,0 +1,80 @@
+use crate::errors::{Error, ErrorKind, Result};
+use crate::mediatypes::MediaTypes;
+use crate::v2::manifest::{Manifest, ManifestList};
+use crate::v2::*;
+use reqwest::{header, Url};
+
+impl Client {
+    /// List the artifacts referring to a manifest, e.g. signatures or SBOMs.
+    ///
+    /// This uses the OCI referrers API and returns the image index of the
+    /// referrers, restricted to the given artifact type if any. Registries
+    /// without the referrers API are queried through the referrers tag
+    /// schema, where the index is pushed under the `<alg>-<hex>` tag.
+    pub async fn get_referrers(
+        &self,
+        name: &str,
+        digest: &str,
+        artifact_type: Option<&str>,
+    ) -> Result<ManifestList> {
+        let digest = ContentDigest::try_new(digest.to_string())?.to_string();
+        validate_name(name)?;
+        let mut url = Url::parse(&format!(
+            "{}{}/{}/referrers/{}",
+            self.base_url, self.api_base_path, name, digest
+        ))
+        .map_err(|e| Error::from(format!("{}", e)))?;
+        if let Some(artifact_type) = artifact_type {
+            url.query_pairs_mut()
+                .append_pair("artifactType", artifact_type);
+        }
+
+        self.refresh_auth_if_needed().await?;
+        let res = self
+            .send_with_escalation(|| {
+                self.build_reqwest(Method::GET, url.clone())
+                    .header(header::ACCEPT, MediaTypes::OciImageIndexV1.to_string())
+            })
+            .await?;
+
+        let status = res.status();
+        trace!("GET '{}' status: {:?}", res.url(), status);
+
+        let (referrers, filtered) = match status {
+            StatusCode::OK => {
+                let filtered = res
+                    .headers()
+                    .get("OCI-Filters-Applied")
+                    .and_then(|v| v.to_str().ok())
+                    .is_some_and(|v| v.split(',').any(|f| f.trim() == "artifactType"));
+                let body = read_body_limited(res, self.max_manifest_size).await?;
+                (serde_json::from_slice::<ManifestList>(&body)?, filtered)
+            }
+            StatusCode::NOT_FOUND => (self.get_referrers_tag(name, &digest).await?, false),
+            _ => return Err(response_error(res, "GET").await),
+        };
+
+        Ok(match (artifact_type, filtered) {
+            (Some(artifact_type), false) => referrers.with_artifact_type(artifact_type),
+            _ => referrers,
+        })
+    }
+
+    /// Fetch the referrers index pushed under the referrers tag of a digest.
+    async fn get_referrers_tag(&self, name: &str, digest: &str) -> Result<ManifestList> {
+        let tag = digest.replacen(':', "-", 1);
+        trace!("falling back to referrers tag '{}'", tag);
+
+        match self.get_manifest(name, &tag).await {
+            Ok(Manifest::ML(list)) => Ok(list),
+            Ok(m) => bail!(
+                "referrers tag {}:{} is not an image index but '{}'",
+                name,
+                tag,
+                m.media_type().to_string()
+            ),
+            Err(Error(ErrorKind::NotFound(_), _)) => Ok(ManifestList::empty_index()),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use crate::errors::{ErrorKind, Result};
+use crate::v2::*;
+use reqwest::RequestBuilder;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// Policy for retrying requests which failed with a transient error.
+///
+/// Transient errors are connection failures, timeouts and `502`, `503` or `504`
+/// responses. Only idempotent (`GET` and `HEAD`) requests are retried, unless
+/// `retry_writes` is set. Retries are delayed by an exponential backoff with jitter.
+///
+/// Rate-limited idempotent requests are retried too, after the delay asked for
+/// by the registry, unless it is longer than the maximum backoff.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retry_writes: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl RetryPolicy {
+    /// Initialize a policy allowing up to `max_attempts` attempts per request.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            retry_writes: false,
+        }
+    }
+
+    /// Initialize a policy which never retries.
+    pub fn none() -> Self {
+        Self::new(1)
+    }
+
+    /// Set the delay before the first retry, doubled for each further one.
+    pub fn initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Set the longest delay between two attempts.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Whether to also retry non-idempotent requests, e.g. blob uploads.
+    pub fn retry_writes(mut self, retry_writes: bool) -> Self {
+        self.retry_writes = retry_writes;
+        self
+    }
+
+    /// Return the maximum number of attempts per request.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    fn retries_method(&self, method: &Method) -> bool {
+        self.retry_writes || *method == Method::GET || *method == Method::HEAD
+    }
+
+    /// Return the delay before the given retry, starting from 1.
+    ///
+    /// The delay is picked at random between half and all of the exponential backoff.
+    fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let backoff = self
+            .initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+
+        let half = backoff / 2;
+        let jitter = RandomState::new().build_hasher().finish() % (half.as_millis() as u64 + 1);
+        half + Duration::from_millis(jitter)
+    }
+}
+
+impl Client {
+    /// Send a request, retrying it on transient errors according to the retry policy.
+    ///
+    /// Rate-limited (`429`) requests are retried like transient errors, after
+    /// the delay asked for by the registry in `Retry-After`. They fail with
+    /// `ErrorKind::RateLimited` once the attempts are exhausted, if they may
+    /// not be retried, or if the delay is longer than the maximum backoff.
+    /// The rate limit reported by responses is tracked for `last_rate_limit`.
+    /// `build` is called again for each attempt.
+    pub(crate) async fn send_with_retry<F>(&self, build: &F) -> Result<reqwest::Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        if let Some(res) = self.send_to_mirrors(build).await {
+            return Ok(res);
+        }
+        self.send_to_primary(build).await
+    }
+
+    /// Send a request to the registry itself, never to its mirrors.
+    ///
+    /// This is meant for checks on the write path, e.g. whether a blob must be
+    /// pushed: a mirror may have content the registry lacks. Requests are
+    /// retried as by `send_with_retry`.
+    pub(crate) async fn send_to_primary<F>(&self, build: &F) -> Result<reqwest::Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 1;
+        loop {
+            let request = build().build()?;
+            let retryable = self.retry_policy.retries_method(request.method());
+            let url = request.url().clone();
+            let method = request.method().clone();
+            let bytes_sent = body_len(&request);
+
+            let result = self.client.execute(request).await;
+            self.record_request(&method, &url, bytes_sent, result.as_ref().ok());
+            if let Ok(res) = &result {
+                self.report_warnings(res);
+                self.record_rate_limit(res);
+            }
+            let delay = match &result {
+                Ok(r) if r.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    let retry_after = parse_retry_after(r.headers());
+                    let too_long =
+                        matches!(retry_after, Some(d) if d > self.retry_policy.max_backoff);
+                    if !retryable || too_long || attempt >= self.retry_policy.max_attempts {
+                        bail!(ErrorKind::RateLimited(retry_after));
+                    }
+                    retry_after.unwrap_or_else(|| self.retry_policy.backoff(attempt))
+                }
+                Ok(r) if retryable && is_transient_status(r.status()) => {
+                    self.retry_policy.backoff(attempt)
+                }
+                // Certificate problems do not go away by retrying.
+                Err(e)
+                    if retryable
+                        && !is_tls_error(e)
+                        && (e.is_connect() || e.is_timeout() || e.is_request()) =>
+                {
+                    self.retry_policy.backoff(attempt)
+                }
+                _ => return result.map_err(request_error),
+            };
+            if attempt >= self.retry_policy.max_attempts {
+                return result.map_err(request_error);
+            }
+
+            debug!(
+                "retry: attempt {} for '{}' failed, retrying in {:?}",
+                attempt, url, delay
+            );
+            tokio::time::delay_for(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Send a read request to each mirror in turn, until one of them serves it.
+    ///
+    /// Only manifest, blob and tag reads are sent to mirrors, without credentials
+    /// nor extra headers.
+    async fn send_to_mirrors<F>(&self, build: &F) -> Option<reqwest::Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        if self.mirrors.is_empty() {
+            return None;
+        }
+        let request = build().build().ok()?;
+        if !is_mirrored(request.method(), request.url().path(), &self.api_base_path) {
+            return None;
+        }
+        let request_method = request.method().clone();
+
+        for mirror in &self.mirrors {
+            let mut request = request.try_clone()?;
+            let url = request.url_mut();
+            url.set_scheme(mirror.scheme()).ok()?;
+            url.set_host(mirror.host_str()).ok()?;
+            url.set_port(mirror.port()).ok()?;
+            request.headers_mut().remove(reqwest::header::AUTHORIZATION);
+            for name in self.extra_headers.keys() {
+                request.headers_mut().remove(name);
+            }
+
+            trace!("trying mirror: {} {}", request.method(), request.url());
+            let url = request.url().clone();
+            let bytes_sent = body_len(&request);
+            let result = self.client.execute(request).await;
+            self.record_request(&request_method, &url, bytes_sent, result.as_ref().ok());
+            if let Ok(res) = &result {
+                self.report_warnings(res);
+            }
+            match result {
+                Ok(res) if !is_mirror_miss(res.status()) => return Some(res),
+                Ok(res) => debug!("mirror '{}' failed: status {}", mirror, res.status()),
+                Err(e) => debug!("mirror '{}' failed: {}", mirror, e),
+            }
+        }
+        None
+    }
+}
+
+/// Whether a request can be served by a registry mirror.
+fn is_mirrored(method: &Method, path: &str, api_base_path: &str) -> bool {
+    (*method == Method::GET || *method == Method::HEAD)
+        && path.starts_with(&format!("{}/", api_base_path))
+        && !path.contains("/blobs/uploads/")
+        && (path.contains("/manifests/")
+            || path.contains("/blobs/")
+            || path.ends_with("/tags/list"))
+}
+
+/// Whether a mirror response should be skipped for the next mirror, or the registry.
+///
+/// Mirrors may lack content, deny it or be rate-limited while the registry
+/// still serves it.
+fn is_mirror_miss(status: StatusCode) -> bool {
+    status.is_server_error()
+        || matches!(
+            status,
+            StatusCode::UNAUTHORIZED
+                | StatusCode::FORBIDDEN
+                | StatusCode::NOT_FOUND
+                | StatusCode::TOO_MANY_REQUESTS
+        )
+}
+
+/// Size of a request body, or zero for streamed bodies.
+fn body_len(request: &reqwest::Request) -> u64 {
+    request
+        .body()
+        .and_then(reqwest::Body::as_bytes)
+        .map_or(0, |body| body.len() as u64)
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy::new(5)
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(300));
+
+        for _ in 0..10 {
+            let first = policy.backoff(1);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            let second = policy.backoff(2);
+            assert!(second >= Duration::from_millis(100) && second <= Duration::from_millis(200));
+            let capped = policy.backoff(40);
+            assert!(capped >= Duration::from_millis(150) && capped <= Duration::from_millis(300));
+        }
+    }
+
+    #[test]
+    fn only_reads_are_mirrored() {
+        assert!(is_mirrored(
+            &Method::GET,
+            "/v2/library/busybox/manifests/latest",
+            "/v2"
+        ));
+        assert!(is_mirrored(
+            &Method::HEAD,
+            "/v2/library/busybox/blobs/sha256:00",
+            "/v2"
+        ));
+        assert!(is_mirrored(
+            &Method::GET,
+            "/v2/library/busybox/tags/list",
+            "/v2"
+        ));
+        assert!(!is_mirrored(&Method::GET, "/v2/", "/v2"));
+        assert!(!is_mirrored(
+            &Method::GET,
+            "/v2/library/busybox/blobs/uploads/1234",
+            "/v2"
+        ));
+        assert!(!is_mirrored(
+            &Method::PUT,
+            "/v2/library/busybox/manifests/latest",
+            "/v2"
+        ));
+        assert!(is_mirrored(
+            &Method::GET,
+            "/registry/v2/library/busybox/tags/list",
+            "/registry/v2"
+        ));
+        assert!(!is_mirrored(
+            &Method::GET,
+            "/v2/library/busybox/tags/list",
+            "/registry/v2"
+        ));
+    }
+
+    #[test]
+    fn mirror_misses_fall_through() {
+        for status in &[
+            StatusCode::UNAUTHORIZED,
+            StatusCode::FORBIDDEN,
+            StatusCode::NOT_FOUND,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::BAD_GATEWAY,
+        ] {
+            assert!(is_mirror_miss(*status), "{}", status);
+        }
+        assert!(!is_mirror_miss(StatusCode::OK));
+        assert!(!is_mirror_miss(StatusCode::TEMPORARY_REDIRECT));
+    }
+
+    #[test]
+    fn only_idempotent_methods_are_retried() {
+        let policy = RetryPolicy::default();
+        assert!(policy.retries_method(&Method::GET));
+        assert!(policy.retries_method(&Method::HEAD));
+        assert!(!policy.retries_method(&Method::PUT));
+        assert!(policy.retry_writes(true).retries_method(&Method::PATCH));
+    }
+}
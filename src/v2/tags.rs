@@ -1,6 +1,7 @@
 use crate::errors::{Error, Result};
 use crate::v2::*;
 use async_stream::try_stream;
+use futures::TryStreamExt;
 use reqwest::{self, header, Url};
 use std::fmt::Debug;
 
@@ -18,51 +19,114 @@ struct TagsChunk {
 
 impl Client {
     /// List existing tags for an image.
+    ///
+    /// Pages of `paginate` entries are requested, or of the configured
+    /// `Config::page_size` if none is given. Paginated results are followed
+    /// through the `Link` header until all tags have been returned.
     pub fn get_tags<'a, 'b: 'a, 'c: 'a>(
         &'b self,
         name: &'c str,
         paginate: Option<u32>,
     ) -> impl Stream<Item = Result<String>> + 'a {
-        let base_url = format!("{}/v2/{}/tags/list", self.base_url, name);
+        let base_url = format!("{}{}/{}/tags/list", self.base_url, self.api_base_path, name);
+        let paginate = paginate.or(self.page_size);
         let mut link: Option<String> = None;
 
         try_stream! {
+            validate_name(name)?;
             loop {
-                let (tags_chunk, last) = self.fetch_tags_chunk(paginate, &base_url, &link).await?;
+                let (tags_chunk, last) = self.fetch_tags_chunk(name, paginate, &base_url, &link).await?;
                 for tag in tags_chunk.tags {
                     yield tag;
                 }
 
                 link = match last {
+                    Some(next) => Some(next),
                     None => break,
-                    Some(ref s) if s == "" => None,
-                    s => s,
                 };
             }
         }
     }
 
+    /// Fetch the page of at most `n` tags following `last`, in lexical order.
+    ///
+    /// This allows resuming a listing from a known tag, e.g. the last one of
+    /// a previous page. An empty page means there are no tags after `last`.
+    pub async fn get_tags_after(&self, name: &str, last: &str, n: u32) -> Result<Vec<String>> {
+        validate_name(name)?;
+        let base_url = format!("{}{}/{}/tags/list", self.base_url, self.api_base_path, name);
+        let mut url = Url::parse(&base_url).map_err(|e| Error::from(format!("{}", e)))?;
+        url.query_pairs_mut()
+            .append_pair("n", &n.to_string())
+            .append_pair("last", last);
+        let query = url.query().map(ToString::to_string);
+
+        let (tags_chunk, _) = self.fetch_tags_chunk(name, None, &base_url, &query).await?;
+        Ok(tags_chunk.tags)
+    }
+
+    /// List existing tags for an image, requesting pages of the given size.
+    ///
+    /// Registries may cap the page size.
+    pub fn tags_paginated<'a, 'b: 'a, 'c: 'a>(
+        &'b self,
+        name: &'c str,
+        page_size: u32,
+    ) -> impl Stream<Item = Result<String>> + 'a {
+        self.get_tags(name, Some(page_size))
+    }
+
+    /// List the tags of an image for which `predicate` returns `true`.
+    ///
+    /// Tags are filtered page by page as they are received, so that only
+    /// the matching ones are kept.
+    pub fn tags_matching<'a, 'b: 'a, 'c: 'a, P>(
+        &'b self,
+        name: &'c str,
+        predicate: P,
+    ) -> impl Stream<Item = Result<String>> + 'a
+    where
+        P: Fn(&str) -> bool + 'a,
+    {
+        self.get_tags(name, None).try_filter(move |tag| {
+            let matches = predicate(tag);
+            async move { matches }
+        })
+    }
+
+    /// List the tags of an image starting with `prefix`, e.g. `v1.`.
+    pub fn tags_with_prefix<'a, 'b: 'a, 'c: 'a, 'd: 'a>(
+        &'b self,
+        name: &'c str,
+        prefix: &'d str,
+    ) -> impl Stream<Item = Result<String>> + 'a {
+        self.tags_matching(name, move |tag| tag.starts_with(prefix))
+    }
+
     async fn fetch_tags_chunk(
         &self,
+        name: &str,
         paginate: Option<u32>,
         base_url: &str,
         link: &Option<String>,
     ) -> Result<(TagsChunk, Option<String>)> {
         let url_paginated = match (paginate, link) {
+            (_, Some(query)) => format!("{}?{}", base_url, query),
             (Some(p), None) => format!("{}?n={}", base_url, p),
-            (None, Some(l)) => format!("{}?next_page={}", base_url, l),
-            (Some(p), Some(l)) => format!("{}?n={}&next_page={}", base_url, p, l),
-            _ => base_url.to_string(),
+            (None, None) => base_url.to_string(),
         };
         let url = Url::parse(&url_paginated).map_err(|e| Error::from(format!("{}", e)))?;
 
+        self.refresh_auth_if_needed().await?;
         let resp = self
-            .build_reqwest(Method::GET, url.clone())
-            .header(header::ACCEPT, "application/json")
-            .send()
-            .await?
-            .error_for_status()
-            .map_err(|e| Error::from(format!("{}", e)))?;
+            .send_with_escalation(|| {
+                self.build_reqwest(Method::GET, url.clone())
+                    .header(header::ACCEPT, "application/json")
+            })
+            .await?;
+        if !resp.status().is_success() {
+            return Err(fetch_error(resp, "get_tags: GET", name.to_string()).await);
+        }
 
         // ensure the CONTENT_TYPE header is application/json
         let ct_hdr = resp.headers().get(header::CONTENT_TYPE).cloned();
@@ -90,38 +154,3 @@ impl Client {
         Ok((tags_chunk, next))
     }
 }
-
-/// Parse a `Link` header.
-///
-/// Format is described at https://docs.docker.com/registry/spec/api/#listing-image-tags#pagination.
-fn parse_link(hdr: Option<&header::HeaderValue>) -> Option<String> {
-    // TODO(lucab): this a brittle string-matching parser. Investigate
-    // whether there is a a common library to do this, in the future.
-
-    // Raw Header value bytes.
-    let hval = match hdr {
-        Some(v) => v,
-        None => return None,
-    };
-
-    // Header value string.
-    let sval = match hval.to_str() {
-        Ok(v) => v.to_owned(),
-        _ => return None,
-    };
-
-    // Query parameters for next page URL.
-    let uri = sval.trim_end_matches(">; rel=\"next\"");
-    let query: Vec<&str> = uri.splitn(2, "next_page=").collect();
-    let params = match query.get(1) {
-        Some(v) if *v != "" => v,
-        _ => return None,
-    };
-
-    // Last item in current page (pagination parameter).
-    let last: Vec<&str> = params.splitn(2, '&').collect();
-    match last.get(0).cloned() {
-        Some(v) if v != "" => Some(v.to_string()),
-        _ => None,
-    }
-}
@@ -0,0 +1,590 @@
+use crate::errors::{Error, Result};
+use crate::v2::*;
+use bytes::Bytes;
+use reqwest::{header, Method, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tracing::Instrument;
+
+/// Size of the chunks read from a stream by `upload_blob_stream`.
+const STREAM_CHUNK_SIZE: usize = 5 * 1024 * 1024;
+
+/// An in-progress chunked blob upload.
+///
+/// Chunks are pushed in order with `push_chunk`, and the upload is completed
+/// with `finish`. If a chunk fails to be transferred, `status` queries the
+/// registry for the offset from which to resume.
+#[derive(Debug)]
+pub struct BlobUpload {
+    client: Client,
+    name: String,
+    location: Url,
+    offset: u64,
+}
+
+/// A blob uploaded from a stream.
+#[derive(Clone, Debug)]
+pub struct UploadedBlob {
+    /// Digest of the uploaded content, e.g. `sha256:...`.
+    pub digest: String,
+    /// Size of the uploaded content in bytes.
+    pub size: u64,
+    /// Location of the uploaded blob.
+    pub location: String,
+}
+
+impl Client {
+    /// Start a chunked blob upload to the given repository.
+    pub async fn start_blob_upload(&self, name: &str) -> Result<BlobUpload> {
+        BlobUpload::start(self, name).await
+    }
+
+    /// Upload a blob in a single request, returning the location of the uploaded blob.
+    ///
+    /// This is meant for small blobs, e.g. image configs; see `BlobUpload` for large ones.
+    pub async fn upload_blob<B: Into<Bytes>>(
+        &self,
+        name: &str,
+        digest: &str,
+        blob: B,
+    ) -> Result<String> {
+        let span = self.span("upload_blob", name, digest);
+        async move {
+            let blob = blob.into();
+            let size = blob.len() as u64;
+            let upload = self.start_blob_upload(name).await?;
+            let location = upload.put(digest, blob).await?;
+            self.record_upload(size);
+            Ok(location)
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Upload a blob in chunks of `chunk_size` bytes, reporting the upload progress.
+    ///
+    /// `progress` is called after each accepted chunk with the number of bytes
+    /// uploaded so far and the blob size. Returns the location of the uploaded blob.
+    pub async fn upload_blob_with_progress<B, F>(
+        &self,
+        name: &str,
+        digest: &str,
+        blob: B,
+        chunk_size: usize,
+        mut progress: F,
+    ) -> Result<String>
+    where
+        B: Into<Bytes>,
+        F: FnMut(u64, u64),
+    {
+        let span = self.span("upload_blob", name, digest);
+        async move {
+            let blob = blob.into();
+            let total = blob.len() as u64;
+            let mut upload = self.start_blob_upload(name).await?;
+
+            let mut start = 0;
+            while start < blob.len() {
+                let end = blob.len().min(start + chunk_size.max(1));
+                let offset = upload.push_chunk(blob.slice(start..end)).await?;
+                ensure!(
+                    offset as usize > start && offset <= total,
+                    "upload: registry reported unexpected offset {} after chunk at {}",
+                    offset,
+                    start
+                );
+                progress(offset, total);
+                // Registries may accept only part of a chunk, resume from their offset.
+                start = offset as usize;
+            }
+
+            let location = upload.finish(digest).await?;
+            self.record_upload(total);
+            Ok(location)
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Upload a blob read from a stream of unknown size, computing its digest on the way.
+    ///
+    /// Content is read and uploaded in chunks, so it is not held in memory as a whole.
+    /// A stream which fits in a single chunk is uploaded in a single request.
+    /// If the registry does not accept chunked uploads, the rest of the stream
+    /// is buffered to a temporary file and the blob uploaded in one request.
+    pub async fn upload_blob_stream<R>(&self, name: &str, reader: R) -> Result<UploadedBlob>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let span = self.span("upload_blob_stream", name, "");
+        async move {
+            let mut reader = reader;
+            let mut hasher = Sha256::new();
+            let mut upload = self.start_blob_upload(name).await?;
+
+            let mut chunk = read_chunk(&mut reader, STREAM_CHUNK_SIZE).await?;
+            hasher.update(&chunk);
+            if chunk.len() < STREAM_CHUNK_SIZE {
+                let digest = format!("sha256:{:x}", hasher.finalize());
+                let size = chunk.len() as u64;
+                let location = upload.put(&digest, chunk).await?;
+                self.record_upload(size);
+                return Ok(UploadedBlob {
+                    digest,
+                    size,
+                    location,
+                });
+            }
+
+            let mut size = 0;
+            while !chunk.is_empty() {
+                let end = size + chunk.len() as u64;
+                while upload.offset() < end {
+                    let start = (upload.offset() - size) as usize;
+                    match upload.try_push_chunk(chunk.slice(start..)).await? {
+                        Some(offset) => ensure!(
+                            offset > size + start as u64 && offset <= end,
+                            "upload: registry reported unexpected offset {} after chunk at {}",
+                            offset,
+                            size + start as u64
+                        ),
+                        None if size == 0 => {
+                            debug!("chunked upload declined, buffering blob to a file");
+                            return self.upload_spooled(upload, chunk, reader, hasher).await;
+                        }
+                        None => bail!("upload: registry stopped accepting chunks at {}", size),
+                    }
+                }
+                size = end;
+
+                chunk = read_chunk(&mut reader, STREAM_CHUNK_SIZE).await?;
+                hasher.update(&chunk);
+            }
+
+            let digest = format!("sha256:{:x}", hasher.finalize());
+            let location = upload.finish(&digest).await?;
+            self.record_upload(size);
+            Ok(UploadedBlob {
+                digest,
+                size,
+                location,
+            })
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Complete a streamed upload in a single request, buffering the stream to a file.
+    ///
+    /// `head` is the content already read from `reader` and hashed.
+    async fn upload_spooled<R>(
+        &self,
+        upload: BlobUpload,
+        head: Bytes,
+        mut reader: R,
+        mut hasher: Sha256,
+    ) -> Result<UploadedBlob>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let spool = SpoolFile::new();
+        let mut file = tokio::fs::File::create(spool.path()).await.map_err(|e| {
+            Error::from(format!(
+                "upload: failed to create '{}': {}",
+                spool.path().display(),
+                e
+            ))
+        })?;
+        file.write_all(&head).await?;
+        let mut size = head.len() as u64;
+        loop {
+            let chunk = read_chunk(&mut reader, STREAM_CHUNK_SIZE).await?;
+            if chunk.is_empty() {
+                break;
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+            size += chunk.len() as u64;
+        }
+        file.flush().await?;
+        drop(file);
+
+        let digest = format!("sha256:{:x}", hasher.finalize());
+        let path = spool.path().to_path_buf();
+        let location = upload
+            .put_with(&digest, size, || file_body(path.clone()))
+            .await?;
+        self.record_upload(size);
+        Ok(UploadedBlob {
+            digest,
+            size,
+            location,
+        })
+    }
+
+    /// Mount a blob from another repository of the same registry.
+    ///
+    /// Returns `false` if the registry declined the mount, in which case
+    /// the blob has to be uploaded instead. The upload session the registry
+    /// opens when declining is cancelled.
+    pub async fn mount_blob(&self, name: &str, digest: &str, from_repo: &str) -> Result<bool> {
+        validate_name(name)?;
+        validate_name(from_repo)?;
+        let ep = format!(
+            "{}{}/{}/blobs/uploads/",
+            self.base_url, self.api_base_path, name
+        );
+        let mut url = Url::parse(&ep)
+            .map_err(|e| Error::from(format!("failed to parse url from string: {}", e)))?;
+        url.query_pairs_mut()
+            .append_pair("mount", digest)
+            .append_pair("from", from_repo);
+
+        self.refresh_auth_if_needed().await?;
+        let res = self
+            .send_with_escalation(|| {
+                self.build_reqwest(Method::POST, url.clone())
+                    .header(header::CONTENT_LENGTH, 0)
+            })
+            .await?;
+
+        trace!("POST {} status: {}", res.url(), res.status());
+        match res.status() {
+            StatusCode::CREATED => Ok(true),
+            StatusCode::ACCEPTED => {
+                debug!("mount of {} from {} declined", digest, from_repo);
+                if res.headers().contains_key(header::LOCATION) {
+                    let upload = BlobUpload {
+                        location: self.upload_location(res.headers())?,
+                        client: self.clone(),
+                        name: name.to_string(),
+                        offset: 0,
+                    };
+                    if let Err(e) = upload.cancel().await {
+                        warn!("mount: failed to cancel the declined mount upload: {}", e);
+                    }
+                }
+                Ok(false)
+            }
+            _ => Err(response_error(res, "POST").await),
+        }
+    }
+
+    /// Resolve the `Location` header of an upload response against the registry URL.
+    pub(crate) fn upload_location(&self, headers: &header::HeaderMap) -> Result<Url> {
+        let location = headers
+            .get(header::LOCATION)
+            .ok_or("upload: registry response has no Location header")?
+            .to_str()?;
+
+        let base = Url::parse(&self.base_url)
+            .map_err(|e| Error::from(format!("failed to parse base url: {}", e)))?;
+        base.join(location).map_err(|e| {
+            Error::from(format!(
+                "failed to parse upload location '{}': {}",
+                location, e
+            ))
+        })
+    }
+}
+
+impl BlobUpload {
+    /// Start a chunked blob upload to the given repository.
+    pub async fn start(client: &Client, name: &str) -> Result<Self> {
+        validate_name(name)?;
+        let ep = format!(
+            "{}{}/{}/blobs/uploads/",
+            client.base_url, client.api_base_path, name
+        );
+        let url = Url::parse(&ep)
+            .map_err(|e| Error::from(format!("failed to parse url from string: {}", e)))?;
+
+        client.refresh_auth_if_needed().await?;
+        let res = client
+            .send_with_escalation(|| {
+                client
+                    .build_reqwest(Method::POST, url.clone())
+                    .header(header::CONTENT_LENGTH, 0)
+            })
+            .await?;
+
+        trace!("POST {} status: {}", res.url(), res.status());
+        let status = res.status();
+        if status != StatusCode::ACCEPTED {
+            bail!("upload: POST request failed with status '{}'", status);
+        }
+        ensure!(
+            res.headers().contains_key(header::LOCATION),
+            "upload: registry answered '{}' without a Location header",
+            status
+        );
+
+        Ok(BlobUpload {
+            location: client.upload_location(res.headers())?,
+            client: client.clone(),
+            name: name.to_string(),
+            offset: 0,
+        })
+    }
+
+    /// Return the repository this blob is uploaded to.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the number of bytes accepted by the registry so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Upload the next chunk, returning the new offset.
+    pub async fn push_chunk<B: Into<Bytes>>(&mut self, chunk: B) -> Result<u64> {
+        match self.try_push_chunk(chunk.into()).await? {
+            Some(offset) => Ok(offset),
+            None => bail!("upload: registry does not accept chunked uploads"),
+        }
+    }
+
+    /// Upload the next chunk, returning `None` if the registry does not accept chunks.
+    async fn try_push_chunk(&mut self, chunk: Bytes) -> Result<Option<u64>> {
+        if chunk.is_empty() {
+            return Ok(Some(self.offset));
+        }
+        let content_range = format!("{}-{}", self.offset, self.offset + chunk.len() as u64 - 1);
+
+        self.client.refresh_auth_if_needed().await?;
+        let res = self
+            .client
+            .send_with_escalation(|| {
+                self.client
+                    .build_reqwest(Method::PATCH, self.location.clone())
+                    .header(header::CONTENT_TYPE, "application/octet-stream")
+                    .header(header::CONTENT_RANGE, content_range.as_str())
+                    .header(header::CONTENT_LENGTH, chunk.len())
+                    .body(chunk.clone())
+            })
+            .await?;
+
+        trace!("PATCH {} status: {}", res.url(), res.status());
+        match res.status() {
+            StatusCode::ACCEPTED => {}
+            StatusCode::LENGTH_REQUIRED
+            | StatusCode::METHOD_NOT_ALLOWED
+            | StatusCode::NOT_IMPLEMENTED => return Ok(None),
+            StatusCode::RANGE_NOT_SATISFIABLE => bail!(
+                "upload: chunk at offset {} was rejected, query the upload status to resume",
+                self.offset
+            ),
+            status => bail!("upload: PATCH request failed with status '{}'", status),
+        }
+
+        self.update(res.headers(), self.offset + chunk.len() as u64)?;
+        Ok(Some(self.offset))
+    }
+
+    /// Query the registry for the current offset of this upload.
+    pub async fn status(&mut self) -> Result<u64> {
+        self.client.refresh_auth_if_needed().await?;
+        let res = self
+            .client
+            .send_with_escalation(|| {
+                self.client
+                    .build_reqwest(Method::GET, self.location.clone())
+            })
+            .await?;
+
+        trace!("GET {} status: {}", res.url(), res.status());
+        let status = res.status();
+        if status != StatusCode::NO_CONTENT {
+            bail!("upload: status request failed with status '{}'", status);
+        }
+
+        self.update(res.headers(), 0)?;
+        Ok(self.offset)
+    }
+
+    /// Cancel the upload, so that the registry discards the content sent so far.
+    pub async fn cancel(self) -> Result<()> {
+        self.client.refresh_auth_if_needed().await?;
+        let res = self
+            .client
+            .send_with_escalation(|| {
+                self.client
+                    .build_reqwest(Method::DELETE, self.location.clone())
+            })
+            .await?;
+
+        trace!("DELETE {} status: {}", res.url(), res.status());
+        match res.status() {
+            StatusCode::NO_CONTENT => Ok(()),
+            _ => Err(response_error(res, "DELETE").await),
+        }
+    }
+
+    /// Complete the upload, returning the location of the uploaded blob.
+    ///
+    /// The registry verifies the uploaded content against the given digest.
+    pub async fn finish(self, digest: &str) -> Result<String> {
+        self.put(digest, Bytes::new()).await
+    }
+
+    /// Complete the upload with a final chunk.
+    async fn put(self, digest: &str, chunk: Bytes) -> Result<String> {
+        let len = chunk.len() as u64;
+        self.put_with(digest, len, || chunk.clone().into()).await
+    }
+
+    /// Complete the upload with a final chunk of `len` bytes.
+    ///
+    /// `body` is called again for each attempt.
+    async fn put_with<F>(self, digest: &str, len: u64, body: F) -> Result<String>
+    where
+        F: Fn() -> reqwest::Body,
+    {
+        let mut url = self.location.clone();
+        url.query_pairs_mut().append_pair("digest", digest);
+
+        self.client.refresh_auth_if_needed().await?;
+        let res = self
+            .client
+            .send_with_escalation(|| {
+                self.client
+                    .build_reqwest(Method::PUT, url.clone())
+                    .header(header::CONTENT_TYPE, "application/octet-stream")
+                    .header(header::CONTENT_LENGTH, len)
+                    .body(body())
+            })
+            .await?;
+
+        trace!("PUT {} status: {}", res.url(), res.status());
+        let status = res.status();
+        if status != StatusCode::CREATED {
+            bail!("upload: PUT request failed with status '{}'", status);
+        }
+
+        Ok(self.client.upload_location(res.headers())?.to_string())
+    }
+
+    /// Track the upload location and offset from a registry response.
+    ///
+    /// `expected_offset` is used if the response has no `Range` header,
+    /// and to disambiguate the `0-0` range.
+    fn update(&mut self, headers: &header::HeaderMap, expected_offset: u64) -> Result<()> {
+        if headers.contains_key(header::LOCATION) {
+            self.location = self.client.upload_location(headers)?;
+        }
+
+        self.offset = match headers.get(header::RANGE) {
+            Some(range) => match parse_range_end(range.to_str()?)? {
+                Some(end) => end + 1,
+                None => expected_offset.min(1),
+            },
+            None => expected_offset,
+        };
+
+        Ok(())
+    }
+}
+
+/// Read up to `size` bytes, stopping short only at the end of the stream.
+async fn read_chunk<R: AsyncRead + Unpin>(reader: &mut R, size: usize) -> Result<Bytes> {
+    let mut buf = vec![0; size];
+    let mut filled = 0;
+    while filled < size {
+        match reader.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    buf.truncate(filled);
+    Ok(buf.into())
+}
+
+/// Stream the content of a file as a request body.
+fn file_body(path: PathBuf) -> reqwest::Body {
+    reqwest::Body::wrap_stream(async_stream::stream! {
+        let mut file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(e) => {
+                yield Err(e);
+                return;
+            }
+        };
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            match file.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(n) => yield Ok(Bytes::copy_from_slice(&buf[..n])),
+                Err(e) => {
+                    yield Err(e);
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// A temporary file, removed when dropped.
+struct SpoolFile(PathBuf);
+
+impl SpoolFile {
+    fn new() -> Self {
+        let id = RandomState::new().build_hasher().finish();
+        SpoolFile(std::env::temp_dir().join(format!(
+            "dkregistry-upload-{}-{:016x}",
+            std::process::id(),
+            id
+        )))
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for SpoolFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Parse the inclusive end of an upload `Range` header, e.g. `0-1023`.
+///
+/// Returns `None` for the `0-0` range, which registries report both before
+/// any content is received and after the first byte.
+fn parse_range_end(range: &str) -> Result<Option<u64>> {
+    let range = range.trim().trim_start_matches("bytes=");
+    let mut bounds = range.splitn(2, '-');
+    let (start, end) = match (bounds.next(), bounds.next()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => bail!("upload: malformed Range header '{}'", range),
+    };
+
+    let start = start
+        .parse::<u64>()
+        .map_err(|e| Error::from(format!("upload: malformed Range '{}': {}", range, e)))?;
+    let end = end
+        .parse::<u64>()
+        .map_err(|e| Error::from(format!("upload: malformed Range '{}': {}", range, e)))?;
+    ensure!(start == 0, "upload: unexpected Range start in '{}'", range);
+
+    Ok(if end == 0 { None } else { Some(end) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_end_parses() -> Result<()> {
+        assert_eq!(Some(1023), parse_range_end("0-1023")?);
+        assert_eq!(Some(1023), parse_range_end("bytes=0-1023")?);
+        assert_eq!(None, parse_range_end("0-0")?);
+        assert!(parse_range_end("garbage").is_err());
+
+        Ok(())
+    }
+}
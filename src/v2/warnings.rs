@@ -0,0 +1,105 @@
+//! Registry `Warning` headers.
+
+use crate::v2::*;
+use reqwest::header::WARNING;
+use std::sync::Arc;
+
+/// Callback receiving the text of `Warning` headers sent by the registry.
+#[derive(Clone)]
+pub(crate) struct WarningHandler(pub(crate) Arc<dyn Fn(&str) + Send + Sync>);
+
+impl std::fmt::Debug for WarningHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("WarningHandler")
+    }
+}
+
+impl Client {
+    /// Log the `Warning` headers of a response and pass them to the warning handler.
+    pub(crate) fn report_warnings(&self, res: &reqwest::Response) {
+        for value in res.headers().get_all(WARNING) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(e) => {
+                    debug!("skipping non-UTF-8 Warning header: {}", e);
+                    continue;
+                }
+            };
+            for text in parse_warnings(value) {
+                warn!("registry warning for '{}': {}", res.url(), text);
+                if let Some(handler) = &self.warning_handler {
+                    (handler.0)(&text);
+                }
+            }
+        }
+    }
+}
+
+/// Extract the texts of a `Warning` header, e.g. `299 - "deprecated"`.
+///
+/// See RFC 7234, section 5.5. A header can hold several comma-separated warnings.
+fn parse_warnings(value: &str) -> Vec<String> {
+    let mut texts = vec![];
+    let mut rest = value;
+    // Skip the warning code and agent, up to the quoted text.
+    while let Some(start) = rest.find('"') {
+        let mut text = String::new();
+        let mut chars = rest[start + 1..].char_indices();
+        let mut end = None;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        text.push(escaped);
+                    }
+                }
+                '"' => {
+                    end = Some(start + 1 + i + 1);
+                    break;
+                }
+                c => text.push(c),
+            }
+        }
+        let end = match end {
+            Some(end) => end,
+            None => break,
+        };
+        texts.push(text);
+
+        // Skip an optional quoted date, up to the next warning.
+        rest = &rest[end..];
+        let mut in_quotes = false;
+        let next = rest.char_indices().find(|(_, c)| {
+            if *c == '"' {
+                in_quotes = !in_quotes;
+            }
+            *c == ',' && !in_quotes
+        });
+        match next {
+            Some((i, _)) => rest = &rest[i + 1..],
+            None => break,
+        }
+    }
+    texts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_parse() {
+        assert_eq!(
+            parse_warnings(r#"299 - "this API is deprecated""#),
+            vec!["this API is deprecated"]
+        );
+        assert_eq!(
+            parse_warnings(
+                r#"299 - "first, with a comma", 299 registry "a \"quoted\" word" "Sat, 25 Aug 2012 23:34:45 GMT""#
+            ),
+            vec!["first, with a comma", r#"a "quoted" word"#]
+        );
+        assert!(parse_warnings("299 -").is_empty());
+        assert!(parse_warnings(r#"299 - "unterminated"#).is_empty());
+    }
+}
@@ -11,6 +11,30 @@ fn test_deserialize_manifest_v2s1_signed() {
         serde_json::from_reader(bufrd).unwrap();
 }
 
+#[test]
+fn test_deserialize_manifest_v2s1_unsigned() {
+    let f = fs::File::open("tests/fixtures/manifest_v2_s1_unsigned.json").expect("Missing fixture");
+    let bufrd = io::BufReader::new(f);
+    let manif: dkregistry::v2::manifest::ManifestSchema1Signed =
+        serde_json::from_reader(bufrd).unwrap();
+
+    let manifest = dkregistry::v2::manifest::Manifest::S1(manif);
+    assert_eq!(
+        manifest.media_type(),
+        dkregistry::mediatypes::MediaTypes::ManifestV2S1
+    );
+    assert_eq!(
+        manifest.layers_digests(None).unwrap(),
+        vec![
+            "sha256:5f70bf18a086007016e948b04aed3b82103a36bea41755b6cddfaf10ace3c6ef",
+            "sha256:cc8567d70002e957612902a8e985ea129d831ebe04057d88fb644857caa45d11",
+            "sha256:5f70bf18a086007016e948b04aed3b82103a36bea41755b6cddfaf10ace3c6ef",
+            "sha256:5f70bf18a086007016e948b04aed3b82103a36bea41755b6cddfaf10ace3c6ef",
+        ]
+    );
+    assert!(manifest.to_json().is_err());
+}
+
 #[test]
 fn test_deserialize_manifest_v2s2() {
     let f = fs::File::open("tests/fixtures/manifest_v2_s2.json").expect("Missing fixture");
@@ -19,6 +43,39 @@ fn test_deserialize_manifest_v2s2() {
         serde_json::from_reader(bufrd).unwrap();
 }
 
+#[test]
+fn test_deserialize_oci_image_manifest_zstd_layers() -> Result<(), Box<dyn std::error::Error>> {
+    use dkregistry::mediatypes::MediaTypes;
+
+    let f =
+        fs::File::open("tests/fixtures/oci_image_manifest_v1_zstd.json").expect("Missing fixture");
+    let bufrd = io::BufReader::new(f);
+    let spec: dkregistry::v2::manifest::ManifestSchema2Spec = serde_json::from_reader(bufrd)?;
+    let manifest =
+        dkregistry::v2::manifest::Manifest::S2(dkregistry::v2::manifest::ManifestSchema2 {
+            manifest_spec: spec,
+            config_blob: Default::default(),
+        });
+    assert_eq!(manifest.media_type(), MediaTypes::OciImageManifestV1);
+
+    let layers = manifest.layers()?;
+    let media_types = layers
+        .iter()
+        .map(|l| l.get_media_type())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        media_types,
+        vec![
+            MediaTypes::OciImageLayerV1TarGzip,
+            MediaTypes::OciImageLayerV1TarZstd
+        ]
+    );
+    assert!(media_types.iter().all(MediaTypes::is_layer));
+    assert_eq!(32_654 + 16_724 + 7023, manifest.total_size()?);
+
+    Ok(())
+}
+
 fn deserialize_manifest_v2s2_config(
 ) -> Result<dkregistry::v2::manifest::Manifest, Box<dyn std::error::Error>> {
     let manifest_spec = {
@@ -69,6 +126,26 @@ fn test_manifest_v2s2() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn test_manifest_v2s2_layers_and_size() -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = deserialize_manifest_v2s2_config()?;
+
+    let layers = manifest.layers()?;
+    assert_eq!(5, layers.len());
+    assert_eq!(75_851_015, layers[0].size);
+    assert_eq!(
+        "application/vnd.docker.image.rootfs.diff.tar.gzip",
+        layers[0].media_type
+    );
+    assert_eq!(1218, manifest.config()?.size);
+    assert_eq!(95_263_530 + 1218, manifest.total_size()?);
+
+    let list = dkregistry::v2::manifest::Manifest::ML(Default::default());
+    assert!(list.layers().is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_deserialize_manifest_list_v2() {
     let f = fs::File::open("tests/fixtures/manifest_list_v2.json").expect("Missing fixture");
@@ -76,6 +153,104 @@ fn test_deserialize_manifest_list_v2() {
     let _manif: dkregistry::v2::manifest::ManifestList = serde_json::from_reader(bufrd).unwrap();
 }
 
+#[test]
+fn test_deserialize_oci_image_index_v1() {
+    let f = fs::File::open("tests/fixtures/oci_image_index_v1.json").expect("Missing fixture");
+    let bufrd = io::BufReader::new(f);
+    let index: dkregistry::v2::manifest::ManifestList = serde_json::from_reader(bufrd).unwrap();
+
+    let platforms = index
+        .manifests
+        .iter()
+        .map(|m| {
+            (
+                m.platform.architecture.as_str(),
+                m.platform.variant.as_deref(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        platforms,
+        vec![("ppc64le", None), ("amd64", None), ("arm64", Some("v8"))]
+    );
+    assert_eq!(
+        index.manifests[2]
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get("org.opencontainers.image.ref.name"))
+            .map(String::as_str),
+        Some("latest")
+    );
+    assert_eq!(index.annotations.as_ref().map(|a| a.len()), Some(2));
+
+    let manifest = dkregistry::v2::manifest::Manifest::ML(index);
+    assert_eq!(
+        manifest.media_type(),
+        dkregistry::mediatypes::MediaTypes::OciImageIndexV1
+    );
+    assert_eq!(manifest.annotation("com.example.key1"), Some("value1"));
+    assert_eq!(manifest.annotation("com.example.missing"), None);
+}
+
+#[test]
+fn test_deserialize_oci_image_manifest_annotations() -> Result<(), Box<dyn std::error::Error>> {
+    let raw = r#"{
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "config": {
+            "mediaType": "application/vnd.oci.image.config.v1+json",
+            "size": 7023,
+            "digest": "sha256:b5b2b2c507a0944348e0303114d8d93aaaa081732b86451d9bce1f432a537bc7",
+            "annotations": { "com.example.config": "yes" }
+        },
+        "layers": [
+            {
+                "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                "size": 32654,
+                "digest": "sha256:9834876dcfb05cb167a5c24953eba58c4ac89b1adf57f28f2f9d09af107ee8f0",
+                "annotations": { "org.opencontainers.image.title": "layer.tar.gz" }
+            }
+        ],
+        "subject": {
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "size": 7682,
+            "digest": "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270",
+            "annotations": { "com.example.subject": "yes" }
+        },
+        "annotations": {
+            "org.opencontainers.image.created": "2023-01-02T03:04:05Z",
+            "com.example.unknown": "kept"
+        }
+    }"#;
+    let spec: dkregistry::v2::manifest::ManifestSchema2Spec = serde_json::from_str(raw)?;
+    assert_eq!(spec.config().annotations.as_ref().map(|a| a.len()), Some(1));
+    assert_eq!(
+        spec.layers()[0]
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get("org.opencontainers.image.title"))
+            .map(String::as_str),
+        Some("layer.tar.gz")
+    );
+    assert!(spec.get_subject().unwrap().annotations.is_some());
+
+    // Annotations survive a round trip, including unknown keys.
+    let spec: dkregistry::v2::manifest::ManifestSchema2Spec =
+        serde_json::from_slice(&serde_json::to_vec(&spec)?)?;
+    let manifest =
+        dkregistry::v2::manifest::Manifest::S2(dkregistry::v2::manifest::ManifestSchema2 {
+            manifest_spec: spec,
+            config_blob: Default::default(),
+        });
+    assert_eq!(manifest.annotation("com.example.unknown"), Some("kept"));
+    assert_eq!(
+        manifest.annotation("org.opencontainers.image.created"),
+        Some("2023-01-02T03:04:05Z")
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_deserialize_etcd_manifest() {
     let f =
@@ -106,3 +281,25 @@ fn test_labels_manifest_v2s1_signed() {
     assert_eq!(expected_labels_0, labels_0);
     assert_eq!(None, manif.get_labels(1));
 }
+
+#[test]
+fn test_deserialize_image_config() -> Result<(), Box<dyn std::error::Error>> {
+    let f = fs::File::open("tests/fixtures/container_config_blob.json").expect("Missing fixture");
+    let config: dkregistry::v2::manifest::ImageConfig = serde_json::from_reader(f)?;
+
+    assert_eq!(config.architecture, "amd64");
+    assert_eq!(config.os, "linux");
+    assert_eq!(config.config.user, Some("0".to_string()));
+    assert_eq!(config.rootfs.fs_type, "layers");
+    assert_eq!(config.rootfs.diff_ids.len(), 6);
+    assert_eq!(
+        config.history[0].comment,
+        Some("Release image for OpenShift".to_string())
+    );
+    assert_eq!(
+        config.labels().get("io.openshift.release"),
+        Some(&"4.1.12".to_string())
+    );
+
+    Ok(())
+}
@@ -156,3 +156,47 @@ fn test_version_check_trailing_slash() {
 
     mockito::reset();
 }
+
+#[test]
+fn test_api_version_anonymous() {
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .with_header("WWW-Authenticate", r#"Basic realm="Registry""#)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.api_version()).unwrap();
+    assert_eq!(res, Some(API_VERSION_V.to_string()));
+
+    mockito::reset();
+}
+
+#[test]
+fn test_api_version_missing() {
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/").with_status(200).create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.api_version()).unwrap();
+    assert_eq!(res, None);
+
+    mockito::reset();
+}
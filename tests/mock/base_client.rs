@@ -1,5 +1,6 @@
 extern crate dkregistry;
 extern crate mockito;
+extern crate reqwest;
 extern crate tokio;
 
 use self::mockito::mock;
@@ -90,3 +91,462 @@ fn test_base_custom_useragent() {
 
     mockito::reset();
 }
+
+#[test]
+fn test_base_request_timeout() {
+    // The registry accepts connections but never responds.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        let _streams: Vec<_> = listener.incoming().collect();
+    });
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .request_timeout(std::time::Duration::from_millis(200))
+        .retry_policy(dkregistry::v2::RetryPolicy::none())
+        .build()
+        .unwrap();
+
+    let err = runtime.block_on(dclient.is_v2_supported()).unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::Timeout(_) => {}
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+}
+
+#[test]
+fn test_base_custom_root_certificate() {
+    let pem = include_bytes!("../fixtures/self_signed_ca.pem");
+    let certificate = dkregistry::v2::Certificate::from_pem(pem).unwrap();
+
+    let res = dkregistry::v2::Client::configure()
+        .registry("registry.local")
+        .add_root_certificate(certificate)
+        .build();
+    assert!(res.is_ok());
+}
+
+#[test]
+#[cfg(feature = "reqwest-default-tls")]
+fn test_base_client_identity() {
+    let der = include_bytes!("../fixtures/self_signed_identity.p12");
+    let identity = dkregistry::v2::Identity::from_pkcs12_der(der, "dkregistry").unwrap();
+
+    let res = dkregistry::v2::Client::configure()
+        .registry("registry.local")
+        .client_identity(identity)
+        .build();
+    assert!(res.is_ok());
+}
+
+#[test]
+fn test_base_http_scheme_is_insecure() {
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&format!("http://{}/", addr))
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.is_v2_supported()).unwrap();
+    assert!(res);
+
+    mockito::reset();
+}
+
+#[test]
+fn test_base_prebuilt_reqwest_client() {
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/")
+        .match_header("x-shared-client", "yes")
+        .match_header("user-agent", dkregistry::USER_AGENT)
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .create();
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("x-shared-client", "yes".parse().unwrap());
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .reqwest_client(client)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.is_v2_supported()).unwrap();
+    assert!(res);
+
+    mockito::reset();
+}
+
+#[test]
+fn test_base_connections_are_reused() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    let accepted = connections.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            accepted.fetch_add(1, Ordering::SeqCst);
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    if line == "\r\n" {
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\n{}: {}\r\nContent-Length: 0\r\n\r\n",
+                            API_VERSION_K, API_VERSION_V
+                        );
+                        stream.write_all(response.as_bytes()).unwrap();
+                    }
+                }
+            });
+        }
+    });
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    for _ in 0..3 {
+        assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+        let cloned = dclient.clone();
+        assert!(runtime.block_on(cloned.is_v2_supported()).unwrap());
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_base_custom_api_base_path() {
+    let addr = mockito::server_address().to_string();
+    let _m_base = mock("GET", "/registry/v2/")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .create();
+    let _m_blob = mock("HEAD", "/registry/v2/repo/blobs/sha256:00")
+        .with_status(200)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .api_base_path("/registry/v2/".to_string())
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+    assert!(runtime
+        .block_on(dclient.has_blob("repo", "sha256:00"))
+        .unwrap());
+
+    mockito::reset();
+}
+
+#[test]
+fn test_base_from_reference() {
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .create();
+
+    let (config, reference) =
+        dkregistry::v2::Config::from_reference(&format!("{}/foo/bar:v1", addr)).unwrap();
+    assert_eq!(reference.repository(), "foo/bar");
+    assert_eq!(reference.version(), "v1");
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = config.insecure_registry(true).build().unwrap();
+    assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+
+    let (_, reference) = dkregistry::v2::Config::from_reference("busybox").unwrap();
+    assert_eq!(reference.registry(), "registry-1.docker.io");
+    assert_eq!(reference.repository(), "library/busybox");
+
+    mockito::reset();
+}
+
+#[test]
+fn test_base_warning_headers() {
+    use std::sync::{Arc, Mutex};
+
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .with_header("Warning", r#"299 - "this registry is deprecated""#)
+        .create();
+
+    let warnings = Arc::new(Mutex::new(vec![]));
+    let received = warnings.clone();
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .warning_handler(move |text| received.lock().unwrap().push(text.to_string()))
+        .build()
+        .unwrap();
+
+    assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+    assert_eq!(
+        *warnings.lock().unwrap(),
+        vec!["this registry is deprecated".to_string()]
+    );
+
+    mockito::reset();
+}
+
+#[test]
+fn test_base_pool_without_idle_connections() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    let accepted = connections.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            accepted.fetch_add(1, Ordering::SeqCst);
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    if line == "\r\n" {
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\n{}: {}\r\nContent-Length: 0\r\n\r\n",
+                            API_VERSION_K, API_VERSION_V
+                        );
+                        stream.write_all(response.as_bytes()).unwrap();
+                    }
+                }
+            });
+        }
+    });
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .pool_max_idle_per_host(0)
+        .pool_idle_timeout(std::time::Duration::from_secs(30))
+        .build()
+        .unwrap();
+
+    for _ in 0..3 {
+        assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_base_local_address() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::{Arc, Mutex};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let peers = Arc::new(Mutex::new(Vec::new()));
+
+    let accepted = peers.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            accepted
+                .lock()
+                .unwrap()
+                .push(stream.peer_addr().unwrap().ip());
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    if line == "\r\n" {
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\n{}: {}\r\nContent-Length: 0\r\n\r\n",
+                            API_VERSION_K, API_VERSION_V
+                        );
+                        stream.write_all(response.as_bytes()).unwrap();
+                    }
+                }
+            });
+        }
+    });
+
+    // Any address of the loopback network can be bound on Linux.
+    let local_address = std::net::IpAddr::from([127, 0, 0, 2]);
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .tcp_nodelay(false)
+        .local_address(local_address)
+        .build()
+        .unwrap();
+
+    assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+    assert_eq!(*peers.lock().unwrap(), vec![local_address]);
+}
+
+#[test]
+fn test_base_http_versions() {
+    use std::io::Write;
+
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let configure = || {
+        dkregistry::v2::Client::configure()
+            .registry(&addr)
+            .insecure_registry(true)
+            .username(None)
+            .password(None)
+    };
+
+    // A registry only speaking HTTP/1.1 answers the HTTP/2 preface with an HTTP/1.1 response.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let http1_addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let response = "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n";
+            let _ = stream.unwrap().write_all(response.as_bytes());
+        }
+    });
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&http1_addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .http2_prior_knowledge(true)
+        .request_timeout(std::time::Duration::from_secs(5))
+        .retry_policy(dkregistry::v2::RetryPolicy::none())
+        .build()
+        .unwrap();
+    assert!(runtime.block_on(dclient.is_v2_supported()).is_err());
+
+    let dclient = configure()
+        .http2_prior_knowledge(true)
+        .http1_only(true)
+        .build()
+        .unwrap();
+    assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+
+    mockito::reset();
+}
+
+#[test]
+#[cfg(feature = "reqwest-default-tls")]
+fn test_base_untrusted_certificate() {
+    use std::io::{BufRead, BufReader, Write};
+
+    let der = include_bytes!("../fixtures/self_signed_identity.p12");
+    let identity = native_tls::Identity::from_pkcs12(der, "dkregistry").unwrap();
+    let acceptor = native_tls::TlsAcceptor::new(identity).unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let acceptor = acceptor.clone();
+            std::thread::spawn(move || {
+                let stream = match acceptor.accept(stream.unwrap()) {
+                    Ok(stream) => stream,
+                    Err(_) => return,
+                };
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    if line == "\r\n" {
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\n{}: {}\r\nContent-Length: 0\r\n\r\n",
+                            API_VERSION_K, API_VERSION_V
+                        );
+                        reader.get_mut().write_all(response.as_bytes()).unwrap();
+                    }
+                }
+            });
+        }
+    });
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(false)
+        .build()
+        .unwrap();
+
+    let err = runtime.block_on(dclient.is_v2_supported()).unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::Tls(_) => {}
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(false)
+        .accept_invalid_certs(true)
+        .build()
+        .unwrap();
+    assert!(runtime.block_on(dclient.is_v2_supported()).unwrap());
+}
@@ -1,11 +1,14 @@
 extern crate dkregistry;
+extern crate libflate;
 extern crate mockito;
 extern crate sha2;
+extern crate tar;
 extern crate tokio;
 
 use self::mockito::mock;
 use self::tokio::runtime::Runtime;
 use crate::mock::blobs_download::sha2::Digest;
+use futures::{StreamExt, TryStreamExt};
 
 type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
 
@@ -66,6 +69,98 @@ fn test_blobs_hasnot_layer() {
     mockito::reset();
 }
 
+#[test]
+fn test_blobs_size() {
+    let name = "my-repo/my-image";
+    let digest = "fakedigest";
+
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let missing = format!("/v2/{}/blobs/{}", name, "missingdigest");
+    let addr = mockito::server_address().to_string();
+    let _m = mock("HEAD", ep.as_str())
+        .with_status(200)
+        .with_header("Content-Length", "1234")
+        .create();
+    let _m_missing = mock("HEAD", missing.as_str()).with_status(404).create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let size = runtime.block_on(dclient.blob_size(name, digest)).unwrap();
+    assert_eq!(size, Some(1234));
+    let size = runtime
+        .block_on(dclient.blob_size(name, "missingdigest"))
+        .unwrap();
+    assert_eq!(size, None);
+
+    mockito::reset();
+}
+
+#[test]
+fn get_blob_range_returns_partial_content() {
+    let name = "my-repo/my-image";
+    let digest = "sha256:f2ca1bb6c7e907d06dafe4687e579fce76b37e4e93b7605022da52e6ccc26fd2";
+
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .match_header("Range", "bytes=1-3")
+        .with_status(206)
+        .with_header("Content-Range", "bytes 1-3/5")
+        .with_body("ell")
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .build()
+        .unwrap();
+
+    let part = runtime
+        .block_on(dclient.get_blob_range(name, digest, 1, 3))
+        .unwrap();
+    assert_eq!(part, b"ell");
+
+    mockito::reset();
+}
+
+#[test]
+fn get_blob_range_unsupported() {
+    let name = "my-repo/my-image";
+    let digest = "sha256:f2ca1bb6c7e907d06dafe4687e579fce76b37e4e93b7605022da52e6ccc26fd2";
+
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body("hello")
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .build()
+        .unwrap();
+
+    let err = runtime
+        .block_on(dclient.get_blob_range(name, digest, 1, 3))
+        .unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::Unsupported(_) => {}
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    mockito::reset();
+}
+
 #[test]
 fn get_blobs_succeeds_with_consistent_layer() -> Fallible<()> {
     let addr = mockito::server_address().to_string();
@@ -131,3 +226,663 @@ fn get_blobs_fails_with_inconsistent_layer() -> Fallible<()> {
     mockito::reset();
     Ok(())
 }
+
+#[test]
+fn get_blobs_reports_digest_mismatch() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let blob2 = b"hello2";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+    let digest2 = format!("sha256:{:x}", sha2::Sha256::digest(blob2));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob2)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_blob(name, &digest);
+
+    match runtime.block_on(futcheck) {
+        Err(dkregistry::errors::Error(
+            dkregistry::errors::ErrorKind::DigestMismatch(expected, actual),
+            _,
+        )) => {
+            assert_eq!(expected, digest);
+            assert_eq!(actual, digest2);
+        }
+        res => return Err(format!("expected a digest mismatch, got {:?}", res).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blobs_skips_verification_if_disabled() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let blob2 = b"hello2";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob2)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .verify_digests(false)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_blob(name, &digest);
+
+    let result = runtime.block_on(futcheck)?;
+    assert_eq!(blob2, result.as_slice());
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blobs_streams_to_writer() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let mut written = Vec::new();
+    let futcheck = dclient.blob_to_writer(name, &digest, &mut written);
+
+    let (len, written_digest) = runtime.block_on(futcheck)?;
+    assert_eq!(len, blob.len() as u64);
+    assert_eq!(written_digest, digest);
+    assert_eq!(blob, written.as_slice());
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn delete_blobs_reports_disabled_deletion() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("DELETE", ep.as_str()).with_status(405).create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.delete_blob(name, &digest)) {
+        Err(dkregistry::errors::Error(dkregistry::errors::ErrorKind::Unsupported(_), _)) => {}
+        res => return Err(format!("expected an unsupported operation, got {:?}", res).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn delete_blobs_reports_registry_errors() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("DELETE", ep.as_str())
+        .with_status(403)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"errors":[{"code":"DENIED","message":"requested access to the resource is denied"}]}"#)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.delete_blob(name, &digest)) {
+        Err(dkregistry::errors::Error(
+            dkregistry::errors::ErrorKind::Registry(403, errors, _),
+            _,
+        )) => {
+            assert_eq!(errors[0].code, dkregistry::v2::ErrorCode::Denied);
+        }
+        res => return Err(format!("expected a registry error, got {:?}", res).into()),
+    };
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_pull_layers_in_order() {
+    let name = "my-repo/my-image";
+    let blobs = ["layer-one", "layer-two", "layer-three"];
+    let digests = blobs
+        .iter()
+        .map(|blob| format!("sha256:{:x}", sha2::Sha256::digest(blob.as_bytes())))
+        .collect::<Vec<_>>();
+
+    let addr = mockito::server_address().to_string();
+    let _mocks = blobs
+        .iter()
+        .zip(digests.iter())
+        .map(|(blob, digest)| {
+            let ep = format!("/v2/{}/blobs/{}", name, digest);
+            mock("GET", ep.as_str())
+                .with_status(200)
+                .with_body(blob)
+                .create()
+        })
+        .collect::<Vec<_>>();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient
+        .pull_layers(name, &digests, 2, false)
+        .try_collect::<Vec<_>>();
+
+    let res = runtime.block_on(futcheck).unwrap();
+    assert_eq!(
+        res,
+        digests
+            .iter()
+            .cloned()
+            .zip(blobs.iter().map(|b| b.as_bytes().to_vec()))
+            .collect::<Vec<_>>()
+    );
+
+    mockito::reset();
+}
+
+#[test]
+fn test_blobs_pull_layers_continue_on_error() {
+    let name = "my-repo/my-image";
+    let blob = "layer-one";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob.as_bytes()));
+    let missing = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+    let addr = mockito::server_address().to_string();
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+    let ep = format!("/v2/{}/blobs/{}", name, missing);
+    let _m_missing = mock("GET", ep.as_str()).with_status(404).create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let digests = [missing, digest.as_str()];
+    let res = runtime.block_on(
+        dclient
+            .pull_layers(name, &digests, 2, false)
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(res.len(), 1);
+    match res[0].as_ref().unwrap_err().kind() {
+        dkregistry::errors::ErrorKind::NotFound(resource) => {
+            assert_eq!(resource, &format!("{}@{}", name, missing))
+        }
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    let res = runtime.block_on(
+        dclient
+            .pull_layers(name, &digests, 2, true)
+            .collect::<Vec<_>>(),
+    );
+    assert!(res[0].is_err());
+    assert_eq!(
+        res[1].as_ref().unwrap(),
+        &(digest.clone(), blob.as_bytes().to_vec())
+    );
+
+    mockito::reset();
+}
+
+#[test]
+fn get_blobs_reports_download_progress() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let mut written = Vec::new();
+    let mut reports = Vec::new();
+    let futcheck =
+        dclient.blob_to_writer_with_progress(name, &digest, &mut written, |done, total| {
+            reports.push((done, total))
+        });
+    runtime.block_on(futcheck)?;
+
+    assert_eq!(reports.last(), Some(&(5, Some(5))));
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_layer_reader_decompresses_gzip() -> Fallible<()> {
+    use self::dkregistry::v2::manifest::LayerDescriptor;
+    use self::tokio::io::AsyncReadExt;
+    use std::io::Write;
+
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let content = b"uncompressed layer content";
+    let mut encoder = libflate::gzip::Encoder::new(Vec::new())?;
+    encoder.write_all(content)?;
+    let blob = encoder.finish().into_result()?;
+    let layer = LayerDescriptor {
+        media_type: "application/vnd.oci.image.layer.v1.tar+gzip".to_string(),
+        size: blob.len() as u64,
+        digest: format!("sha256:{:x}", sha2::Sha256::digest(&blob)),
+        urls: None,
+        annotations: None,
+    };
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &layer.digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(&blob)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let uncompressed = runtime.block_on(async {
+        let mut reader = dclient.get_layer_reader(name, &layer).await?;
+        let mut uncompressed = Vec::new();
+        reader.read_to_end(&mut uncompressed).await?;
+        Ok::<_, Box<dyn std::error::Error>>(uncompressed)
+    })?;
+    assert_eq!(uncompressed, content);
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_layer_reader_decompresses_zstd() -> Fallible<()> {
+    use self::dkregistry::v2::manifest::LayerDescriptor;
+    use self::tokio::io::AsyncReadExt;
+    use std::io::Read;
+
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = include_bytes!("../fixtures/oci_image_layer_v1.tar.zst");
+    let layer = LayerDescriptor {
+        media_type: "application/vnd.oci.image.layer.v1.tar+zstd".to_string(),
+        size: blob.len() as u64,
+        digest: format!("sha256:{:x}", sha2::Sha256::digest(&blob[..])),
+        urls: None,
+        annotations: None,
+    };
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &layer.digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(&blob[..])
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let uncompressed = runtime.block_on(async {
+        let mut reader = dclient.get_layer_reader(name, &layer).await?;
+        let mut uncompressed = Vec::new();
+        reader.read_to_end(&mut uncompressed).await?;
+        Ok::<_, Box<dyn std::error::Error>>(uncompressed)
+    })?;
+
+    let mut archive = tar::Archive::new(&uncompressed[..]);
+    let mut hello = archive.entries()?.next().unwrap()?;
+    assert_eq!(hello.path()?.to_str(), Some("hello.txt"));
+    let mut content = String::new();
+    hello.read_to_string(&mut content)?;
+    assert_eq!(content, "hello from a zstd layer\n");
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_from_mirror() -> Fallible<()> {
+    // Nothing listens on these, connections are refused.
+    let closed = || {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string()
+    };
+    let (registry, down_mirror) = (closed(), closed());
+    let mirror = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&registry)
+        .mirrors(vec![down_mirror, mirror])
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.get_blob(name, &digest))?;
+    assert_eq!(res, blob);
+    m.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_falls_back_on_mirror_miss() -> Fallible<()> {
+    // Same server, under two host names.
+    let port = mockito::server_address().port();
+    let registry = format!("127.0.0.1:{}", port);
+    let mirror = format!("localhost:{}", port);
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let m_mirror = mock("GET", ep.as_str())
+        .match_header("Host", mirror.as_str())
+        .with_status(404)
+        .expect(1)
+        .create();
+    let m_registry = mock("GET", ep.as_str())
+        .match_header("Host", registry.as_str())
+        .with_status(200)
+        .with_body(blob)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&registry)
+        .mirrors(vec![mirror])
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.get_blob(name, &digest))?;
+    assert_eq!(res, blob);
+    m_mirror.assert();
+    m_registry.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn has_blob_ignores_mirrors() -> Fallible<()> {
+    // Same server, under two host names.
+    let port = mockito::server_address().port();
+    let registry = format!("127.0.0.1:{}", port);
+    let mirror = format!("localhost:{}", port);
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m_mirror = mock("HEAD", ep.as_str())
+        .match_header("Host", mirror.as_str())
+        .with_status(200)
+        .with_header("Content-Length", "5")
+        .create();
+    let m_registry = mock("HEAD", ep.as_str())
+        .match_header("Host", registry.as_str())
+        .with_status(404)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&registry)
+        .mirrors(vec![mirror])
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    // Pushes need the blob in the registry itself, reads can be served by mirrors.
+    assert!(!runtime.block_on(dclient.has_blob(name, &digest))?);
+    assert_eq!(runtime.block_on(dclient.blob_size(name, &digest))?, Some(5));
+    m_registry.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_redirect_drops_credentials() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+    // Same server, under another host name, like a storage bucket.
+    let storage = format!("http://localhost:{}", mockito::server_address().port());
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+    let _m_challenge = mock("GET", "/v2/")
+        .with_status(401)
+        .with_header("WWW-Authenticate", r#"Basic realm="Registry""#)
+        .create();
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let m_blob = mock("GET", ep.as_str())
+        .match_header("authorization", mockito::Matcher::Regex("^Basic ".into()))
+        .with_status(307)
+        .with_header("Location", &format!("{}/storage/blob?signature=s", storage))
+        .expect(1)
+        .create();
+    let m_storage = mock("GET", "/storage/blob?signature=s")
+        .match_header("authorization", mockito::Matcher::Missing)
+        .with_status(200)
+        .with_body(blob)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(Some("user".into()))
+        .password(Some("password".into()))
+        .build()
+        .unwrap();
+    let dclient = runtime.block_on(dclient.authenticate(&[]))?;
+
+    let result = runtime.block_on(dclient.get_blob(name, &digest))?;
+    assert_eq!(blob, result.as_slice());
+    m_blob.assert();
+    m_storage.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn get_blob_redirects_disabled() {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m = mock("GET", ep.as_str())
+        .with_status(307)
+        .with_header("Location", "/storage/blob")
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .follow_redirects(false)
+        .build()
+        .unwrap();
+
+    let err = runtime
+        .block_on(dclient.get_blob(name, &digest))
+        .unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::Redirect(location) => {
+            assert_eq!(location, &format!("{}/storage/blob", mockito::server_url()))
+        }
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    mockito::reset();
+}
+
+#[test]
+fn get_blob_redirects_capped() {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(b"hello"));
+    let ep = format!("/v2/{}/blobs/{}", &name, &digest);
+    let _m_blob = mock("GET", ep.as_str())
+        .with_status(307)
+        .with_header("Location", "/storage/1")
+        .create();
+    let _m_hop = mock("GET", "/storage/1")
+        .with_status(307)
+        .with_header("Location", "/storage/2")
+        .create();
+    let m_storage = mock("GET", "/storage/2").expect(0).create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .max_redirects(1)
+        .build()
+        .unwrap();
+
+    let err = runtime
+        .block_on(dclient.get_blob(name, &digest))
+        .unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::Redirect(_) => {}
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+    m_storage.assert();
+
+    mockito::reset();
+}
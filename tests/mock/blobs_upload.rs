@@ -0,0 +1,615 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate tokio;
+
+use self::mockito::{mock, Matcher};
+use self::tokio::runtime::Runtime;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[test]
+fn test_blobs_chunked_upload() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let _m_start = mock("POST", ep.as_str())
+        .with_status(202)
+        .with_header("Location", "/upload/1")
+        .with_header("Range", "0-0")
+        .create();
+    let _m_chunk1 = mock("PATCH", "/upload/1")
+        .match_header("Content-Range", "0-4")
+        .match_body("hello")
+        .with_status(202)
+        .with_header("Location", "/upload/2")
+        .with_header("Range", "0-2")
+        .create();
+    let _m_status = mock("GET", "/upload/2")
+        .with_status(204)
+        .with_header("Range", "0-2")
+        .create();
+    let _m_chunk2 = mock("PATCH", "/upload/2")
+        .match_header("Content-Range", "3-4")
+        .match_body("lo")
+        .with_status(202)
+        .with_header("Location", "/upload/3")
+        .with_header("Range", "0-4")
+        .create();
+    let m_finish = mock("PUT", "/upload/3")
+        .match_query(Matcher::UrlEncoded(
+            "digest".to_string(),
+            digest.to_string(),
+        ))
+        .with_status(201)
+        .with_header("Location", &format!("/v2/{}/blobs/{}", name, digest))
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let mut upload = runtime.block_on(dclient.start_blob_upload(name))?;
+    assert_eq!(upload.offset(), 0);
+
+    // The registry only accepted part of the first chunk.
+    let offset = runtime.block_on(upload.push_chunk(&b"hello"[..]))?;
+    assert_eq!(offset, 3);
+    assert_eq!(runtime.block_on(upload.status())?, 3);
+
+    let offset = runtime.block_on(upload.push_chunk(&b"lo"[..]))?;
+    assert_eq!(offset, 5);
+
+    let location = runtime.block_on(upload.finish(digest))?;
+    assert_eq!(
+        location,
+        format!("{}/v2/{}/blobs/{}", mockito::server_url(), name, digest)
+    );
+    m_finish.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_upload_escalates_scopes() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let challenge = |scope: &str| {
+        format!(
+            r#"Bearer realm="{}/token",service="mock-registry",scope="{}""#,
+            mockito::server_url(),
+            scope
+        )
+    };
+
+    let _m_challenge = mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &challenge("repository:my-repo/my-image:pull"),
+        )
+        .create();
+    let _m_token = mock("GET", Matcher::Regex("^/token\\?.*%3Apull$".to_string()))
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "pull-token"}"#)
+        .create();
+    let _m_push_token = mock("GET", Matcher::Regex("^/token\\?.*%2Cpush$".to_string()))
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "push-token"}"#)
+        .create();
+
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let _m_denied = mock("POST", ep.as_str())
+        .match_header("Authorization", "Bearer pull-token")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &challenge("repository:my-repo/my-image:pull,push"),
+        )
+        .create();
+    let m_start = mock("POST", ep.as_str())
+        .match_header("Authorization", "Bearer push-token")
+        .with_status(202)
+        .with_header("Location", "/upload/1")
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let dclient = runtime.block_on(dclient.authenticate(&["repository:my-repo/my-image:pull"]))?;
+    runtime.block_on(dclient.start_blob_upload(name))?;
+    m_start.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_monolithic_upload() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let _m_start = mock("POST", ep.as_str())
+        .with_status(202)
+        .with_header("Location", "/upload/1?_state=abc")
+        .create();
+    let m_put = mock("PUT", "/upload/1")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("_state".to_string(), "abc".to_string()),
+            Matcher::UrlEncoded("digest".to_string(), digest.to_string()),
+        ]))
+        .match_body("hello")
+        .with_status(201)
+        .with_header("Location", &format!("/v2/{}/blobs/{}", name, digest))
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let location = runtime.block_on(dclient.upload_blob(name, digest, &b"hello"[..]))?;
+    assert_eq!(
+        location,
+        format!("{}/v2/{}/blobs/{}", mockito::server_url(), name, digest)
+    );
+    m_put.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_stream_upload_without_chunks() -> Fallible<()> {
+    use sha2::Digest;
+
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    // Larger than a chunk, so that the upload starts with a PATCH.
+    let blob = vec![7u8; 6 * 1024 * 1024];
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(&blob));
+
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let _m_start = mock("POST", ep.as_str())
+        .with_status(202)
+        .with_header("Location", "/upload/1")
+        .create();
+    let _m_chunk = mock("PATCH", "/upload/1").with_status(405).create();
+    let m_put = mock("PUT", "/upload/1")
+        .match_query(Matcher::UrlEncoded("digest".to_string(), digest.clone()))
+        .match_header("Content-Length", blob.len().to_string().as_str())
+        .with_status(201)
+        .with_header("Location", &format!("/v2/{}/blobs/{}", name, digest))
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let uploaded = runtime.block_on(dclient.upload_blob_stream(name, &blob[..]))?;
+    assert_eq!(uploaded.digest, digest);
+    assert_eq!(uploaded.size, blob.len() as u64);
+    m_put.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_upload_without_location() {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let _m_start = mock("POST", ep.as_str()).with_status(202).create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.upload_blob(name, digest, &b"hello"[..]));
+    assert!(res.is_err());
+
+    mockito::reset();
+}
+
+#[test]
+fn test_blobs_mount() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let _m_mounted = mock("POST", ep.as_str())
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("mount".to_string(), digest.to_string()),
+            Matcher::UrlEncoded("from".to_string(), "my-repo/base".to_string()),
+        ]))
+        .with_status(201)
+        .with_header("Location", &format!("/v2/{}/blobs/{}", name, digest))
+        .create();
+    let _m_declined = mock("POST", ep.as_str())
+        .match_query(Matcher::UrlEncoded(
+            "from".to_string(),
+            "my-repo/other".to_string(),
+        ))
+        .with_status(202)
+        .with_header("Location", "/upload/1")
+        .create();
+    // The upload session opened by the declined mount is not left behind.
+    let m_cancel = mock("DELETE", "/upload/1")
+        .with_status(204)
+        .expect(1)
+        .create();
+    let _m_denied = mock("POST", ep.as_str())
+        .match_query(Matcher::UrlEncoded(
+            "from".to_string(),
+            "my-repo/private".to_string(),
+        ))
+        .with_status(403)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"errors": [{"code": "DENIED", "message": "denied"}]}"#)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    assert!(runtime.block_on(dclient.mount_blob(name, digest, "my-repo/base"))?);
+    assert!(!runtime.block_on(dclient.mount_blob(name, digest, "my-repo/other"))?);
+    m_cancel.assert();
+
+    let err = runtime
+        .block_on(dclient.mount_blob(name, digest, "my-repo/private"))
+        .unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::Registry(403, errors, _) => {
+            assert_eq!(errors[0].code, dkregistry::v2::ErrorCode::Denied)
+        }
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_ensure_blob() -> Fallible<()> {
+    use dkregistry::v2::{BlobSource, BlobTransfer};
+
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let blob = b"hello";
+    let mounted = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+    let existing = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+    let uploaded = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    let _m_existing = mock("HEAD", format!("/v2/{}/blobs/{}", name, existing).as_str())
+        .with_status(200)
+        .create();
+    let _m_mounted_head = mock("HEAD", format!("/v2/{}/blobs/{}", name, mounted).as_str())
+        .with_status(404)
+        .create();
+    let _m_uploaded_head = mock("HEAD", format!("/v2/{}/blobs/{}", name, uploaded).as_str())
+        .with_status(404)
+        .create();
+
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let _m_mounted = mock("POST", ep.as_str())
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("mount".to_string(), mounted.to_string()),
+            Matcher::UrlEncoded("from".to_string(), "my-repo/base".to_string()),
+        ]))
+        .with_status(201)
+        .with_header("Location", &format!("/v2/{}/blobs/{}", name, mounted))
+        .create();
+    let _m_declined = mock("POST", ep.as_str())
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("mount".to_string(), uploaded.to_string()),
+            Matcher::Regex("from=".to_string()),
+        ]))
+        .with_status(202)
+        .with_header("Location", "/upload/declined")
+        .expect(2)
+        .create();
+    let _m_start = mock("POST", ep.as_str())
+        .match_query(Matcher::Missing)
+        .with_status(202)
+        .with_header("Location", "/upload/1")
+        .create();
+    let m_put = mock("PUT", "/upload/1")
+        .match_query(Matcher::UrlEncoded(
+            "digest".to_string(),
+            uploaded.to_string(),
+        ))
+        .match_body("hello")
+        .with_status(201)
+        .with_header("Location", &format!("/v2/{}/blobs/{}", name, uploaded))
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let descriptor = |digest: &str| dkregistry::v2::manifest::Descriptor {
+        media_type: "application/octet-stream".to_string(),
+        size: blob.len() as u64,
+        digest: digest.to_string(),
+        annotations: None,
+    };
+    let from_repos = vec!["my-repo/other".to_string(), "my-repo/base".to_string()];
+
+    let transfer = runtime.block_on(dclient.ensure_blob(
+        name,
+        &descriptor(existing),
+        &from_repos,
+        BlobSource::Bytes(blob[..].into()),
+    ))?;
+    assert_eq!(transfer, BlobTransfer::Existing);
+
+    let transfer = runtime.block_on(dclient.ensure_blob(
+        name,
+        &descriptor(mounted),
+        &["my-repo/base".to_string()],
+        BlobSource::Bytes(blob[..].into()),
+    ))?;
+    assert_eq!(transfer, BlobTransfer::Mounted("my-repo/base".to_string()));
+
+    let transfer = runtime.block_on(dclient.ensure_blob(
+        name,
+        &descriptor(uploaded),
+        &from_repos,
+        BlobSource::Reader(Box::new(&blob[..])),
+    ))?;
+    assert_eq!(transfer, BlobTransfer::Uploaded);
+    m_put.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_ensure_blob_ignores_mirrors() -> Fallible<()> {
+    use dkregistry::v2::{BlobSource, BlobTransfer};
+
+    // Same server, under two host names.
+    let port = mockito::server_address().port();
+    let registry = format!("127.0.0.1:{}", port);
+    let mirror = format!("localhost:{}", port);
+
+    let name = "my-repo/my-image";
+    let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    // A pull-through mirror has the blob, the registry does not.
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let _m_mirror = mock("HEAD", ep.as_str())
+        .match_header("Host", mirror.as_str())
+        .with_status(200)
+        .create();
+    let _m_registry = mock("HEAD", ep.as_str())
+        .match_header("Host", registry.as_str())
+        .with_status(404)
+        .create();
+    let _m_start = mock("POST", format!("/v2/{}/blobs/uploads/", name).as_str())
+        .match_header("Host", registry.as_str())
+        .with_status(202)
+        .with_header("Location", "/upload/1")
+        .create();
+    let m_put = mock("PUT", "/upload/1")
+        .match_header("Host", registry.as_str())
+        .match_query(Matcher::UrlEncoded(
+            "digest".to_string(),
+            digest.to_string(),
+        ))
+        .match_body("hello")
+        .with_status(201)
+        .with_header("Location", &ep)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&registry)
+        .mirrors(vec![mirror])
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let descriptor = dkregistry::v2::manifest::Descriptor {
+        media_type: "application/octet-stream".to_string(),
+        size: 5,
+        digest: digest.to_string(),
+        annotations: None,
+    };
+    let transfer = runtime.block_on(dclient.ensure_blob(
+        name,
+        &descriptor,
+        &[],
+        BlobSource::Bytes(b"hello"[..].into()),
+    ))?;
+    assert_eq!(transfer, BlobTransfer::Uploaded);
+    m_put.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_upload_reports_progress() -> Fallible<()> {
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let _m_start = mock("POST", ep.as_str())
+        .with_status(202)
+        .with_header("Location", "/upload/1")
+        .create();
+    let _m_chunk1 = mock("PATCH", "/upload/1")
+        .match_header("Content-Range", "0-2")
+        .match_body("hel")
+        .with_status(202)
+        .with_header("Location", "/upload/2")
+        .with_header("Range", "0-2")
+        .create();
+    let _m_chunk2 = mock("PATCH", "/upload/2")
+        .match_header("Content-Range", "3-4")
+        .match_body("lo")
+        .with_status(202)
+        .with_header("Location", "/upload/3")
+        .with_header("Range", "0-4")
+        .create();
+    let _m_finish = mock("PUT", "/upload/3")
+        .match_query(Matcher::UrlEncoded(
+            "digest".to_string(),
+            digest.to_string(),
+        ))
+        .with_status(201)
+        .with_header("Location", &format!("/v2/{}/blobs/{}", name, digest))
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let mut reports = Vec::new();
+    let futcheck =
+        dclient.upload_blob_with_progress(name, digest, &b"hello"[..], 3, |done, total| {
+            reports.push((done, total))
+        });
+    runtime.block_on(futcheck)?;
+
+    assert_eq!(reports, vec![(3, 5), (5, 5)]);
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_blobs_plan_push() -> Fallible<()> {
+    use dkregistry::v2::manifest::{
+        Config, LayerDescriptor, Manifest, ManifestSchema2, ManifestSchema2Spec,
+    };
+
+    let addr = mockito::server_address().to_string();
+
+    let name = "my-repo/my-image";
+    let digest = |c: char| format!("sha256:{}", c.to_string().repeat(64));
+    let layer = |c: char, media_type: &str| LayerDescriptor {
+        media_type: media_type.to_string(),
+        size: 10,
+        digest: digest(c),
+        urls: None,
+        annotations: None,
+    };
+    let config = Config {
+        media_type: "application/vnd.oci.image.config.v1+json".to_string(),
+        size: 2,
+        digest: digest('0'),
+        annotations: None,
+    };
+    let gzip = "application/vnd.oci.image.layer.v1.tar+gzip";
+    let foreign = "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip";
+    let manifest = Manifest::S2(ManifestSchema2 {
+        manifest_spec: ManifestSchema2Spec::new(
+            config,
+            vec![layer('1', gzip), layer('2', gzip), layer('3', foreign)],
+        ),
+        config_blob: Default::default(),
+    });
+
+    let head = |c: char, status: usize| {
+        mock("HEAD", format!("/v2/{}/blobs/{}", name, digest(c)).as_str())
+            .with_status(status)
+            .expect(1)
+            .create()
+    };
+    let m_config = head('0', 200);
+    let m_layer1 = head('1', 404);
+    let m_layer2 = head('2', 200);
+    // Foreign layers are not checked, unmocked requests would fail the plan.
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let plan = runtime.block_on(dclient.plan_push(name, &manifest))?;
+    assert_eq!(plan.existing, vec![digest('0'), digest('2')]);
+    assert_eq!(plan.missing.len(), 1);
+    assert_eq!(plan.missing[0].digest, digest('1'));
+    assert_eq!(plan.missing_size(), 10);
+
+    m_config.assert();
+    m_layer1.assert();
+    m_layer2.assert();
+
+    mockito::reset();
+    Ok(())
+}
@@ -53,7 +53,7 @@ fn test_catalog_paginate() {
         .with_header("Content-Type", "application/json")
         .with_body(repos_p1)
         .create();
-    let _m2 = mock("GET", "/v2/_catalog?n=1&last=r1")
+    let _m2 = mock("GET", "/v2/_catalog?n=21&last=r1/i1")
         .with_status(200)
         .with_header("Content-Type", "application/json")
         .with_body(repos_p2)
@@ -74,10 +74,7 @@ fn test_catalog_paginate() {
     assert_eq!(page1.unwrap().unwrap(), "r1/i1".to_owned());
 
     let (page2, next) = runtime.block_on(next.into_future());
-    // TODO(lucab): implement pagination
-    if page2.is_some() {
-        panic!("end is some: {:?}", page2);
-    }
+    assert_eq!(page2.unwrap().unwrap(), "r2".to_owned());
 
     let (end, _) = runtime.block_on(next.into_future());
     if end.is_some() {
@@ -86,3 +83,117 @@ fn test_catalog_paginate() {
 
     mockito::reset();
 }
+
+#[test]
+fn test_catalog_stream_three_pages() {
+    let addr = mockito::server_address().to_string();
+    // The registry caps the requested page size to 2.
+    let _m1 = mock("GET", "/v2/_catalog?n=100")
+        .with_status(200)
+        .with_header("Link", r#"</v2/_catalog?n=2&last=r2>; rel="next""#)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"repositories": ["r1", "r2"]}"#)
+        .create();
+    let _m2 = mock("GET", "/v2/_catalog?n=2&last=r2")
+        .with_status(200)
+        .with_header("Link", r#"</v2/_catalog?n=2&last=r4>; rel="next""#)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"repositories": ["r3", "r4"]}"#)
+        .create();
+    let _m3 = mock("GET", "/v2/_catalog?n=2&last=r4")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"repositories": ["r5"]}"#)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.catalog_stream(100);
+
+    let res = runtime.block_on(futcheck.map(Result::unwrap).collect::<Vec<_>>());
+    assert_eq!(res, vec!["r1", "r2", "r3", "r4", "r5"]);
+
+    mockito::reset();
+}
+
+#[test]
+fn test_catalog_configured_page_size() {
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/_catalog?n=10000")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"repositories": ["r1", "r2"]}"#)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .page_size(50_000)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_catalog(None);
+
+    let res = runtime.block_on(futcheck.map(Result::unwrap).collect::<Vec<_>>());
+    assert_eq!(res, vec!["r1", "r2"]);
+
+    mockito::reset();
+}
+
+#[test]
+fn test_catalog_enumerate() {
+    let repos = r#"{"repositories": ["r1/i1", "r2"]}"#;
+    let tags_r1 = r#"{"name": "r1/i1", "tags": ["t1", "t2"]}"#;
+    let tags_r2 = r#"{"name": "r2", "tags": ["t3"]}"#;
+
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/_catalog")
+        .with_status(200)
+        .with_body(repos)
+        .create();
+    let _m_r1 = mock("GET", "/v2/r1/i1/tags/list")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_r1)
+        .create();
+    let _m_r2 = mock("GET", "/v2/r2/tags/list")
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_r2)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.enumerate(2);
+
+    let res = runtime.block_on(futcheck.map(Result::unwrap).collect::<Vec<_>>());
+    assert_eq!(
+        res,
+        vec![
+            (
+                "r1/i1".to_string(),
+                vec!["t1".to_string(), "t2".to_string()]
+            ),
+            ("r2".to_string(), vec!["t3".to_string()]),
+        ]
+    );
+
+    mockito::reset();
+}
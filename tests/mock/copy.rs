@@ -0,0 +1,416 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate sha2;
+extern crate tokio;
+
+use self::dkregistry::reference::Reference;
+use self::dkregistry::v2::BlobTransfer;
+use self::mockito::{mock, Matcher};
+use self::tokio::runtime::Runtime;
+use crate::mock::copy::sha2::Digest;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+fn digest(content: &[u8]) -> String {
+    format!("sha256:{:x}", sha2::Sha256::digest(content))
+}
+
+#[test]
+fn test_copy_image_between_registries() -> Fallible<()> {
+    let config = br#"{"architecture": "amd64", "os": "linux"}"#;
+    let layer = b"layer tarball";
+    let manifest = format!(
+        r#"{{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+            "config": {{
+                "mediaType": "application/vnd.docker.container.image.v1+json",
+                "size": {},
+                "digest": "{}"
+            }},
+            "layers": [{{
+                "mediaType": "application/vnd.docker.image.rootfs.diff.tar.gzip",
+                "size": {},
+                "digest": "{}"
+            }}]
+        }}"#,
+        config.len(),
+        digest(config),
+        layer.len(),
+        digest(layer)
+    );
+    let manifest_digest = digest(manifest.as_bytes());
+
+    let _m_manifest = mock("GET", "/v2/src/image/manifests/latest")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    let _m_config = mock(
+        "HEAD",
+        format!("/v2/dst/image/blobs/{}", digest(config)).as_str(),
+    )
+    .with_status(200)
+    .create();
+    let _m_missing_layer = mock(
+        "HEAD",
+        format!("/v2/dst/image/blobs/{}", digest(layer)).as_str(),
+    )
+    .with_status(404)
+    .create();
+    let _m_layer = mock(
+        "GET",
+        format!("/v2/src/image/blobs/{}", digest(layer)).as_str(),
+    )
+    .with_status(200)
+    .with_body(&layer[..])
+    .create();
+    let _m_start = mock("POST", "/v2/dst/image/blobs/uploads/")
+        .with_status(202)
+        .with_header("Location", "/upload/1")
+        .create();
+    let m_upload = mock("PUT", "/upload/1")
+        .match_query(Matcher::UrlEncoded("digest".to_string(), digest(layer)))
+        .match_body(std::str::from_utf8(layer)?)
+        .with_status(201)
+        .with_header(
+            "Location",
+            &format!("/v2/dst/image/blobs/{}", digest(layer)),
+        )
+        .expect(1)
+        .create();
+    let m_put = mock("PUT", "/v2/dst/image/manifests/v1")
+        .match_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .match_body(manifest.as_str())
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &manifest_digest)
+        .expect(1)
+        .create();
+
+    // Different hosts for the same server, so that blobs are not mounted.
+    let port = mockito::server_address().port();
+    let mut runtime = Runtime::new().unwrap();
+    let src = dkregistry::v2::Client::configure()
+        .registry(&format!("127.0.0.1:{}", port))
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+    let dst = dkregistry::v2::Client::configure()
+        .registry(&format!("localhost:{}", port))
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let src_ref = Reference::parse_with("src/image:latest", false)?;
+    let dst_ref = Reference::parse_with("dst/image:v1", false)?;
+    let mut transfers = Vec::new();
+    let pushed = runtime.block_on(dkregistry::v2::copy_image_with_progress(
+        &src,
+        &dst,
+        &src_ref,
+        &dst_ref,
+        None,
+        |progress| transfers.push((progress.transfer.clone(), progress.done, progress.total)),
+    ))?;
+
+    assert_eq!(pushed, manifest_digest);
+    assert_eq!(
+        transfers,
+        vec![
+            (BlobTransfer::Existing, 1, 2),
+            (BlobTransfer::Uploaded, 2, 2)
+        ]
+    );
+    m_upload.assert();
+    m_put.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+/// Media types of an image manifest, its config and its layers.
+type ImageMediaTypes = [&'static str; 3];
+
+const OCI_IMAGE: ImageMediaTypes = [
+    "application/vnd.oci.image.manifest.v1+json",
+    "application/vnd.oci.image.config.v1+json",
+    "application/vnd.oci.image.layer.v1.tar+gzip",
+];
+
+const DOCKER_IMAGE: ImageMediaTypes = [
+    "application/vnd.docker.distribution.manifest.v2+json",
+    "application/vnd.docker.container.image.v1+json",
+    "application/vnd.docker.image.rootfs.diff.tar.gzip",
+];
+
+/// Build an image manifest for a platform, with a single layer.
+fn image_manifest(media_types: ImageMediaTypes, architecture: &str, layer: &[u8]) -> String {
+    let config = format!(r#"{{"architecture": "{}", "os": "linux"}}"#, architecture);
+    format!(
+        r#"{{
+            "schemaVersion": 2,
+            "mediaType": "{}",
+            "config": {{
+                "mediaType": "{}",
+                "size": {},
+                "digest": "{}"
+            }},
+            "layers": [{{
+                "mediaType": "{}",
+                "size": {},
+                "digest": "{}"
+            }}]
+        }}"#,
+        media_types[0],
+        media_types[1],
+        config.len(),
+        digest(config.as_bytes()),
+        media_types[2],
+        layer.len(),
+        digest(layer)
+    )
+}
+
+#[test]
+fn test_copy_image_index() -> Fallible<()> {
+    let layer = b"shared layer";
+    let amd64 = image_manifest(OCI_IMAGE, "amd64", layer);
+    let arm64 = image_manifest(OCI_IMAGE, "arm64", layer);
+    let index = format!(
+        r#"{{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [{{
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "size": {},
+                "digest": "{}",
+                "platform": {{"architecture": "amd64", "os": "linux"}}
+            }}, {{
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "size": {},
+                "digest": "{}",
+                "platform": {{"architecture": "arm64", "os": "linux"}}
+            }}]
+        }}"#,
+        amd64.len(),
+        digest(amd64.as_bytes()),
+        arm64.len(),
+        digest(arm64.as_bytes())
+    );
+
+    let _m_index = mock("GET", "/v2/src/image/manifests/latest")
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .with_body(&index)
+        .create();
+    let mut m_children = Vec::new();
+    for manifest in &[&amd64, &arm64] {
+        let manifest_digest = digest(manifest.as_bytes());
+        m_children.push(
+            mock(
+                "GET",
+                format!("/v2/src/image/manifests/{}", manifest_digest).as_str(),
+            )
+            .with_status(200)
+            .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+            .with_body(manifest.as_str())
+            .create(),
+        );
+        m_children.push(
+            mock(
+                "PUT",
+                format!("/v2/dst/image/manifests/{}", manifest_digest).as_str(),
+            )
+            .match_body(manifest.as_str())
+            .with_status(201)
+            .with_header("Docker-Content-Digest", &manifest_digest)
+            .expect(1)
+            .create(),
+        );
+    }
+    // The destination already has all blobs, e.g. from an interrupted copy.
+    let _m_blobs = mock(
+        "HEAD",
+        Matcher::Regex(r"^/v2/dst/image/blobs/sha256:[0-9a-f]{64}$".to_string()),
+    )
+    .with_status(200)
+    .expect(3)
+    .create();
+    let m_put_index = mock("PUT", "/v2/dst/image/manifests/v1")
+        .match_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .match_body(index.as_str())
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest(index.as_bytes()))
+        .expect(1)
+        .create();
+
+    let port = mockito::server_address().port();
+    let mut runtime = Runtime::new().unwrap();
+    let src = dkregistry::v2::Client::configure()
+        .registry(&format!("127.0.0.1:{}", port))
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+    let dst = dkregistry::v2::Client::configure()
+        .registry(&format!("localhost:{}", port))
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let src_ref = Reference::parse_with("src/image:latest", false)?;
+    let dst_ref = Reference::parse_with("dst/image:v1", false)?;
+    let mut transfers = Vec::new();
+    let pushed = runtime.block_on(dkregistry::v2::copy_image_with_progress(
+        &src,
+        &dst,
+        &src_ref,
+        &dst_ref,
+        None,
+        |progress| transfers.push(progress.transfer.clone()),
+    ))?;
+
+    assert_eq!(pushed, digest(index.as_bytes()));
+    // Two configs and the shared layer.
+    assert_eq!(transfers, vec![BlobTransfer::Existing; 3]);
+    for m in &m_children {
+        m.assert();
+    }
+    m_put_index.assert();
+
+    mockito::reset();
+    Ok(())
+}
+
+#[test]
+fn test_copy_docker_manifest_list() -> Fallible<()> {
+    let layer = b"shared layer";
+    let amd64 = image_manifest(DOCKER_IMAGE, "amd64", layer);
+    let arm64 = image_manifest(DOCKER_IMAGE, "arm64", layer);
+    let list = format!(
+        r#"{{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.docker.distribution.manifest.list.v2+json",
+            "manifests": [{{
+                "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+                "size": {},
+                "digest": "{}",
+                "platform": {{"architecture": "amd64", "os": "linux"}}
+            }}, {{
+                "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+                "size": {},
+                "digest": "{}",
+                "platform": {{"architecture": "arm64", "os": "linux"}}
+            }}]
+        }}"#,
+        amd64.len(),
+        digest(amd64.as_bytes()),
+        arm64.len(),
+        digest(arm64.as_bytes())
+    );
+
+    // Like Docker Hub, only serve the list to clients accepting it.
+    let _m_list = mock("GET", "/v2/src/image/manifests/latest")
+        .match_header(
+            "Accept",
+            Matcher::Regex(r"manifest\.list\.v2\+json".to_string()),
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(&list)
+        .create();
+    let mut m_children = Vec::new();
+    for manifest in &[&amd64, &arm64] {
+        let manifest_digest = digest(manifest.as_bytes());
+        m_children.push(
+            mock(
+                "GET",
+                format!("/v2/src/image/manifests/{}", manifest_digest).as_str(),
+            )
+            .with_status(200)
+            .with_header(
+                "Content-Type",
+                "application/vnd.docker.distribution.manifest.v2+json",
+            )
+            .with_body(manifest.as_str())
+            .create(),
+        );
+        m_children.push(
+            mock(
+                "PUT",
+                format!("/v2/dst/image/manifests/{}", manifest_digest).as_str(),
+            )
+            .match_body(manifest.as_str())
+            .with_status(201)
+            .with_header("Docker-Content-Digest", &manifest_digest)
+            .expect(1)
+            .create(),
+        );
+    }
+    let _m_blobs = mock(
+        "HEAD",
+        Matcher::Regex(r"^/v2/dst/image/blobs/sha256:[0-9a-f]{64}$".to_string()),
+    )
+    .with_status(200)
+    .expect(3)
+    .create();
+    let m_put_list = mock("PUT", "/v2/dst/image/manifests/v1")
+        .match_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .match_body(list.as_str())
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest(list.as_bytes()))
+        .expect(1)
+        .create();
+
+    let port = mockito::server_address().port();
+    let mut runtime = Runtime::new().unwrap();
+    let src = dkregistry::v2::Client::configure()
+        .registry(&format!("127.0.0.1:{}", port))
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+    let dst = dkregistry::v2::Client::configure()
+        .registry(&format!("localhost:{}", port))
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let src_ref = Reference::parse_with("src/image:latest", false)?;
+    let dst_ref = Reference::parse_with("dst/image:v1", false)?;
+    let pushed = runtime.block_on(dkregistry::v2::copy_image(
+        &src, &dst, &src_ref, &dst_ref, None,
+    ))?;
+
+    // All platforms are copied, and the tag keeps the digest of the list.
+    assert_eq!(pushed, digest(list.as_bytes()));
+    for m in &m_children {
+        m.assert();
+    }
+    m_put_list.assert();
+
+    mockito::reset();
+    Ok(())
+}
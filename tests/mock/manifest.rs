@@ -0,0 +1,1281 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate serde_json;
+extern crate sha2;
+extern crate tokio;
+
+use self::dkregistry::mediatypes::MediaTypes;
+use self::mockito::mock;
+use self::tokio::runtime::Runtime;
+use crate::mock::manifest::sha2::Digest;
+
+#[test]
+fn test_manifest_has_manifest() {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("HEAD", ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_header("Docker-Content-Digest", digest)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.has_manifest(name, reference, None);
+
+    let res = runtime.block_on(futcheck).unwrap();
+    assert_eq!(
+        res,
+        Some((MediaTypes::ManifestV2S2, Some(digest.to_string())))
+    );
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_has_manifest_without_digest_header() {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let body = r#"{"schemaVersion": 2, "mediaType": "application/vnd.docker.distribution.manifest.v2+json"}"#;
+
+    // Some registries, e.g. older Artifactory, omit the digest on HEAD.
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let _m_head = mock("HEAD", ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .create();
+    let m_get = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(body)
+        .expect(0)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.has_manifest(name, reference, None);
+
+    // The manifest body is not fetched to compute the digest.
+    let res = runtime.block_on(futcheck).unwrap();
+    assert_eq!(res, Some((MediaTypes::ManifestV2S2, None)));
+    m_get.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_has_no_manifest() {
+    let name = "my-repo/my-image";
+    let reference = "missing";
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("HEAD", ep.as_str()).with_status(404).create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.has_manifest(name, reference, None);
+
+    let res = runtime.block_on(futcheck).unwrap();
+    assert_eq!(res, None);
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_get_manifest_response() {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let body = r#"{
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.docker.distribution.manifest.list.v2+json",
+        "manifests": []
+    }"#;
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(body.as_bytes()));
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_header("Docker-Content-Digest", &digest)
+        .with_body(body)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_manifest_response(name, reference);
+
+    let res = runtime.block_on(futcheck).unwrap();
+    assert_eq!(res.media_type, MediaTypes::ManifestList);
+    assert_eq!(res.digest, Some(digest));
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_put_manifest_content_type() {
+    use self::dkregistry::v2::manifest::{Manifest, ManifestList, ManifestSchema2};
+
+    let name = "my-repo/my-image";
+    let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+    let addr = mockito::server_address().to_string();
+    let m_image = mock("PUT", format!("/v2/{}/manifests/image", name).as_str())
+        .match_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_status(201)
+        .with_header("Docker-Content-Digest", digest)
+        .expect(1)
+        .create();
+    let m_list = mock("PUT", format!("/v2/{}/manifests/list", name).as_str())
+        .match_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_status(201)
+        .with_header("Docker-Content-Digest", digest)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let image = Manifest::S2(ManifestSchema2 {
+        manifest_spec: Default::default(),
+        config_blob: Default::default(),
+    });
+    let list = Manifest::ML(ManifestList::default());
+
+    let res = runtime.block_on(dclient.put_manifest(name, "image", &image));
+    assert_eq!(res.unwrap(), digest);
+    let res = runtime.block_on(dclient.put_manifest(name, "list", &list));
+    assert_eq!(res.unwrap(), digest);
+
+    m_image.assert();
+    m_list.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_delete_manifest() {
+    use self::dkregistry::errors::{Error, ErrorKind};
+
+    let name = "my-repo/my-image";
+    let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+    let missing = "sha256:1111111111111111111111111111111111111111111111111111111111111111";
+
+    let addr = mockito::server_address().to_string();
+    let m_delete = mock(
+        "DELETE",
+        format!("/v2/{}/manifests/{}", name, digest).as_str(),
+    )
+    .with_status(202)
+    .expect(1)
+    .create();
+    let _m_missing = mock(
+        "DELETE",
+        format!("/v2/{}/manifests/{}", name, missing).as_str(),
+    )
+    .with_status(404)
+    .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    runtime
+        .block_on(dclient.delete_manifest(name, digest))
+        .unwrap();
+    m_delete.assert();
+
+    match runtime.block_on(dclient.delete_manifest(name, missing)) {
+        Err(Error(ErrorKind::NotFound(_), _)) => {}
+        res => panic!("expected NotFound, got {:?}", res),
+    }
+
+    // Tags are rejected without a request.
+    assert!(runtime
+        .block_on(dclient.delete_manifest(name, "latest"))
+        .is_err());
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_resolve_manifest() {
+    use self::dkregistry::v2::manifest::{Manifest, Platform};
+
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let index = std::fs::read_to_string("tests/fixtures/oci_image_index_v1.json").unwrap();
+    let child = std::fs::read_to_string("tests/fixtures/manifest_v2_s1.json").unwrap();
+    let digest = "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270";
+
+    let addr = mockito::server_address().to_string();
+    let _m_index = mock(
+        "GET",
+        format!("/v2/{}/manifests/{}", name, reference).as_str(),
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+    .with_body(index)
+    .create();
+    let m_child = mock("GET", format!("/v2/{}/manifests/{}", name, digest).as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v1+prettyjws",
+        )
+        .with_body(child)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let amd64 = Platform {
+        os: "linux".to_string(),
+        architecture: "amd64".to_string(),
+        ..Default::default()
+    };
+    let manifest = runtime
+        .block_on(dclient.resolve_manifest(name, reference, &amd64))
+        .unwrap();
+    match manifest {
+        Manifest::S1Signed(_) => {}
+        m => panic!("expected the child manifest, got {:?}", m),
+    }
+    m_child.assert();
+
+    let s390x = Platform {
+        os: "linux".to_string(),
+        architecture: "s390x".to_string(),
+        ..Default::default()
+    };
+    let err = runtime
+        .block_on(dclient.resolve_manifest(name, reference, &s390x))
+        .unwrap_err();
+    assert!(err.to_string().contains("linux/arm64/v8"));
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_resolve_docker_manifest_list() {
+    use self::dkregistry::v2::manifest::{Manifest, Platform};
+    use self::mockito::Matcher;
+
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let list = std::fs::read_to_string("tests/fixtures/manifest_list_v2.json").unwrap();
+    let child = std::fs::read_to_string("tests/fixtures/manifest_v2_s1.json").unwrap();
+    let digest = "sha256:e692418e4cbaf90ca69d05a66403747baa33ee08806650b51fab815ad7fc331f";
+
+    // Like Docker Hub, only serve the list to clients accepting it.
+    let addr = mockito::server_address().to_string();
+    let m_list = mock(
+        "GET",
+        format!("/v2/{}/manifests/{}", name, reference).as_str(),
+    )
+    .match_header("Accept", Matcher::Regex("manifest.list.v2".to_string()))
+    .with_status(200)
+    .with_header(
+        "Content-Type",
+        "application/vnd.docker.distribution.manifest.list.v2+json",
+    )
+    .with_body(list)
+    .expect(1)
+    .create();
+    let m_child = mock("GET", format!("/v2/{}/manifests/{}", name, digest).as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v1+prettyjws",
+        )
+        .with_body(child)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let ppc64le = Platform {
+        os: "linux".to_string(),
+        architecture: "ppc64le".to_string(),
+        ..Default::default()
+    };
+    let manifest = runtime
+        .block_on(dclient.resolve_manifest(name, reference, &ppc64le))
+        .unwrap();
+    match manifest {
+        Manifest::S1Signed(_) => {}
+        m => panic!("expected the child manifest, got {:?}", m),
+    }
+    m_list.assert();
+    m_child.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_get_manifest_skips_lists_by_default() {
+    use self::dkregistry::v2::manifest::Manifest;
+    use self::mockito::Matcher;
+
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let list = std::fs::read_to_string("tests/fixtures/manifest_list_v2.json").unwrap();
+    let image = std::fs::read_to_string("tests/fixtures/manifest_v2_s1.json").unwrap();
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+
+    // Like Docker Hub, serve an image manifest to clients not accepting lists.
+    let addr = mockito::server_address().to_string();
+    let _m_list = mock("GET", ep.as_str())
+        .match_header("Accept", Matcher::Regex("manifest.list.v2".to_string()))
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(list)
+        .create();
+    let m_image = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v1+prettyjws",
+        )
+        .with_body(image)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let manifest = runtime
+        .block_on(dclient.get_manifest(name, reference))
+        .unwrap();
+    match manifest {
+        Manifest::S1Signed(_) => {}
+        m => panic!("expected the image manifest, got {:?}", m),
+    }
+    m_image.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_extra_header() {
+    use self::dkregistry::v2::manifest::Manifest;
+
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let body = r#"{
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.docker.distribution.manifest.list.v2+json",
+        "manifests": []
+    }"#;
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let m = mock("GET", ep.as_str())
+        .match_header("x-tenant-id", "tenant-1")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_body(body)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .extra_header("x-tenant-id".parse().unwrap(), "tenant-1".parse().unwrap())
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.get_manifest(name, reference);
+
+    let res = runtime.block_on(futcheck).unwrap();
+    assert!(matches!(res, Manifest::ML(_)));
+
+    m.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_unknown_registry_error() {
+    use self::dkregistry::errors::ErrorKind;
+    use self::dkregistry::v2::ErrorCode;
+
+    let name = "my-repo/my-image";
+    let reference = "missing";
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .with_status(404)
+        .with_header("Content-Type", "application/json")
+        .with_body(
+            r#"{"errors": [{"code": "MANIFEST_UNKNOWN", "message": "manifest unknown", "detail": {"Tag": "missing"}}]}"#,
+        )
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let err = runtime
+        .block_on(dclient.get_manifest(name, reference))
+        .unwrap_err();
+    match err.kind() {
+        ErrorKind::NotFound(resource) => assert_eq!(resource, "my-repo/my-image:missing"),
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    // The registry errors are kept as the cause.
+    let cause = std::error::Error::source(&err)
+        .and_then(|e| e.downcast_ref::<dkregistry::errors::Error>())
+        .expect("missing registry error cause");
+    match cause.kind() {
+        ErrorKind::Registry(status, errors, _) => {
+            assert_eq!(*status, 404);
+            assert_eq!(errors[0].code, ErrorCode::ManifestUnknown);
+            assert_eq!(errors[0].message, "manifest unknown");
+        }
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_denied_registry_error() {
+    use self::dkregistry::errors::ErrorKind;
+    use self::dkregistry::v2::ErrorCode;
+
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .with_status(403)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"errors": [{"code": "DENIED", "message": "requested access to the resource is denied"}]}"#)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let err = runtime
+        .block_on(dclient.get_manifest(name, reference))
+        .unwrap_err();
+    match err.kind() {
+        ErrorKind::Registry(status, errors, _) => {
+            assert_eq!(*status, 403);
+            assert_eq!(errors[0].code, ErrorCode::Denied);
+        }
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_get_config() {
+    use self::dkregistry::v2::manifest::{Manifest, ManifestSchema2, ManifestSchema2Spec};
+
+    let name = "my-repo/my-image";
+    let config = r#"{
+        "architecture": "amd64",
+        "os": "linux",
+        "config": {"Env": ["PATH=/bin"], "Labels": {"org.opencontainers.image.version": "1.0"}},
+        "rootfs": {"type": "layers", "diff_ids": ["sha256:1111"]},
+        "history": [{"created_by": "/bin/sh -c #(nop) ADD file"}]
+    }"#;
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(config.as_bytes()));
+    let manifest_spec = format!(
+        r#"{{
+            "schemaVersion": 2,
+            "config": {{
+                "mediaType": "application/vnd.docker.container.image.v1+json",
+                "size": {},
+                "digest": "{}"
+            }},
+            "layers": []
+        }}"#,
+        config.len(),
+        digest
+    );
+    let manifest = Manifest::S2(ManifestSchema2 {
+        manifest_spec: serde_json::from_str::<ManifestSchema2Spec>(&manifest_spec).unwrap(),
+        config_blob: Default::default(),
+    });
+
+    let ep = format!("/v2/{}/blobs/{}", name, digest);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(config)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime
+        .block_on(dclient.get_config(name, &manifest))
+        .unwrap();
+    assert_eq!(res.config.env, Some(vec!["PATH=/bin".to_string()]));
+    assert_eq!(res.rootfs.diff_ids, vec!["sha256:1111".to_string()]);
+    assert_eq!(
+        res.history[0].created_by,
+        Some("/bin/sh -c #(nop) ADD file".to_string())
+    );
+    assert_eq!(
+        res.labels().get("org.opencontainers.image.version"),
+        Some(&"1.0".to_string())
+    );
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_get_labels() {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let config = r#"{
+        "architecture": "amd64",
+        "os": "linux",
+        "config": {"Labels": {"org.opencontainers.image.version": "1.0"}}
+    }"#;
+    let config_digest = format!("sha256:{:x}", sha2::Sha256::digest(config.as_bytes()));
+    let manifest = format!(
+        r#"{{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+            "config": {{
+                "mediaType": "application/vnd.docker.container.image.v1+json",
+                "size": {},
+                "digest": "{}"
+            }},
+            "layers": []
+        }}"#,
+        config.len(),
+        config_digest
+    );
+
+    let addr = mockito::server_address().to_string();
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let _m_manifest = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(&manifest)
+        .create();
+    let ep = format!("/v2/{}/blobs/{}", name, config_digest);
+    let _m_config = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_body(config)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let labels = runtime
+        .block_on(dclient.get_labels(name, reference))
+        .unwrap();
+    assert_eq!(
+        labels.get("org.opencontainers.image.version"),
+        Some(&"1.0".to_string())
+    );
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_by_digest_mismatch() {
+    use self::dkregistry::errors::{Error, ErrorKind};
+
+    let name = "my-repo/my-image";
+    let body = r#"{
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.docker.distribution.manifest.list.v2+json",
+        "manifests": []
+    }"#;
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(body.as_bytes()));
+    let tampered = body.replace("\"manifests\": []", "\"manifests\": [] ");
+
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", format!("/v2/{}/manifests/{}", name, digest).as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.list.v2+json",
+        )
+        .with_header("Docker-Content-Digest", &digest)
+        .with_body(&tampered)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    match runtime.block_on(dclient.get_manifest(name, &digest)) {
+        Err(Error(ErrorKind::DigestMismatch(expected, _), _)) => assert_eq!(expected, digest),
+        res => panic!("expected DigestMismatch, got {:?}", res),
+    }
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_put_artifact_round_trip() {
+    use self::dkregistry::v2::manifest::{
+        Config, Descriptor, LayerDescriptor, Manifest, ManifestSchema2, ManifestSchema2Spec,
+    };
+
+    let name = "my-repo/my-image";
+    let artifact_type = "application/spdx+json";
+    let subject = Descriptor {
+        media_type: "application/vnd.oci.image.manifest.v1+json".to_string(),
+        size: 1234,
+        digest: "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270"
+            .to_string(),
+        annotations: None,
+    };
+    let config = Config {
+        media_type: "application/vnd.oci.empty.v1+json".to_string(),
+        size: 2,
+        digest: "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+            .to_string(),
+        annotations: None,
+    };
+    let sbom = LayerDescriptor {
+        media_type: artifact_type.to_string(),
+        size: 42,
+        digest: "sha256:1111111111111111111111111111111111111111111111111111111111111111"
+            .to_string(),
+        urls: None,
+        annotations: None,
+    };
+    let artifact = Manifest::S2(ManifestSchema2 {
+        manifest_spec: ManifestSchema2Spec::new(config, vec![sbom])
+            .artifact_type(artifact_type)
+            .subject(subject.clone()),
+        config_blob: Default::default(),
+    });
+    let body = artifact.to_json().unwrap();
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(&body));
+
+    let addr = mockito::server_address().to_string();
+    let ep = format!("/v2/{}/manifests/{}", name, digest);
+    let m_put = mock("PUT", ep.as_str())
+        .match_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .match_body(mockito::Matcher::PartialJsonString(format!(
+            r#"{{"artifactType": "{}", "subject": {{"digest": "{}"}}}}"#,
+            artifact_type, subject.digest
+        )))
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest)
+        .expect(1)
+        .create();
+    let _m_get = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .with_body(&body)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.put_manifest(name, &digest, &artifact));
+    assert_eq!(res.unwrap(), digest);
+    m_put.assert();
+
+    // Artifact configs are not fetched as image configs.
+    match runtime
+        .block_on(dclient.get_manifest(name, &digest))
+        .unwrap()
+    {
+        Manifest::S2(m) => {
+            assert_eq!(m.manifest_spec.get_artifact_type(), Some(artifact_type));
+            assert_eq!(
+                m.manifest_spec.get_subject().map(|s| s.digest.as_str()),
+                Some(subject.digest.as_str())
+            );
+        }
+        m => panic!("expected an image manifest, got {:?}", m),
+    }
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_raw_round_trip() {
+    use self::dkregistry::mediatypes::MediaTypes;
+
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    // Unusual formatting, changed by any re-serialization.
+    let body = "{\"schemaVersion\":2,  \"manifests\":[],\n\"mediaType\":\"application/vnd.oci.image.index.v1+json\"}";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(body.as_bytes()));
+
+    let addr = mockito::server_address().to_string();
+    let _m_get = mock(
+        "GET",
+        format!("/v2/{}/manifests/{}", name, reference).as_str(),
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+    .with_header("Docker-Content-Digest", &digest)
+    .with_body(body)
+    .create();
+    let m_put = mock("PUT", format!("/v2/{}/manifests/copy", name).as_str())
+        .match_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .match_body(body)
+        .with_status(201)
+        .with_header("Docker-Content-Digest", &digest)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let raw = runtime
+        .block_on(dclient.get_raw_manifest(name, reference))
+        .unwrap();
+    assert_eq!(raw.body, body.as_bytes());
+    assert_eq!(raw.media_type, MediaTypes::OciImageIndexV1);
+    assert_eq!(raw.digest.as_deref(), Some(digest.as_str()));
+
+    let res = runtime.block_on(dclient.put_raw_manifest(name, "copy", &raw.media_type, raw.body));
+    assert_eq!(res.unwrap(), digest);
+    m_put.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_layer_pairs() {
+    let name = "my-repo/my-image";
+    let config = r#"{
+        "architecture": "amd64",
+        "os": "linux",
+        "rootfs": {"type": "layers", "diff_ids": ["sha256:aaaa", "sha256:bbbb"]}
+    }"#;
+    let config_digest = format!("sha256:{:x}", sha2::Sha256::digest(config.as_bytes()));
+    let manifest = |layers: &[&str]| {
+        format!(
+            r#"{{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+                "config": {{
+                    "mediaType": "application/vnd.docker.container.image.v1+json",
+                    "size": {},
+                    "digest": "{}"
+                }},
+                "layers": [{}]
+            }}"#,
+            config.len(),
+            config_digest,
+            layers
+                .iter()
+                .map(|digest| format!(
+                    r#"{{"mediaType": "application/vnd.docker.image.rootfs.diff.tar.gzip", "size": 1, "digest": "{}"}}"#,
+                    digest
+                ))
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    };
+
+    let addr = mockito::server_address().to_string();
+    let content_type = "application/vnd.docker.distribution.manifest.v2+json";
+    let _m_complete = mock("GET", format!("/v2/{}/manifests/complete", name).as_str())
+        .with_status(200)
+        .with_header("Content-Type", content_type)
+        .with_body(manifest(&["sha256:1111", "sha256:2222"]))
+        .create();
+    let _m_broken = mock("GET", format!("/v2/{}/manifests/broken", name).as_str())
+        .with_status(200)
+        .with_header("Content-Type", content_type)
+        .with_body(manifest(&["sha256:1111"]))
+        .create();
+    let _m_config = mock(
+        "GET",
+        format!("/v2/{}/blobs/{}", name, config_digest).as_str(),
+    )
+    .with_status(200)
+    .with_body(config)
+    .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let pairs = runtime
+        .block_on(dclient.layer_pairs(name, "complete"))
+        .unwrap();
+    assert_eq!(
+        pairs,
+        vec![
+            ("sha256:1111".to_string(), "sha256:aaaa".to_string()),
+            ("sha256:2222".to_string(), "sha256:bbbb".to_string()),
+        ]
+    );
+
+    let err = runtime
+        .block_on(dclient.layer_pairs(name, "broken"))
+        .unwrap_err();
+    assert!(err.to_string().contains("1 layers but 2 diff_ids"));
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_too_large() {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let manifest = include_str!("../fixtures/manifest_v2_s2.json");
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(manifest)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .max_manifest_size(16)
+        .build()
+        .unwrap();
+
+    let err = runtime
+        .block_on(dclient.get_manifest(name, reference))
+        .unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::ResponseTooLarge(_, limit) => assert_eq!(*limit, 16),
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_error_keeps_response_headers() {
+    use self::dkregistry::errors::ErrorKind;
+
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .with_status(500)
+        .with_header("X-Request-Id", "req-1234")
+        .with_header("RateLimit-Remaining", "99")
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let err = runtime
+        .block_on(dclient.get_manifest(name, reference))
+        .unwrap_err();
+    match err.kind() {
+        ErrorKind::Status(_, status, headers) => {
+            assert_eq!(status.as_u16(), 500);
+            assert_eq!(headers.get("X-Request-Id"), Some("req-1234"));
+            assert_eq!(headers.get("RateLimit-Remaining"), Some("99"));
+        }
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+    assert!(err.to_string().contains("x-request-id: req-1234"));
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_not_acceptable_fallback() {
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let manifest = r#"{"schemaVersion": 2, "mediaType": "application/vnd.docker.distribution.manifest.v2+json"}"#;
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let addr = mockito::server_address().to_string();
+    let m_oci = mock("GET", ep.as_str())
+        .match_header(
+            "Accept",
+            mockito::Matcher::Regex("vnd\\.oci\\.".to_string()),
+        )
+        .with_status(406)
+        .expect(1)
+        .create();
+    let m_schema2 = mock("GET", ep.as_str())
+        .match_header(
+            "Accept",
+            "application/vnd.docker.distribution.manifest.v2+json; q=1",
+        )
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(manifest)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let raw = runtime
+        .block_on(dclient.get_raw_manifest(name, reference))
+        .unwrap();
+    assert_eq!(raw.media_type, MediaTypes::ManifestV2S2);
+    assert_eq!(raw.body, manifest.as_bytes());
+    m_oci.assert();
+    m_schema2.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_not_acceptable_fallback_renews_token() {
+    use self::mockito::Matcher;
+
+    let name = "my-repo/my-image";
+    let reference = "latest";
+    let manifest = r#"{"schemaVersion": 2, "mediaType": "application/vnd.docker.distribution.manifest.v2+json"}"#;
+
+    let addr = mockito::server_address().to_string();
+    let _m_challenge = mock("GET", "/v2/")
+        .with_status(401)
+        .with_header(
+            "WWW-Authenticate",
+            &format!(
+                r#"Bearer realm="{}/token",service="mock-registry""#,
+                mockito::server_url()
+            ),
+        )
+        .create();
+    // Tokens expire within the refresh skew, so each request renews them:
+    // once to authenticate, once before the first attempt, once before the fallback.
+    let m_token = mock("GET", Matcher::Regex("^/token\\?".to_string()))
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "old-token", "expires_in": 60}"#)
+        .expect(2)
+        .create();
+    let m_renewed = mock("GET", Matcher::Regex("^/token\\?".to_string()))
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"token": "renewed-token", "expires_in": 60}"#)
+        .expect(1)
+        .create();
+
+    let ep = format!("/v2/{}/manifests/{}", name, reference);
+    let m_schema2 = mock("GET", ep.as_str())
+        .match_header("Authorization", "Bearer renewed-token")
+        .with_status(200)
+        .with_header(
+            "Content-Type",
+            "application/vnd.docker.distribution.manifest.v2+json",
+        )
+        .with_body(manifest)
+        .expect(1)
+        .create();
+    let m_oci = mock("GET", ep.as_str())
+        .match_header("Authorization", "Bearer old-token")
+        .with_status(406)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(Some("user".to_string()))
+        .password(Some("password".to_string()))
+        .token_refresh_skew(std::time::Duration::from_secs(3600))
+        .build()
+        .unwrap();
+    let dclient = runtime
+        .block_on(dclient.authenticate(&[&format!("repository:{}:pull", name)]))
+        .unwrap();
+
+    let raw = runtime
+        .block_on(dclient.get_raw_manifest(name, reference))
+        .unwrap();
+    assert_eq!(raw.body, manifest.as_bytes());
+    m_token.assert();
+    m_renewed.assert();
+    m_oci.assert();
+    m_schema2.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_digest_of() {
+    let name = "my-repo/my-image";
+    let body = "{\"schemaVersion\":2,\"manifests\":[]}";
+    let digest = format!("sha256:{:x}", sha2::Sha256::digest(body.as_bytes()));
+
+    let addr = mockito::server_address().to_string();
+    // A multi-arch tag: its digest is the one of the list, given it is accepted.
+    let _m_head = mock("HEAD", format!("/v2/{}/manifests/tagged", name).as_str())
+        .match_header(
+            "Accept",
+            mockito::Matcher::Regex("manifest.list.v2".to_string()),
+        )
+        .with_status(200)
+        .with_header("Docker-Content-Digest", &digest)
+        .create();
+    let m_get = mock("GET", format!("/v2/{}/manifests/tagged", name).as_str())
+        .expect(0)
+        .create();
+    // A registry which does not send digests.
+    let _m_head_no_digest = mock("HEAD", format!("/v2/{}/manifests/latest", name).as_str())
+        .with_status(200)
+        .create();
+    let _m_get_no_digest = mock("GET", format!("/v2/{}/manifests/latest", name).as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .with_body(body)
+        .create();
+    let _m_head_missing = mock("HEAD", format!("/v2/{}/manifests/missing", name).as_str())
+        .with_status(404)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime.block_on(dclient.digest_of(name, "tagged"));
+    assert_eq!(res.unwrap(), digest);
+    m_get.assert();
+
+    let res = runtime.block_on(dclient.digest_of(name, "latest"));
+    assert_eq!(res.unwrap(), digest);
+
+    let err = runtime
+        .block_on(dclient.digest_of(name, "missing"))
+        .unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::NotFound(resource) => {
+            assert_eq!(resource, "my-repo/my-image:missing")
+        }
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    mockito::reset();
+}
+
+#[test]
+fn test_manifest_rate_limit() {
+    let name = "library/busybox";
+    let body = "{\"schemaVersion\":2,\"manifests\":[]}";
+
+    let addr = mockito::server_address().to_string();
+    // Headers as documented at https://docs.docker.com/docker-hub/download-rate-limit/.
+    let _m_head = mock("HEAD", format!("/v2/{}/manifests/latest", name).as_str())
+        .with_status(200)
+        .with_header("RateLimit-Limit", "100;w=21600")
+        .with_header("RateLimit-Remaining", "76;w=21600")
+        .with_header("Docker-RateLimit-Source", "192.0.2.1")
+        .create();
+    let _m_get = mock("GET", format!("/v2/{}/manifests/latest", name).as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .with_header("RateLimit-Limit", "100;w=21600")
+        .with_header("RateLimit-Remaining", "75;w=21600")
+        .with_header("Docker-RateLimit-Source", "192.0.2.1")
+        .with_body(body)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+    assert_eq!(dclient.last_rate_limit(), None);
+
+    runtime
+        .block_on(dclient.get_manifestref(name, "latest"))
+        .unwrap();
+    let rate_limit = dclient.last_rate_limit().unwrap();
+    assert_eq!(rate_limit.limit, Some(100));
+    assert_eq!(rate_limit.remaining, Some(76));
+    assert_eq!(
+        rate_limit.window,
+        Some(std::time::Duration::from_secs(21600))
+    );
+    assert_eq!(rate_limit.source.as_deref(), Some("192.0.2.1"));
+    assert_eq!(rate_limit.raw_remaining.as_deref(), Some("76;w=21600"));
+
+    runtime
+        .block_on(dclient.get_raw_manifest(name, "latest"))
+        .unwrap();
+    assert_eq!(dclient.last_rate_limit().unwrap().remaining, Some(75));
+
+    mockito::reset();
+}
@@ -1,5 +1,12 @@
 mod api_version;
+mod auth;
 mod base_client;
 mod blobs_download;
+mod blobs_upload;
 mod catalog;
+mod copy;
+mod manifest;
+mod pull;
+mod referrers;
+mod retry;
 mod tags;
@@ -0,0 +1,101 @@
+extern crate dkregistry;
+extern crate libflate;
+extern crate mockito;
+extern crate sha2;
+extern crate tokio;
+
+use self::mockito::mock;
+use self::tokio::runtime::Runtime;
+use crate::mock::pull::sha2::Digest;
+
+type Fallible<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[test]
+fn test_pull_image() -> Fallible<()> {
+    use self::dkregistry::v2::manifest::Platform;
+    use self::tokio::io::AsyncReadExt;
+    use std::io::Write;
+
+    let name = "my-repo/my-image";
+    let reference = "latest";
+
+    let content = b"layer tarball";
+    let mut encoder = libflate::gzip::Encoder::new(Vec::new())?;
+    encoder.write_all(content)?;
+    let layer = encoder.finish().into_result()?;
+    let layer_digest = format!("sha256:{:x}", sha2::Sha256::digest(&layer));
+
+    let config = r#"{"architecture": "amd64", "os": "linux", "config": {"Cmd": ["/hello"]}}"#;
+    let config_digest = format!("sha256:{:x}", sha2::Sha256::digest(config.as_bytes()));
+    let manifest = format!(
+        r#"{{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "config": {{
+                "mediaType": "application/vnd.oci.image.config.v1+json",
+                "size": {},
+                "digest": "{}"
+            }},
+            "layers": [{{
+                "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                "size": {},
+                "digest": "{}"
+            }}]
+        }}"#,
+        config.len(),
+        config_digest,
+        layer.len(),
+        layer_digest
+    );
+
+    let addr = mockito::server_address().to_string();
+    let _m_manifest = mock(
+        "GET",
+        format!("/v2/{}/manifests/{}", name, reference).as_str(),
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+    .with_body(&manifest)
+    .create();
+    let _m_config = mock(
+        "GET",
+        format!("/v2/{}/blobs/{}", name, config_digest).as_str(),
+    )
+    .with_status(200)
+    .with_body(config)
+    .create();
+    let m_layer = mock(
+        "GET",
+        format!("/v2/{}/blobs/{}", name, layer_digest).as_str(),
+    )
+    .with_status(200)
+    .with_body(&layer)
+    .expect(1)
+    .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let image =
+        runtime.block_on(dclient.pull(name, reference, &Platform::new("linux", "amd64")))?;
+    assert_eq!(image.config.config.cmd, Some(vec!["/hello".to_string()]));
+    assert_eq!(image.layers().len(), 1);
+
+    let uncompressed = runtime.block_on(async {
+        let mut reader = image.layer_reader(&image.layers()[0]).await?;
+        let mut uncompressed = Vec::new();
+        reader.read_to_end(&mut uncompressed).await?;
+        Ok::<_, Box<dyn std::error::Error>>(uncompressed)
+    })?;
+    assert_eq!(uncompressed, content);
+    m_layer.assert();
+
+    mockito::reset();
+    Ok(())
+}
@@ -0,0 +1,140 @@
+extern crate dkregistry;
+extern crate mockito;
+extern crate tokio;
+
+use self::mockito::{mock, Matcher};
+use self::tokio::runtime::Runtime;
+
+static SUBJECT: &str = "sha256:5b0bcabd1ed22e9fb1310cf6c2dec7cdef19f0ad69efa1f392e94a4333501270";
+
+fn referrers_index() -> String {
+    r#"{
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": [
+            {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "size": 1234,
+                "digest": "sha256:1111111111111111111111111111111111111111111111111111111111111111",
+                "artifactType": "application/vnd.dev.cosign.artifact.sig.v1+json"
+            },
+            {
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "size": 4321,
+                "digest": "sha256:2222222222222222222222222222222222222222222222222222222222222222",
+                "artifactType": "application/spdx+json"
+            }
+        ]
+    }"#
+    .to_string()
+}
+
+#[test]
+fn test_referrers_filtered_by_client() {
+    let name = "my-repo/my-image";
+    let artifact_type = "application/spdx+json";
+
+    let addr = mockito::server_address().to_string();
+    let _m = mock(
+        "GET",
+        format!("/v2/{}/referrers/{}", name, SUBJECT).as_str(),
+    )
+    .match_query(Matcher::UrlEncoded(
+        "artifactType".to_string(),
+        artifact_type.to_string(),
+    ))
+    .with_status(200)
+    .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+    .with_body(referrers_index())
+    .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let referrers = runtime
+        .block_on(dclient.get_referrers(name, SUBJECT, Some(artifact_type)))
+        .unwrap();
+    assert_eq!(referrers.manifests.len(), 1);
+    assert_eq!(
+        referrers.manifests[0].artifact_type.as_deref(),
+        Some(artifact_type)
+    );
+
+    mockito::reset();
+}
+
+#[test]
+fn test_referrers_tag_fallback() {
+    let name = "my-repo/my-image";
+    let tag = SUBJECT.replace(':', "-");
+
+    let addr = mockito::server_address().to_string();
+    let _m_api = mock(
+        "GET",
+        format!("/v2/{}/referrers/{}", name, SUBJECT).as_str(),
+    )
+    .with_status(404)
+    .create();
+    let m_tag = mock("GET", format!("/v2/{}/manifests/{}", name, tag).as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+        .with_body(referrers_index())
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let referrers = runtime
+        .block_on(dclient.get_referrers(name, SUBJECT, None))
+        .unwrap();
+    assert_eq!(referrers.manifests.len(), 2);
+    m_tag.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_referrers_none() {
+    let name = "my-repo/my-image";
+    let tag = SUBJECT.replace(':', "-");
+
+    let addr = mockito::server_address().to_string();
+    let _m_api = mock(
+        "GET",
+        format!("/v2/{}/referrers/{}", name, SUBJECT).as_str(),
+    )
+    .with_status(404)
+    .create();
+    let _m_tag = mock("GET", format!("/v2/{}/manifests/{}", name, tag).as_str())
+        .with_status(404)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let referrers = runtime
+        .block_on(dclient.get_referrers(name, SUBJECT, None))
+        .unwrap();
+    assert!(referrers.manifests.is_empty());
+
+    mockito::reset();
+}
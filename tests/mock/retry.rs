@@ -0,0 +1,203 @@
+extern crate dkregistry;
+extern crate futures;
+extern crate mockito;
+extern crate tokio;
+
+use self::futures::StreamExt;
+use self::mockito::mock;
+use self::tokio::runtime::Runtime;
+use std::time::Duration;
+
+static API_VERSION_K: &str = "Docker-Distribution-API-Version";
+static API_VERSION_V: &str = "registry/2.0";
+
+fn retrying_client(addr: &str) -> dkregistry::v2::Client {
+    dkregistry::v2::Client::configure()
+        .registry(addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .retry_policy(
+            dkregistry::v2::RetryPolicy::new(3).initial_backoff(Duration::from_millis(10)),
+        )
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_retry_get_after_transient_errors() {
+    let addr = mockito::server_address().to_string();
+    let _m_fail = mock("GET", "/v2/").with_status(503).expect(2).create();
+    let _m_ok = mock("GET", "/v2/")
+        .with_status(200)
+        .with_header(API_VERSION_K, API_VERSION_V)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = retrying_client(&addr);
+
+    let res = runtime.block_on(dclient.is_v2_supported()).unwrap();
+    assert!(res);
+
+    _m_fail.assert();
+    _m_ok.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_retry_gives_up_after_max_attempts() {
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/").with_status(502).expect(3).create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = retrying_client(&addr);
+
+    let res = runtime.block_on(dclient.is_v2_supported()).unwrap();
+    assert!(!res);
+
+    _m.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_retry_skips_writes() {
+    let name = "my-repo/my-image";
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("POST", ep.as_str())
+        .with_status(503)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = retrying_client(&addr);
+
+    let res = runtime.block_on(dclient.start_blob_upload(name));
+    assert!(res.is_err());
+
+    _m.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_retry_after_rate_limit() {
+    let name = "repo";
+    let ep = format!("/v2/{}/tags/list", name);
+    let addr = mockito::server_address().to_string();
+    let _m_limited = mock("GET", ep.as_str())
+        .with_status(429)
+        .with_header("Retry-After", "1")
+        .expect(1)
+        .create();
+    let _m_ok = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"name": "repo", "tags": ["t1"]}"#)
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = retrying_client(&addr);
+
+    let res = runtime.block_on(
+        dclient
+            .get_tags(name, None)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(res, vec!["t1"]);
+
+    _m_limited.assert();
+    _m_ok.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_retry_rate_limit_exhausted() {
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/")
+        .with_status(429)
+        .with_header("Retry-After", "30")
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .retry_policy(dkregistry::v2::RetryPolicy::none())
+        .build()
+        .unwrap();
+
+    let err = runtime.block_on(dclient.is_v2_supported()).unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::RateLimited(retry_after) => {
+            assert_eq!(*retry_after, Some(Duration::from_secs(30)))
+        }
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    _m.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_retry_rate_limit_too_long() {
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", "/v2/")
+        .with_status(429)
+        .with_header("Retry-After", "86400")
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = retrying_client(&addr);
+
+    // Waiting a day is left to the caller.
+    let err = runtime.block_on(dclient.is_v2_supported()).unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::RateLimited(retry_after) => {
+            assert_eq!(*retry_after, Some(Duration::from_secs(86400)))
+        }
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    _m.assert();
+
+    mockito::reset();
+}
+
+#[test]
+fn test_retry_rate_limit_skips_writes() {
+    let name = "my-repo/my-image";
+    let ep = format!("/v2/{}/blobs/uploads/", name);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("POST", ep.as_str())
+        .with_status(429)
+        .with_header("Retry-After", "1")
+        .expect(1)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = retrying_client(&addr);
+
+    let err = runtime
+        .block_on(dclient.start_blob_upload(name))
+        .unwrap_err();
+    match err.kind() {
+        dkregistry::errors::ErrorKind::RateLimited(_) => {}
+        other => panic!("unexpected error kind: {:?}", other),
+    }
+
+    _m.assert();
+
+    mockito::reset();
+}
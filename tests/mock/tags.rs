@@ -90,6 +90,47 @@ fn test_tags_paginate() {
     mockito::reset();
 }
 
+#[test]
+fn test_tags_paginate_last() {
+    let name = "repo";
+    let tags_p1 = r#"{"name": "repo", "tags": [ "t1", "t2" ]}"#;
+    let tags_p2 = r#"{"name": "repo", "tags": [ "t3" ]}"#;
+
+    let ep1 = format!("/v2/{}/tags/list?n=2", name);
+    let ep2 = format!("/v2/{}/tags/list?n=2&last=t2", name);
+    let addr = mockito::server_address().to_string();
+    let _m1 = mock("GET", ep1.as_str())
+        .with_status(200)
+        .with_header(
+            "Link",
+            &format!(r#"</v2/{}/tags/list?n=2&last=t2>; rel="next""#, name),
+        )
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p1)
+        .create();
+    let _m2 = mock("GET", ep2.as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p2)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.tags_paginated(name, 2);
+
+    let res = runtime.block_on(futcheck.map(Result::unwrap).collect::<Vec<_>>());
+    assert_eq!(vec!["t1", "t2", "t3"], res);
+
+    mockito::reset();
+}
+
 #[test]
 fn test_tags_404() {
     let name = "repo";
@@ -112,7 +153,10 @@ fn test_tags_404() {
     let futcheck = dclient.get_tags(name, None);
 
     let res = runtime.block_on(futcheck.collect::<Vec<_>>());
-    assert!(res.get(0).unwrap().is_err());
+    match res.first().unwrap().as_ref().unwrap_err().kind() {
+        dkregistry::errors::ErrorKind::NotFound(resource) => assert_eq!(resource, name),
+        other => panic!("unexpected error kind: {:?}", other),
+    }
 
     mockito::reset();
 }
@@ -145,3 +189,81 @@ fn test_tags_missing_header() {
 
     mockito::reset();
 }
+
+#[test]
+fn test_tags_with_prefix() {
+    let name = "repo";
+    let tags_p1 = r#"{"name": "repo", "tags": [ "v1.0", "v2.0" ]}"#;
+    let tags_p2 = r#"{"name": "repo", "tags": [ "latest", "v1.1" ]}"#;
+
+    let ep1 = format!("/v2/{}/tags/list?n=2", name);
+    let ep2 = format!("/v2/{}/tags/list?n=2&last=v2.0", name);
+    let addr = mockito::server_address().to_string();
+    let _m1 = mock("GET", ep1.as_str())
+        .with_status(200)
+        .with_header(
+            "Link",
+            &format!(
+                r#"<{}/v2/{}/tags/list?n=2&last=v2.0>; rel="next""#,
+                mockito::server_url(),
+                name
+            ),
+        )
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p1)
+        .create();
+    let _m2 = mock("GET", ep2.as_str())
+        .with_status(200)
+        .with_header("Content-Type", "application/json")
+        .with_body(tags_p2)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .page_size(2)
+        .build()
+        .unwrap();
+
+    let futcheck = dclient.tags_with_prefix(name, "v1.");
+
+    let res = runtime.block_on(futcheck.map(Result::unwrap).collect::<Vec<_>>());
+    assert_eq!(res, vec!["v1.0", "v1.1"]);
+
+    mockito::reset();
+}
+
+#[test]
+fn test_tags_after() {
+    let name = "repo";
+    let tags = r#"{"name": "repo", "tags": [ "t2", "t3" ]}"#;
+
+    let ep = format!("/v2/{}/tags/list?n=2&last=t1", name);
+    let addr = mockito::server_address().to_string();
+    let _m = mock("GET", ep.as_str())
+        .with_status(200)
+        .with_header(
+            "Link",
+            &format!(r#"</v2/{}/tags/list?n=2&last=t3>; rel="next""#, name),
+        )
+        .with_header("Content-Type", "application/json")
+        .with_body(tags)
+        .create();
+
+    let mut runtime = Runtime::new().unwrap();
+    let dclient = dkregistry::v2::Client::configure()
+        .registry(&addr)
+        .insecure_registry(true)
+        .username(None)
+        .password(None)
+        .build()
+        .unwrap();
+
+    let res = runtime
+        .block_on(dclient.get_tags_after(name, "t1", 2))
+        .unwrap();
+    assert_eq!(vec!["t2", "t3"], res);
+
+    mockito::reset();
+}
@@ -101,7 +101,7 @@ fn test_gcrio_has_manifest() {
     let manifest_type_str = manifest_type.as_str();
     let manifest_type_vec = vec![manifest_type_str];
     let fut = dclient.has_manifest(image, tag, Some(manifest_type_vec.as_slice()));
-    let has_manifest = runtime.block_on(fut).unwrap();
+    let has_manifest = runtime.block_on(fut).unwrap().map(|(m, _)| m);
 
     assert_eq!(
         has_manifest,
@@ -202,7 +202,7 @@ fn test_quayio_has_manifest() {
     let image = "coreos/alpine-sh";
     let reference = "latest";
     let fut = dclient.has_manifest(image, reference, None);
-    let has_manifest = runtime.block_on(fut).unwrap();
+    let has_manifest = runtime.block_on(fut).unwrap().map(|(m, _)| m);
 
     assert_eq!(has_manifest, Some(MediaTypes::ManifestV2S1Signed));
 }
@@ -217,7 +217,7 @@ fn test_quayio_auth_manifest() {
 
     let fut_has_manifest = dclient.has_manifest(image, reference, None);
 
-    let has_manifest = runtime.block_on(fut_has_manifest).unwrap();
+    let has_manifest = runtime.block_on(fut_has_manifest).unwrap().map(|(m, _)| m);
     assert_eq!(has_manifest, Some(MediaTypes::ManifestV2S1Signed));
 }
 
@@ -133,3 +133,47 @@ fn ipv4_registry_and_library_with_tag() -> Result<(), Box<dyn std::error::Error>
 
     Ok(())
 }
+
+#[test]
+fn tag_and_digest() -> Result<(), Box<dyn std::error::Error>> {
+    let input = "quay.io/foo/bar:v1@sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+    let dkr_ref = Reference::parse(input)?;
+
+    assert_eq!(dkr_ref.registry(), "quay.io");
+    assert_eq!(dkr_ref.repository(), "foo/bar");
+    assert_eq!(dkr_ref.tag(), Some("v1".to_string()));
+    assert_eq!(
+        dkr_ref.digest(),
+        Some("sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string())
+    );
+    assert_eq!(dkr_ref.to_string(), input);
+
+    Ok(())
+}
+
+#[test]
+fn localhost_registry() -> Result<(), Box<dyn std::error::Error>> {
+    let dkr_ref = Reference::parse("localhost:5000/foo/bar")?;
+
+    assert_eq!(dkr_ref.registry(), "localhost:5000");
+    assert_eq!(dkr_ref.repository(), "foo/bar");
+    assert_eq!(dkr_ref.tag(), Some("latest".to_string()));
+    assert_eq!(dkr_ref.digest(), None);
+
+    Ok(())
+}
+
+#[test]
+fn without_normalization() -> Result<(), Box<dyn std::error::Error>> {
+    let dkr_ref = Reference::parse_with("busybox:1.31", false)?;
+
+    assert_eq!(dkr_ref.registry(), "");
+    assert_eq!(dkr_ref.repository(), "busybox");
+    assert_eq!(dkr_ref.to_string(), "busybox:1.31");
+
+    let dkr_ref = Reference::parse_with("busybox:1.31", true)?;
+    assert_eq!(dkr_ref.registry(), dkregistry::reference::DEFAULT_REGISTRY);
+    assert_eq!(dkr_ref.repository(), "library/busybox");
+
+    Ok(())
+}